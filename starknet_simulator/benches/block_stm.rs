@@ -0,0 +1,85 @@
+//block-stm benchmark
+//times sequential and optimistic parallel execution of the same large synthetic blocks of transfers
+
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+use starknet_simulator::block_stm::{self, BlockStmOutput, TransferOutcome, TxView};
+use starknet_simulator::execution::BaseState;
+use starknet_simulator::gas::GasPrices;
+use starknet_simulator::transaction::{Transaction, TransactionType};
+use starknet_simulator::utils::U256;
+
+const BLOCK_SIZE: usize = 10_000;
+const RUNS: u32 = 5;
+
+fn transfer(sender: usize, receiver: usize, nonce: u64) -> Transaction {
+    Transaction::new(format!("user{}", sender), TransactionType::Invoke, Some(format!("user{}", receiver)), None, Some(U256::ONE), nonce)
+        .with_max_fee(U256::from(20u64))
+}
+
+/// A block where sender `i` pays `receiver(i)`, each sender sending `txs_per_sender` in nonce order
+fn block(txs_per_sender: usize, receiver: impl Fn(usize) -> usize) -> Vec<Transaction> {
+    let senders = BLOCK_SIZE / txs_per_sender;
+    (0..BLOCK_SIZE)
+        .map(|i| {
+            let sender = i % senders;
+            transfer(sender, receiver(sender), (i / senders) as u64)
+        })
+        .collect()
+}
+
+/// Best of `RUNS` timings
+fn time<O>(run: impl Fn() -> BlockStmOutput<O>) -> (Duration, BlockStmOutput<O>) {
+    let mut best = Duration::MAX;
+    let mut output = None;
+    for _ in 0..RUNS {
+        let started = Instant::now();
+        let result = run();
+        best = best.min(started.elapsed());
+        output = Some(result);
+    }
+    (best, output.expect("ran at least once"))
+}
+
+fn main() {
+    let workers = thread::available_parallelism().map_or(4, |n| n.get()).max(2);
+    let balances: HashMap<String, U256> = (0..2 * BLOCK_SIZE).map(|i| (format!("user{}", i), U256::from(1_000_000u64))).collect();
+    let nonces = HashMap::new();
+    let base = BaseState { balances: &balances, nonces: &nonces };
+    let gas_prices = GasPrices::default();
+    let execute = |tx: &Transaction, view: &mut TxView| block_stm::execute_transfer(tx, view, &gas_prices);
+
+    let workloads: Vec<(&str, Vec<Transaction>)> = vec![
+        ("independent senders", block(1, |sender| BLOCK_SIZE + sender)),
+        ("10 txs per sender", block(10, |sender| BLOCK_SIZE + sender)),
+        ("one hot receiver", block(1, |_| BLOCK_SIZE)),
+        ("ring of senders", block(1, |sender| (sender + 1) % BLOCK_SIZE)),
+    ];
+
+    println!("Block-STM: {} transfers per block, {} worker(s), best of {} runs\n", BLOCK_SIZE, workers, RUNS);
+    println!(" workload            | sequential | parallel | speedup | executions | waves");
+    for (name, txs) in &workloads {
+        let (sequential_time, sequential) = time(|| block_stm::execute_sequential(txs, base, execute));
+        let (parallel_time, parallel) = time(|| block_stm::execute_parallel(txs, base, workers, execute));
+
+        let same = sequential
+            .results
+            .iter()
+            .zip(&parallel.results)
+            .all(|(expected, actual)| expected.output == actual.output && expected.writes == actual.writes);
+        assert!(same, "{}: parallel execution diverged from sequential", name);
+        let executed = parallel.results.iter().filter(|r| matches!(r.output, TransferOutcome::Executed { .. })).count();
+        assert_eq!(executed, txs.len(), "{}: every transfer should execute", name);
+
+        println!(
+            " {:<19} | {:>8.1}ms | {:>6.1}ms | {:>6.2}x | {:>10} | {:>5}",
+            name,
+            sequential_time.as_secs_f64() * 1e3,
+            parallel_time.as_secs_f64() * 1e3,
+            sequential_time.as_secs_f64() / parallel_time.as_secs_f64(),
+            parallel.executions,
+            parallel.waves
+        );
+    }
+}
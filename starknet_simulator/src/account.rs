@@ -0,0 +1,305 @@
+//account abstraction
+//accounts are contracts whose `__validate__` decides which transactions they send: multisig, session keys, spending limits, time locks
+
+use std::collections::{HashMap, HashSet};
+use sha2::{Sha256, Digest};
+use crate::contract::{parse_felt, Contract, EntryPointType, ExecutionContext, TxInfo};
+use crate::gas::{Builtin, ResourceMeter};
+use crate::state::{StateOverlay, FEE_TOKEN_ADDRESS};
+use crate::transaction::{Transaction, TransactionType};
+use crate::utils::U256;
+
+/// Entry point every account contract exposes to accept or refuse its transactions
+pub const VALIDATE_ENTRY_POINT: &str = "__validate__";
+
+/// Entry point a paymaster exposes to agree to pay a transaction's fee
+pub const VALIDATE_PAYMASTER_ENTRY_POINT: &str = "__validate_paymaster__";
+
+/// Selector of a fee-token transfer in the calls passed to `__validate__`
+pub const TRANSFER_SELECTOR: &str = "transfer";
+
+/// Steps `__validate__` may use by default. A transaction whose account needs more is rejected.
+pub const DEFAULT_VALIDATION_STEP_LIMIT: u64 = 10_000;
+
+/// Simulated signature of a transaction hash: only `signer` can produce it
+pub fn sign(signer: &str, transaction_hash: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("signature-{}-{}", signer, transaction_hash));
+    format!("{:x}", hasher.finalize())
+}
+
+/// The call a transaction makes, as `__validate__` receives it: `[to, selector, args...]`
+pub fn calls(tx: &Transaction) -> Vec<String> {
+    match tx.tx_type {
+        TransactionType::Invoke => match (&tx.contract_address, &tx.entry_point) {
+            (Some(contract_address), Some(entry_point)) => {
+                let mut calls = vec![contract_address.clone(), entry_point.clone()];
+                calls.extend(tx.calldata.iter().cloned());
+                calls
+            }
+            // A plain transfer is a call to the fee token's `transfer(recipient, amount)`
+            _ => vec![
+                FEE_TOKEN_ADDRESS.to_string(),
+                TRANSFER_SELECTOR.to_string(),
+                tx.receiver.clone().unwrap_or_default(),
+                tx.amount.unwrap_or_default().to_string(),
+            ],
+        },
+        TransactionType::Declare => vec![tx.contract_address.clone().unwrap_or_default(), "declare".to_string()],
+        TransactionType::DeployAccount => vec![tx.sender.clone(), "deploy_account".to_string()],
+        TransactionType::L1Handler => vec![],
+    }
+}
+
+/// What the validation entry points run with: the balances and storage committed before the
+/// transaction, the timestamp of the block it is validated for, and the steps each entry point may use
+#[derive(Debug, Clone, Copy)]
+pub struct Validation<'a> {
+    pub committed_balances: &'a HashMap<String, U256>,
+    pub committed_storage: &'a HashMap<String, HashMap<String, U256>>,
+    pub block_timestamp: u64,
+    pub step_limit: u64,
+}
+
+/// Runs the `__validate__` of `account`, the sender of `tx`, on `state`. It may use at most
+/// `validation.step_limit` steps, cannot call other contracts, send messages to L1 or emit events.
+pub fn validate(
+    account: &dyn Contract,
+    tx: &Transaction,
+    state: &mut StateOverlay,
+    meter: &mut ResourceMeter,
+    validation: &Validation,
+) -> Result<(), String> {
+    meter.storage_write(&tx.sender, "nonce");
+    run_validation(account, VALIDATE_ENTRY_POINT, tx, state, meter, validation)
+}
+
+/// Runs the `__validate_paymaster__` of `paymaster`, the paymaster named by `tx`, under the
+/// same rules as `__validate__`
+pub fn validate_paymaster(
+    paymaster: &dyn Contract,
+    tx: &Transaction,
+    state: &mut StateOverlay,
+    meter: &mut ResourceMeter,
+    validation: &Validation,
+) -> Result<(), String> {
+    run_validation(paymaster, VALIDATE_PAYMASTER_ENTRY_POINT, tx, state, meter, validation)
+}
+
+fn run_validation(
+    contract: &dyn Contract,
+    entry_point: &str,
+    tx: &Transaction,
+    state: &mut StateOverlay,
+    meter: &mut ResourceMeter,
+    validation: &Validation,
+) -> Result<(), String> {
+    let address = match entry_point {
+        VALIDATE_PAYMASTER_ENTRY_POINT => tx.paymaster.clone().unwrap_or_default(),
+        _ => tx.sender.clone(),
+    };
+    let tx_info = TxInfo::new(tx, validation.block_timestamp);
+    let contracts = HashMap::new();
+    let mut messages = vec![];
+    let mut events = vec![];
+    let step_limit = meter.resources().steps.saturating_add(validation.step_limit);
+    let mut ctx = ExecutionContext {
+        contract_address: address,
+        caller_address: String::new(), // called by the protocol, not by a contract
+        state,
+        committed_balances: validation.committed_balances,
+        committed_storage: validation.committed_storage,
+        contracts: &contracts,
+        meter,
+        messages: &mut messages,
+        events: &mut events,
+        tx_info: &tx_info,
+        step_limit: Some(step_limit),
+    };
+    contract.execute(entry_point, &calls(tx), &mut ctx)?;
+    // Storage accesses are metered without checking the limit
+    ctx.steps(0)?;
+    if !messages.is_empty() || !events.is_empty() {
+        return Err(format!("{} cannot send messages to L1 or emit events", entry_point));
+    }
+    Ok(())
+}
+
+/// Checks every `[signer, signature]` pair of the transaction and returns the signers
+fn verified_signers(ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+    let signature = ctx.tx_info.signature.clone();
+    if !signature.len().is_multiple_of(2) {
+        return Err("signature must be [signer, signature] pairs".to_string());
+    }
+    let mut signers = vec![];
+    for pair in signature.chunks(2) {
+        ctx.steps(100)?;
+        ctx.meter.builtin(Builtin::Ecdsa, 1);
+        if pair[1] != sign(&pair[0], &ctx.tx_info.transaction_hash) {
+            return Err(format!("invalid signature from {}", pair[0]));
+        }
+        signers.push(pair[0].clone());
+    }
+    Ok(signers)
+}
+
+fn require_signer(ctx: &mut ExecutionContext, signer: &str) -> Result<(), String> {
+    if verified_signers(ctx)?.iter().any(|s| s == signer) {
+        Ok(())
+    } else {
+        Err(format!("not signed by {}", signer))
+    }
+}
+
+fn validate_entry_point(selector: &str) -> Option<EntryPointType> {
+    (selector == VALIDATE_ENTRY_POINT).then_some(EntryPointType::External)
+}
+
+/// Accepts a transaction signed by at least `threshold` of its `owners`
+pub struct MultisigAccount {
+    pub owners: Vec<String>,
+    pub threshold: usize,
+}
+
+impl Contract for MultisigAccount {
+    fn class_hash(&self) -> String {
+        "multisig_account_class".to_string()
+    }
+
+    fn entry_point_type(&self, selector: &str) -> Option<EntryPointType> {
+        validate_entry_point(selector)
+    }
+
+    fn execute(&self, _selector: &str, _calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+        let signers = verified_signers(ctx)?;
+        let approvals: HashSet<&String> = signers.iter().filter(|signer| self.owners.contains(signer)).collect();
+        if approvals.len() < self.threshold {
+            return Err(format!("{} of {} owner signatures", approvals.len(), self.threshold));
+        }
+        Ok(vec![])
+    }
+}
+
+/// Accepts anything signed by `owner`, and calls to `allowed_contract` signed by
+/// `session_key` until `expires_at`
+pub struct SessionKeyAccount {
+    pub owner: String,
+    pub session_key: String,
+    pub allowed_contract: String,
+    pub expires_at: u64, // seconds since the Unix epoch
+}
+
+impl Contract for SessionKeyAccount {
+    fn class_hash(&self) -> String {
+        "session_key_account_class".to_string()
+    }
+
+    fn entry_point_type(&self, selector: &str) -> Option<EntryPointType> {
+        validate_entry_point(selector)
+    }
+
+    fn execute(&self, _selector: &str, calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+        let signers = verified_signers(ctx)?;
+        if signers.contains(&self.owner) {
+            return Ok(vec![]);
+        }
+        if !signers.contains(&self.session_key) {
+            return Err(format!("not signed by {} or its session key", self.owner));
+        }
+        if ctx.tx_info.block_timestamp >= self.expires_at {
+            return Err("session key expired".to_string());
+        }
+        match calldata.first() {
+            Some(target) if *target == self.allowed_contract => Ok(vec![]),
+            target => Err(format!("session key cannot call {}", target.cloned().unwrap_or_default())),
+        }
+    }
+}
+
+/// Accepts transactions signed by `owner` as long as the fee tokens it has sent stay within
+/// `limit`. What it has sent so far is kept in its storage under `spent`.
+pub struct SpendingLimitAccount {
+    pub owner: String,
+    pub limit: U256,
+}
+
+impl Contract for SpendingLimitAccount {
+    fn class_hash(&self) -> String {
+        "spending_limit_account_class".to_string()
+    }
+
+    fn entry_point_type(&self, selector: &str) -> Option<EntryPointType> {
+        validate_entry_point(selector)
+    }
+
+    fn execute(&self, _selector: &str, calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+        require_signer(ctx, &self.owner)?;
+        // Only fee tokens count: a contract's own `transfer` entry point moves something else
+        if calldata.first().map(String::as_str) == Some(FEE_TOKEN_ADDRESS)
+            && calldata.get(1).map(String::as_str) == Some(TRANSFER_SELECTOR)
+        {
+            let amount = parse_felt(calldata, 3)?;
+            let spent = ctx.storage_read("spent").checked_add(amount).ok_or("spent amount overflows u256")?;
+            if spent > self.limit {
+                return Err(format!("spending limit exceeded: {} of {}", spent, self.limit));
+            }
+            ctx.storage_write("spent", spent);
+        }
+        Ok(vec![])
+    }
+}
+
+/// Accepts transactions signed by `owner` from `unlock_at` on
+pub struct TimeLockAccount {
+    pub owner: String,
+    pub unlock_at: u64, // seconds since the Unix epoch
+}
+
+impl Contract for TimeLockAccount {
+    fn class_hash(&self) -> String {
+        "time_lock_account_class".to_string()
+    }
+
+    fn entry_point_type(&self, selector: &str) -> Option<EntryPointType> {
+        validate_entry_point(selector)
+    }
+
+    fn execute(&self, _selector: &str, _calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+        require_signer(ctx, &self.owner)?;
+        if ctx.tx_info.block_timestamp < self.unlock_at {
+            return Err(format!("account is locked until {}", self.unlock_at));
+        }
+        Ok(vec![])
+    }
+}
+
+/// Sponsors the fees of new users: it pays for up to `max_transactions_per_account`
+/// transactions per sender, each with a max fee of at most `max_fee`. How many it has
+/// sponsored for each sender is kept in its storage under the sender's address.
+pub struct OnboardingPaymaster {
+    pub max_fee: U256,
+    pub max_transactions_per_account: u64,
+}
+
+impl Contract for OnboardingPaymaster {
+    fn class_hash(&self) -> String {
+        "onboarding_paymaster_class".to_string()
+    }
+
+    fn entry_point_type(&self, selector: &str) -> Option<EntryPointType> {
+        (selector == VALIDATE_PAYMASTER_ENTRY_POINT).then_some(EntryPointType::External)
+    }
+
+    fn execute(&self, _selector: &str, _calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+        if ctx.tx_info.max_fee > self.max_fee {
+            return Err(format!("max fee {} is above the sponsored {}", ctx.tx_info.max_fee, self.max_fee));
+        }
+        let sender = ctx.tx_info.account_address.clone();
+        let sponsored = ctx.storage_read(&sender);
+        if sponsored >= U256::from(self.max_transactions_per_account) {
+            return Err(format!("{} already used its {} sponsored transactions", sender, self.max_transactions_per_account));
+        }
+        ctx.storage_write(&sender, sponsored.checked_add(U256::ONE).ok_or("sponsored count overflows u256")?);
+        Ok(vec![])
+    }
+}
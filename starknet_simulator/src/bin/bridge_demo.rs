@@ -0,0 +1,81 @@
+// Scripted walkthrough of the bridge: deposit ETH from L1, spend it on L2, withdraw it back to L1
+
+use starknet_simulator::bridge::{BridgeLedger, L1Bridge, L2Bridge, L2_BRIDGE_ADDRESS};
+use starknet_simulator::mempool::Mempool;
+use starknet_simulator::prover::Prover;
+use starknet_simulator::sequencer::Sequencer;
+use starknet_simulator::state;
+use starknet_simulator::transaction::{Transaction, TransactionType};
+use starknet_simulator::utils::U256;
+use starknet_simulator::verifier::Verifier;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn main() {
+    let mempool = Arc::new(Mempool::new());
+    let mut sequencer = Sequencer::new(mempool.clone());
+    sequencer.processing_delay = Duration::ZERO;
+    sequencer.deploy_contract(L2_BRIDGE_ADDRESS, Arc::new(L2Bridge));
+    let core_contract = sequencer.core_contract.clone();
+    let sequencer = Arc::new(Mutex::new(sequencer));
+
+    let mut prover = Prover::new(Arc::clone(&sequencer));
+    prover.proving_delay = Duration::ZERO;
+    let mut verifier = Verifier::new(Arc::clone(&sequencer), core_contract);
+    verifier.verification_delay = Duration::ZERO;
+
+    let mut l1_balances = HashMap::new();
+    l1_balances.insert("Mark".to_string(), U256::from(1_000u64));
+    let mut l1_bridge = L1Bridge::new(sequencer.lock().unwrap().core_contract.clone(), l1_balances);
+
+    println!("\n🌉 Step 1: Mark deposits 100 ETH from L1 (no tokens on L2 yet)");
+    l1_bridge
+        .deposit("Mark", "Mark", U256::from(100u64), U256::from(10u64))
+        .expect("Mark can afford the deposit");
+    print_ledger(&l1_bridge, &mempool);
+    sequencer.lock().unwrap().process_transactions();
+    print_ledger(&l1_bridge, &mempool);
+
+    println!("\n💸 Step 2: Mark pays Bob 20 tokens on L2");
+    let transfer = Transaction::new("Mark".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(20u64)), 0);
+    mempool.submit_transaction(transfer);
+    sequencer.lock().unwrap().process_transactions();
+
+    println!("\n🔥 Step 3: Mark withdraws 40 tokens back to L1");
+    let withdrawal = Transaction::new("Mark".to_string(), TransactionType::Invoke, None, None, None, 1)
+        .with_call(L2_BRIDGE_ADDRESS, "initiate_withdraw", vec!["Mark".to_string(), "40".to_string()])
+        .with_max_fee(U256::from(20u64));
+    mempool.submit_transaction(withdrawal);
+    sequencer.lock().unwrap().process_transactions();
+    print_ledger(&l1_bridge, &mempool);
+
+    println!("\n⏳ Step 4: The withdrawal cannot be claimed before its block is final on L1");
+    if let Err(err) = l1_bridge.withdraw("Mark", U256::from(40u64)) {
+        println!("❌ Claim refused: {}", err);
+    }
+
+    println!("\n🔐 Step 5: Prove and settle the blocks on L1, then claim");
+    prover.verify_proof().expect("nothing else moves the demo's blocks");
+    verifier.verify_proofs();
+    l1_bridge.withdraw("Mark", U256::from(40u64)).expect("the withdrawal is final");
+    print_ledger(&l1_bridge, &mempool);
+}
+
+fn print_ledger(l1_bridge: &L1Bridge, mempool: &Mempool) {
+    let ledger = BridgeLedger::new(l1_bridge, &mempool.storage.lock().unwrap());
+    println!(
+        "📒 Ledger: {} locked on L1 | {} on L2 | {} deposits in flight | {} withdrawals to claim | reconciled: {}",
+        ledger.l1_locked,
+        ledger.l2_bridged_supply(),
+        ledger.deposits_in_flight(),
+        ledger.withdrawals_in_flight(),
+        ledger.is_reconciled()
+    );
+    println!(
+        "💰 Mark: {} ETH on L1, {} tokens on L2",
+        state::get_balance(&l1_bridge.l1_balances, "Mark"),
+        state::get_balance(&mempool.balances.lock().unwrap(), "Mark")
+    );
+}
@@ -3,8 +3,16 @@ use starknet_simulator::sequencer::Sequencer;
 use starknet_simulator::transaction::{Transaction, TransactionType};
 use starknet_simulator::prover::Prover;
 use starknet_simulator::verifier::Verifier;
+use starknet_simulator::l1::StarknetCoreContract;
+use starknet_simulator::da::{self, DaMode};
+use starknet_simulator::bridge::{BridgeLedger, L1Bridge, L2Bridge, L2_BRIDGE_ADDRESS};
+use starknet_simulator::pipeline::{Pipeline, PipelineConfig, PipelineStats};
+use starknet_simulator::storage::FileStorage;
+use starknet_simulator::utils::U256;
 
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::io;
 use std::io::Write; // For flushing stdout
 
@@ -12,9 +20,30 @@ use std::io::Write; // For flushing stdout
 
 fn main() {
     let mempool = Arc::new(Mempool::new());
-    let sequencer = Arc::new(Mutex::new(Sequencer::new(mempool.clone())));
-    let prover = Prover::new(Arc::clone(&sequencer)); 
-    let verifier = Verifier::new(Arc::clone(&sequencer));
+    // `starknet_simulator <file>` keeps the chain, state and mempool in <file> across restarts
+    let sequencer = match std::env::args().nth(1) {
+        Some(path) => match FileStorage::open(&path).and_then(|storage| Sequencer::open(mempool.clone(), storage)) {
+            Ok(sequencer) => sequencer,
+            Err(err) => {
+                println!("❌ Cannot open {}: {}", path, err);
+                return;
+            }
+        },
+        None => Sequencer::new(mempool.clone()),
+    };
+    let sequencer = Arc::new(Mutex::new(sequencer));
+    let core_contract = sequencer.lock().unwrap().core_contract.clone();
+    sequencer.lock().unwrap().deploy_contract(L2_BRIDGE_ADDRESS, Arc::new(L2Bridge));
+    let mut l1_balances = HashMap::new();
+    for account in ["Alice", "Bob", "Mark", "Cyndie", "Mike"] {
+        l1_balances.insert(account.to_string(), U256::from(1_000u64)); // ETH on L1
+    }
+    let mut l1_bridge = L1Bridge::new(Arc::clone(&core_contract), l1_balances);
+    let mut prover = Prover::new(Arc::clone(&sequencer));
+    prover.max_blocks_per_job = 3; // aggregate up to 3 blocks per proof...
+    prover.max_wait = Duration::from_secs(30); // ...or whatever is waiting after 30s
+    let verifier = Verifier::new(Arc::clone(&sequencer), Arc::clone(&core_contract));
+    let mut pipeline: Option<Pipeline> = None;
 
     loop {
         println!("\n🌟 Welcome to the Starknet Simulator!! 🌟");
@@ -22,9 +51,12 @@ fn main() {
         println!("1. View Wallet Balances");
         println!("2. Submit Transaction");
         println!("3. Process Transactions");
-        println!("4. Prove Pending Blocks (Finalize on Ethereum)");
-        println!("5. Verify Proofs on Ethereum (Finalize on L1)");
-        println!("6. Exit");
+        println!("4. Prove Blocks (Accepted on L2 → Proven)");
+        println!("5. Submit Proofs to Ethereum (Proven → Accepted on L1)");
+        println!("6. View Ethereum L1 State Updates & DA Costs");
+        println!("7. Bridge ETH (StarkGate)");
+        println!("8. Background Pipeline (start / stats / stop)");
+        println!("9. Exit");
 
         let choice = get_input("Select an option: ");
 
@@ -32,9 +64,20 @@ fn main() {
             "1" => display_balances(&mempool),
             "2" => submit_transaction(&mempool),
             "3" => sequencer.lock().unwrap().process_transactions(),
-            "4" => prover.verify_proof(),
+            "4" => {
+                prover.reprove_rejected();
+                if let Err(err) = prover.verify_proof() {
+                    println!("❌ Proof job aborted: {}", err);
+                }
+            }
             "5" => verifier.verify_proofs(),
-            "6" => {
+            "6" => display_l1_state(&core_contract, &sequencer),
+            "7" => bridge_menu(&mempool, &mut l1_bridge),
+            "8" => pipeline_menu(&mut pipeline, &sequencer, &core_contract),
+            "9" => {
+                if let Some(pipeline) = pipeline.take() {
+                    pipeline.shutdown();
+                }
                 println!("👋 Exiting StarkNet Simulator. Goodbye!");
                 break;
             }
@@ -43,6 +86,52 @@ fn main() {
     }
 }
 
+/// Starts the mempool, sequencer, prover and verifier workers, or shows their stats and stops them.
+/// Transactions submitted with option 2 are picked up by the running sequencer.
+fn pipeline_menu(pipeline: &mut Option<Pipeline>, sequencer: &Arc<Mutex<Sequencer>>, core_contract: &Arc<Mutex<StarknetCoreContract>>) {
+    match pipeline.take() {
+        None => {
+            let mut prover = Prover::new(Arc::clone(sequencer));
+            prover.max_blocks_per_job = 3;
+            prover.max_wait = Duration::from_secs(30);
+            let verifier = Verifier::new(Arc::clone(sequencer), Arc::clone(core_contract));
+            let policy = sequencer.lock().unwrap().block_policy;
+            let config = PipelineConfig { poll_interval: Duration::from_secs(1) };
+            *pipeline = Some(Pipeline::start(Arc::clone(sequencer), prover, verifier, config));
+            println!(
+                "⚙️ Pipeline running: a block every {}s or once {} txs / {} L2 gas are waiting; proofs and L1 updates in the background.",
+                policy.block_time.as_secs(),
+                policy.max_transactions,
+                policy.max_l2_gas
+            );
+        }
+        Some(running) => {
+            print_pipeline_stats(&running.stats());
+            if get_input("Stop the pipeline? (y/n): ").eq_ignore_ascii_case("y") {
+                println!("⏹️ Draining the pipeline...");
+                print_pipeline_stats(&running.shutdown());
+            } else {
+                *pipeline = Some(running);
+            }
+        }
+    }
+}
+
+fn print_pipeline_stats(stats: &PipelineStats) {
+    println!("\n📈 Pipeline after {}s", stats.elapsed.as_secs());
+    println!("   - Mempool: {} transaction(s) waiting", stats.in_channel + stats.in_mempool);
+    println!("   - Pending block: {} transaction(s) executed, not yet sealed", stats.in_pending_block);
+    println!(
+        "   - Blocks: {} produced, {} awaiting proof, {} awaiting L1, {} settled, {} rejected",
+        stats.blocks_produced, stats.awaiting_proof, stats.awaiting_l1, stats.blocks_settled, stats.blocks_rejected
+    );
+    println!(
+        "   - Throughput: {:.2} tx/s included, {:.2} tx/s settled",
+        stats.included_tps(),
+        stats.settled_tps()
+    );
+}
+
 /// Handles transaction submission
 fn submit_transaction(mempool: &Arc<Mempool>) {
     println!("💸 Submit a Transaction");
@@ -59,37 +148,40 @@ fn submit_transaction(mempool: &Arc<Mempool>) {
             println!("❌ Invalid sender name! Please enter a valid sender.");
         }
     };
-    let balances = mempool.balances.lock().unwrap();
-    let sender_balance = balances.get(&sender).unwrap_or(&0);
+    let sender_balance = mempool.balances.lock().unwrap().get(&sender).copied().unwrap_or_default();
     println!("💰 {}'s current balance: {} tokens", sender, sender_balance);
 
     let tx_type = get_input("Enter transaction type (invoke, declare, deploy): ").to_lowercase();
 
     // Retrieve the correct nonce automatically from mempool
-    let nonce = {
-        let nonces = mempool.nonces.lock().unwrap();
-        *nonces.get(&sender).unwrap_or(&0)
-    };
+    let nonce = mempool.next_nonce(&sender);
+    let gas_prices = *mempool.gas_prices.lock().unwrap();
 
     let transaction = match tx_type.as_str() {
         "invoke" => {
             let receiver = get_input("Enter receiver name: ");
-            let amount: u64 = get_input("Enter amount: ").parse().unwrap_or(0);
-            let fee = Transaction::calculate_fee(&TransactionType::Invoke);
-            println!("💸 Transaction Fee: {} tokens", fee);
+            let amount = match get_input("Enter amount: ").parse::<U256>() {
+                Ok(amount) => amount,
+                Err(err) => {
+                    println!("❌ Invalid amount: {}", err);
+                    return;
+                }
+            };
+            let fee = Transaction::estimate_fee(&TransactionType::Invoke, &gas_prices);
+            println!("💸 Estimated Fee: {} tokens", fee);
             println!("🔢 Assigned Nonce: {}", nonce);
             Transaction::new(sender, TransactionType::Invoke, Some(receiver), None, Some(amount), nonce)
         }
         "declare" => {
             let contract_address = get_input("Enter contract address: ");
-            let fee = Transaction::calculate_fee(&TransactionType::Declare);
-            println!("💸 Transaction Fee: {} tokens", fee);
+            let fee = Transaction::estimate_fee(&TransactionType::Declare, &gas_prices);
+            println!("💸 Estimated Fee: {} tokens", fee);
             println!("🔢 Assigned Nonce: {}", nonce);
             Transaction::new(sender, TransactionType::Declare, None, Some(contract_address), None, nonce)
         }
         "deploy" => {
-            let fee = Transaction::calculate_fee(&TransactionType::DeployAccount);
-            println!("💸 Transaction Fee: {} tokens", fee);
+            let fee = Transaction::estimate_fee(&TransactionType::DeployAccount, &gas_prices);
+            println!("💸 Estimated Fee: {} tokens", fee);
             println!("🔢 Assigned Nonce: {}", nonce);
             Transaction::new(sender, TransactionType::DeployAccount, None, None, None, nonce)
         }
@@ -99,10 +191,107 @@ fn submit_transaction(mempool: &Arc<Mempool>) {
         }
     };
 
+    // Let the sender cap the fee (only the actual fee is charged after execution) and add a tip
+    let estimate = Transaction::estimate_fee(&transaction.tx_type, &gas_prices);
+    let max_fee = match get_input(&format!("Enter max fee (press Enter to use {}): ", estimate)) {
+        input if input.is_empty() => estimate,
+        input => match input.parse::<U256>() {
+            Ok(max_fee) => max_fee,
+            Err(err) => {
+                println!("❌ Invalid max fee: {}", err);
+                return;
+            }
+        },
+    };
+    let tip = match get_input("Enter priority tip per L2 gas (press Enter for 0): ") {
+        input if input.is_empty() => U256::ZERO,
+        input => match input.parse::<U256>() {
+            Ok(tip) => tip,
+            Err(err) => {
+                println!("❌ Invalid tip: {}", err);
+                return;
+            }
+        },
+    };
+    let transaction = transaction.with_max_fee(max_fee).with_tip(tip);
+
     mempool.submit_transaction(transaction);
     println!("✅ Transaction submitted successfully!");
 }
 
+/// Deposits, withdrawals and the bridge ledger
+fn bridge_menu(mempool: &Arc<Mempool>, l1_bridge: &mut L1Bridge) {
+    println!("🌉 StarkGate Bridge");
+    let action = get_input("Choose an action (deposit, withdraw, claim, ledger): ").to_lowercase();
+
+    match action.as_str() {
+        "deposit" => {
+            let l1_sender = get_input("Enter L1 sender: ");
+            let l2_recipient = get_input("Enter L2 recipient: ");
+            let (Some(amount), Some(message_fee)) = (read_amount("Enter amount: "), read_amount("Enter L1 message fee: ")) else {
+                return;
+            };
+            match l1_bridge.deposit(&l1_sender, &l2_recipient, amount, message_fee) {
+                Ok(message) => println!("✅ Deposit sent to L2 as message #{}. It is minted in the next block.", message.nonce),
+                Err(err) => println!("❌ Deposit failed: {}", err),
+            }
+        }
+        "withdraw" => {
+            let sender = get_input("Enter L2 sender: ");
+            let l1_recipient = get_input("Enter L1 recipient: ");
+            let Some(amount) = read_amount("Enter amount: ") else {
+                return;
+            };
+            // A bridge call does more work than a plain transfer, so leave room for it
+            let gas_prices = *mempool.gas_prices.lock().unwrap();
+            let max_fee = Transaction::estimate_fee(&TransactionType::Invoke, &gas_prices)
+                .checked_mul(U256::from(2u64))
+                .unwrap_or(U256::MAX);
+            let nonce = mempool.next_nonce(&sender);
+            let transaction = Transaction::new(sender, TransactionType::Invoke, None, None, None, nonce)
+                .with_call(L2_BRIDGE_ADDRESS, "initiate_withdraw", vec![l1_recipient, amount.to_string()])
+                .with_max_fee(max_fee);
+            mempool.submit_transaction(transaction);
+            println!("✅ Withdrawal submitted (max fee {}). Claim it on L1 once its block is finalized.", max_fee);
+        }
+        "claim" => {
+            let l1_recipient = get_input("Enter L1 recipient: ");
+            let Some(amount) = read_amount("Enter amount: ") else {
+                return;
+            };
+            match l1_bridge.withdraw(&l1_recipient, amount) {
+                Ok(()) => println!("✅ Claimed {} ETH on L1 for {}", amount, l1_recipient),
+                Err(err) => println!("❌ Claim failed: {}", err),
+            }
+        }
+        "ledger" => {
+            let ledger = BridgeLedger::new(l1_bridge, &mempool.storage.lock().unwrap());
+            println!("\n📒 Bridge Ledger:");
+            println!("   - Locked on L1: {} ETH", ledger.l1_locked);
+            println!("   - Circulating on L2: {} tokens", ledger.l2_bridged_supply());
+            println!("   - Deposits in flight: {}", ledger.deposits_in_flight());
+            println!("   - Withdrawals waiting to be claimed: {}", ledger.withdrawals_in_flight());
+            println!("   - Reconciled: {}", if ledger.is_reconciled() { "✅" } else { "❌" });
+            println!("💰 L1 Balances:");
+            for (account, balance) in &l1_bridge.l1_balances {
+                println!("   - {}: {} ETH", account, balance);
+            }
+        }
+        _ => println!("❌ Invalid bridge action."),
+    }
+}
+
+/// Reads an amount, printing an error when it does not parse
+fn read_amount(prompt: &str) -> Option<U256> {
+    match get_input(prompt).parse::<U256>() {
+        Ok(amount) => Some(amount),
+        Err(err) => {
+            println!("❌ Invalid amount: {}", err);
+            None
+        }
+    }
+}
+
 /// Displays wallet balances
 fn display_balances(mempool: &Arc<Mempool>) {
     let balances = mempool.balances.lock().unwrap();
@@ -112,6 +301,57 @@ fn display_balances(mempool: &Arc<Mempool>) {
     }
 }
 
+/// Displays the state the core contract accepted on L1
+fn display_l1_state(core_contract: &Arc<Mutex<StarknetCoreContract>>, sequencer: &Arc<Mutex<Sequencer>>) {
+    let mut core_contract = core_contract.lock().unwrap();
+    println!("\n🌍 Starknet Core Contract on Ethereum L1:");
+    println!("   - Last accepted block: #{}", core_contract.block_number);
+    println!("   - State root: {}", core_contract.state_root);
+    println!("   - L1→L2 messages sent: {}", core_contract.l1_to_l2_message_nonce);
+    println!(
+        "   - L2→L1 messages waiting to be consumed: {}",
+        core_contract.l2_to_l1_messages.values().sum::<u64>()
+    );
+    println!("📜 State Update Log:");
+    for update in &core_contract.state_updates {
+        println!(
+            "   - Blocks #{}..#{} | {} -> {} | {} storage contract(s), {} nonce update(s), {} L1 message(s) consumed, {} L2 message(s) sent | DA: {}",
+            update.first_block_number,
+            update.block_number,
+            update.prev_state_root,
+            update.new_state_root,
+            update.state_diff.storage_updates.len(),
+            update.state_diff.nonce_updates.len(),
+            update.l1_to_l2_messages.len(),
+            update.l2_to_l1_messages.len(),
+            update.da_mode
+        );
+    }
+
+    // 📦 What the blocks produced so far would cost to publish in each DA mode
+    let sequencer = sequencer.lock().unwrap();
+    let blocks = sequencer.blocks.lock().unwrap();
+    println!("📦 Data availability: {} (blocks so far in each mode)", core_contract.da_mode);
+    for cost in da::compare_da_costs(&blocks, &[DaMode::calldata(), DaMode::blob(), DaMode::offchain()]) {
+        println!(
+            "   - {}: {} bytes, {} unit(s), {} L1 block(s), cost {}",
+            cost.mode, cost.bytes, cost.units, cost.l1_blocks, cost.cost
+        );
+    }
+
+    match get_input("Switch DA mode (calldata, blob, offchain) or press Enter to go back: ").to_lowercase().as_str() {
+        "calldata" => core_contract.da_mode = DaMode::calldata(),
+        "blob" => core_contract.da_mode = DaMode::blob(),
+        "offchain" => core_contract.da_mode = DaMode::offchain(),
+        "" => return,
+        _ => {
+            println!("❌ Invalid DA mode.");
+            return;
+        }
+    }
+    println!("✅ State diffs are now published via {}", core_contract.da_mode);
+}
+
 /// Gets user input
 fn get_input(prompt: &str) -> String {
     let mut input = String::new();
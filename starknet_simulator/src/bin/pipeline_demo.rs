@@ -0,0 +1,115 @@
+// Runs the mempool, sequencer, prover and verifier as concurrent workers under a steady load
+// and prints throughput and queue depths while blocks are built, proven and settled
+
+use starknet_simulator::mempool::Mempool;
+use starknet_simulator::pipeline::{Pipeline, PipelineConfig, PipelineStats};
+use starknet_simulator::prover::Prover;
+use starknet_simulator::sequencer::{BlockPolicy, ExecutionMode, Sequencer};
+use starknet_simulator::transaction::{Transaction, TransactionType};
+use starknet_simulator::utils::U256;
+use starknet_simulator::verifier::Verifier;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TRANSACTIONS_PER_SENDER: u64 = 12;
+const SUBMISSION_GAP: Duration = Duration::from_millis(50);
+
+fn main() {
+    let mempool = Arc::new(Mempool::new());
+    let mut sequencer = Sequencer::new(mempool.clone());
+    sequencer.processing_delay = Duration::ZERO;
+    sequencer.execution_mode = ExecutionMode::Parallel { workers: 4 };
+    // Close a block at 8 transactions, or 250ms after the previous one
+    sequencer.block_policy = BlockPolicy {
+        max_transactions: 8,
+        block_time: Duration::from_millis(250),
+        ..BlockPolicy::default()
+    };
+    let core_contract = sequencer.core_contract.clone();
+    let sequencer = Arc::new(Mutex::new(sequencer));
+
+    let mut prover = Prover::new(Arc::clone(&sequencer));
+    prover.proving_delay = Duration::from_millis(400);
+    prover.max_blocks_per_job = 2;
+    prover.max_wait = Duration::from_secs(1);
+    let mut verifier = Verifier::new(Arc::clone(&sequencer), core_contract);
+    verifier.verification_delay = Duration::from_millis(300);
+
+    let config = PipelineConfig { poll_interval: Duration::from_millis(50) };
+    let pipeline = Arc::new(Pipeline::start(sequencer, prover, verifier, config));
+
+    // Three wallets submit at a steady rate while earlier blocks are still in flight
+    let submitters: Vec<_> = [("Alice", "Bob"), ("Bob", "Cyndie"), ("Cyndie", "Alice")]
+        .into_iter()
+        .map(|(sender, receiver)| {
+            let pipeline = Arc::clone(&pipeline);
+            thread::spawn(move || {
+                for nonce in 0..TRANSACTIONS_PER_SENDER {
+                    // Headroom over the current fee, since the L2 gas price rises as blocks fill up
+                    let tx = Transaction::new(sender.to_string(), TransactionType::Invoke, Some(receiver.to_string()), None, Some(U256::ONE), nonce)
+                        .with_max_fee(U256::from(20u64));
+                    pipeline.submit(tx);
+                    thread::sleep(SUBMISSION_GAP);
+                }
+            })
+        })
+        .collect();
+
+    let mut samples = vec![];
+    while submitters.iter().any(|s| !s.is_finished()) {
+        thread::sleep(Duration::from_millis(200));
+        samples.push(pipeline.stats());
+    }
+    for submitter in submitters {
+        submitter.join().expect("submitter panicked");
+    }
+
+    // Let the last blocks work their way through proving and settlement
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let stats = pipeline.stats();
+        let drained = stats.in_channel + stats.in_mempool == 0 && stats.transactions_settled == stats.transactions_included;
+        if drained || Instant::now() > deadline {
+            break;
+        }
+        thread::sleep(Duration::from_millis(200));
+        samples.push(pipeline.stats());
+    }
+
+    let pipeline = Arc::into_inner(pipeline).expect("submitters are done");
+    let final_stats = pipeline.shutdown();
+    samples.push(final_stats);
+
+    println!("\n📈 Pipeline under load");
+    println!(
+        "{:>7} | {:>9} | {:>7} | {:>6} | {:>11} | {:>8} | {:>7} | {:>8}",
+        "t (ms)", "submitted", "mempool", "blocks", "await proof", "await L1", "settled", "L1 tx/s"
+    );
+    for stats in &samples {
+        print_row(stats);
+    }
+    println!(
+        "\n✅ {} transaction(s) included in {} block(s), {} settled on L1 ({:.1} tx/s included, {:.1} tx/s settled)",
+        final_stats.transactions_included,
+        final_stats.blocks_produced,
+        final_stats.transactions_settled,
+        final_stats.included_tps(),
+        final_stats.settled_tps()
+    );
+}
+
+fn print_row(stats: &PipelineStats) {
+    println!(
+        "{:>7} | {:>9} | {:>7} | {:>6} | {:>11} | {:>8} | {:>7} | {:>8.1}",
+        stats.elapsed.as_millis(),
+        stats.submitted,
+        stats.in_channel + stats.in_mempool,
+        stats.blocks_produced,
+        stats.awaiting_proof,
+        stats.awaiting_l1,
+        stats.blocks_settled,
+        stats.settled_tps()
+    );
+}
@@ -1,16 +1,80 @@
-use crate::transaction::Transaction;
+use crate::da;
+use crate::event::Event;
+use crate::gas::GasPrices;
+use crate::l1::L2ToL1Message;
+use crate::prover::BlockProof;
+use crate::state::{StateDiff, StateSnapshot};
+use crate::transaction::{Transaction, TransactionReceipt};
+use crate::utils::U256;
 use sha2::{Sha256, Digest};
+use std::fmt;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 // This is the status of a block
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+// Blocks move forward one stage at a time: Pending → AcceptedOnL2 → ProofRequested → Proven →
+// SubmittedToL1 → AcceptedOnL1. A block whose proof fails is Rejected and can be re-queued for proving,
+// and the blocks of an aborted proof job go back to AcceptedOnL2.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BlockStatus {
-    Pending,         
-    AcceptedOnL2,    
-    AcceptedOnL1,   
-    Rejected,       
+    Pending,         // still being built by the sequencer
+    AcceptedOnL2,    // sealed, waiting for a proof
+    ProofRequested,  // part of a proof job that is running
+    Proven,          // proof attached, waiting to be sent to L1
+    SubmittedToL1,   // state update sent to the core contract
+    AcceptedOnL1,    // final
+    Rejected { reason: String }, // the proof did not check out; it can be re-proven
 }
 
+impl BlockStatus {
+    /// Whether a block may move from this status to `next`
+    pub fn can_transition_to(&self, next: &BlockStatus) -> bool {
+        matches!(
+            (self, next),
+            (BlockStatus::Pending, BlockStatus::AcceptedOnL2)
+                | (BlockStatus::AcceptedOnL2, BlockStatus::ProofRequested)
+                | (BlockStatus::ProofRequested, BlockStatus::AcceptedOnL2)
+                | (BlockStatus::ProofRequested, BlockStatus::Proven)
+                | (BlockStatus::Proven, BlockStatus::SubmittedToL1)
+                | (BlockStatus::SubmittedToL1, BlockStatus::AcceptedOnL1)
+                | (
+                    BlockStatus::ProofRequested | BlockStatus::Proven | BlockStatus::SubmittedToL1,
+                    BlockStatus::Rejected { .. }
+                )
+                | (BlockStatus::Rejected { .. }, BlockStatus::AcceptedOnL2)
+        )
+    }
+}
+
+impl fmt::Display for BlockStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockStatus::Pending => write!(f, "Pending"),
+            BlockStatus::AcceptedOnL2 => write!(f, "Accepted on L2"),
+            BlockStatus::ProofRequested => write!(f, "Proof requested"),
+            BlockStatus::Proven => write!(f, "Proven"),
+            BlockStatus::SubmittedToL1 => write!(f, "Submitted to L1"),
+            BlockStatus::AcceptedOnL1 => write!(f, "Accepted on L1"),
+            BlockStatus::Rejected { reason } => write!(f, "Rejected ({})", reason),
+        }
+    }
+}
+
+/// A block status change that skips or reverses a stage of the pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidTransition {
+    pub block_number: u64,
+    pub from: BlockStatus,
+    pub to: BlockStatus,
+}
+
+impl fmt::Display for InvalidTransition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "block #{} cannot go from {} to {}", self.block_number, self.from, self.to)
+    }
+}
+
+impl std::error::Error for InvalidTransition {}
+
 // this represents the format of a block header
 #[derive(Debug, Clone)]
 pub struct BlockHeader {
@@ -20,8 +84,107 @@ pub struct BlockHeader {
     pub block_timestamp: u64,
     pub transaction_count: usize,
     pub transaction_commitment: String,  // Hash of all txs in the block
-    pub state_root: String,  // Placeholder for future state root commitment
+    pub message_commitment: String, // Hash of all L2→L1 messages sent in the block
+    pub event_count: usize,
+    pub event_commitment: String, // Hash of all events emitted in the block
+    pub receipt_commitment: String, // Hash of every receipt: status, actual fee, gas and revert reason
+    pub parent_state_root: String, // State root before this block was applied
+    pub state_root: String,  // State root after this block was applied
     pub block_status: BlockStatus,
+    pub l1_gas_price: U256, // gas prices every tx in the block was charged at
+    pub l1_data_gas_price: U256,
+    pub l2_gas_price: U256,
+    pub l2_gas_used: u64, // block fullness, drives the next L2 gas price
+    pub state_diff_length: usize, // felts of DA data the state diff takes on L1
+}
+
+/// The block the sequencer is building. Each transaction shows up here, with its receipt and
+/// what it changed, as soon as it executes; `seal` closes it into an `L2Block`.
+#[derive(Debug, Clone)]
+pub struct PendingBlock {
+    pub block_number: u64,
+    pub parent_block_hash: String,
+    pub sequencer_address: String,
+    pub gas_prices: GasPrices, // fixed for the whole block
+    pub block_timestamp: u64, // set when the block opens; what its transactions see
+    pub transactions: Vec<Transaction>,
+    pub receipts: Vec<TransactionReceipt>,
+    pub l1_to_l2_messages: Vec<String>, // Hashes of the L1→L2 messages handled so far
+    pub parent_state: StateSnapshot, // state after the previous block
+    pub state_diffs: Vec<StateDiff>, // what each transaction changed, in order
+}
+
+impl PendingBlock {
+    /// Opens an empty block on top of `parent_state`
+    pub fn new(block_number: u64, parent_block_hash: String, sequencer_address: String, gas_prices: GasPrices, parent_state: StateSnapshot) -> Self {
+        PendingBlock {
+            block_number,
+            parent_block_hash,
+            sequencer_address,
+            gas_prices,
+            block_timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            transactions: vec![],
+            receipts: vec![],
+            l1_to_l2_messages: vec![],
+            parent_state,
+            state_diffs: vec![],
+        }
+    }
+
+    /// Adds an executed transaction together with what it changed
+    pub fn push(&mut self, tx: Transaction, receipt: TransactionReceipt, state_diff: StateDiff) {
+        self.transactions.push(tx);
+        self.receipts.push(receipt);
+        self.state_diffs.push(state_diff);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    pub fn l2_gas_used(&self) -> u64 {
+        self.receipts.iter().map(|r| r.gas_consumed.l2_gas).sum()
+    }
+
+    /// What the block has changed so far
+    pub fn state_diff(&self) -> StateDiff {
+        StateDiff::squash(&self.state_diffs)
+    }
+
+    /// The state after the latest transaction, rebuilt from the parent state
+    pub fn state(&self) -> StateSnapshot {
+        let mut state = self.parent_state.clone();
+        state.apply(&self.state_diff());
+        state
+    }
+
+    /// Looks up the receipt of a transaction executed in this block
+    pub fn get_receipt(&self, transaction_hash: &str) -> Option<&TransactionReceipt> {
+        self.receipts.iter().find(|r| r.transaction_hash == transaction_hash)
+    }
+
+    /// Closes the block on top of `state`, the state its transactions left behind: commits to
+    /// its transactions, messages and final state, and accepts it on L2
+    pub fn seal(self, state: StateSnapshot) -> L2Block {
+        let state_diff = StateDiff::from_changes(&self.parent_state, &state);
+        let mut block = L2Block::new(
+            self.block_number,
+            self.parent_block_hash,
+            self.sequencer_address,
+            self.transactions,
+            self.receipts,
+            self.gas_prices,
+        );
+        block.header.block_timestamp = self.block_timestamp;
+        block.header.parent_state_root = self.parent_state.state_root();
+        block.header.state_root = state.state_root();
+        block.set_state_diff(state_diff);
+        block.l1_to_l2_messages = self.l1_to_l2_messages;
+        block
+            .transition(BlockStatus::AcceptedOnL2)
+            .expect("a new block is pending until it is sealed");
+        block
+    }
 }
 
 /// Represents a full block containing transactions
@@ -29,11 +192,16 @@ pub struct BlockHeader {
 pub struct L2Block {
     pub header: BlockHeader,
     pub transactions: Vec<Transaction>,
+    pub receipts: Vec<TransactionReceipt>,
+    pub state_diff: StateDiff, // What this block changed, published to L1
+    pub da_encoding: Vec<U256>, // The state diff in the felt layout posted to L1
+    pub l1_to_l2_messages: Vec<String>, // Hashes of the L1→L2 messages handled in this block
+    pub proof: Option<BlockProof>, // Set once the prover has proven the block
 }
 
 impl L2Block {
     /// Creates a new L2 block
-    pub fn new(block_number: u64, parent_block_hash: String, sequencer_address: String, transactions: Vec<Transaction>) -> Self {
+    pub fn new(block_number: u64, parent_block_hash: String, sequencer_address: String, transactions: Vec<Transaction>, receipts: Vec<TransactionReceipt>, gas_prices: GasPrices) -> Self {
         let block_timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
@@ -41,7 +209,12 @@ impl L2Block {
 
         let transaction_count = transactions.len();
         let transaction_commitment = Self::calculate_commitment(&transactions);
-        let state_root = "placeholder_state_root".to_string(); // Placeholder for now
+        let message_commitment = Self::calculate_message_commitment(&receipts);
+        let event_count = receipts.iter().map(|r| r.events.len()).sum();
+        let event_commitment = Self::calculate_event_commitment(&receipts);
+        let receipt_commitment = Self::calculate_receipt_commitment(&receipts);
+        let state_root = "placeholder_state_root".to_string(); // Filled in by the sequencer once the state is known
+        let l2_gas_used = receipts.iter().map(|r| r.gas_consumed.l2_gas).sum();
 
         let header = BlockHeader {
             block_number,
@@ -50,32 +223,177 @@ impl L2Block {
             block_timestamp,
             transaction_count,
             transaction_commitment,
+            message_commitment,
+            event_count,
+            event_commitment,
+            receipt_commitment,
+            parent_state_root: state_root.clone(),
             state_root,
-            block_status: BlockStatus::AcceptedOnL2,
+            block_status: BlockStatus::Pending,
+            l1_gas_price: gas_prices.l1_gas_price,
+            l1_data_gas_price: gas_prices.l1_data_gas_price,
+            l2_gas_price: gas_prices.l2_gas_price,
+            l2_gas_used,
+            state_diff_length: 0,
         };
 
-        L2Block { header, transactions }
+        L2Block {
+            header,
+            transactions,
+            receipts,
+            state_diff: StateDiff::default(),
+            da_encoding: vec![],
+            l1_to_l2_messages: vec![],
+            proof: None,
+        }
+    }
+
+    /// Moves the block to its next status, refusing any change the pipeline does not allow
+    pub fn transition(&mut self, next: BlockStatus) -> Result<(), InvalidTransition> {
+        if !self.header.block_status.can_transition_to(&next) {
+            return Err(InvalidTransition {
+                block_number: self.header.block_number,
+                from: self.header.block_status.clone(),
+                to: next,
+            });
+        }
+        self.header.block_status = next;
+        Ok(())
+    }
+
+    /// Attaches the block's state diff together with its DA encoding
+    pub fn set_state_diff(&mut self, state_diff: StateDiff) {
+        self.da_encoding = da::encode_state_diff(&state_diff);
+        self.header.state_diff_length = self.da_encoding.len();
+        self.state_diff = state_diff;
+    }
+
+    /// Gas prices the block was built at
+    pub fn gas_prices(&self) -> GasPrices {
+        GasPrices {
+            l1_gas_price: self.header.l1_gas_price,
+            l1_data_gas_price: self.header.l1_data_gas_price,
+            l2_gas_price: self.header.l2_gas_price,
+        }
+    }
+
+    /// Size of the block's DA data in bytes
+    pub fn da_size_bytes(&self) -> usize {
+        self.header.state_diff_length * da::BYTES_PER_FELT
     }
 
     /// Generates a commitment hash of all transactions in the block
     fn calculate_commitment(transactions: &[Transaction]) -> String {
         let mut hasher = Sha256::new();
         for tx in transactions {
-            let tx_data = format!("{}-{}-{:?}", tx.id, tx.sender, tx.status);
+            let tx_data = format!("{}-{:?}", tx.get_hash(), tx.status);
             hasher.update(tx_data);
         }
         let result = hasher.finalize();
         format!("{:x}", result)
     }
 
+    /// Generates a commitment hash of the L2→L1 messages, in the order they were sent
+    fn calculate_message_commitment(receipts: &[TransactionReceipt]) -> String {
+        let mut hasher = Sha256::new();
+        for message in receipts.iter().flat_map(|r| r.messages_sent.iter()) {
+            hasher.update(message.hash());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Generates a commitment hash of the events, in the order they were emitted
+    fn calculate_event_commitment(receipts: &[TransactionReceipt]) -> String {
+        let mut hasher = Sha256::new();
+        for receipt in receipts {
+            for event in &receipt.events {
+                hasher.update(format!("{}-{}", receipt.transaction_hash, event.hash()));
+            }
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Generates a commitment hash of the receipts: what each transaction ended up paying,
+    /// how it ended and why it reverted
+    fn calculate_receipt_commitment(receipts: &[TransactionReceipt]) -> String {
+        let mut hasher = Sha256::new();
+        for receipt in receipts {
+            hasher.update(format!(
+                "{}-{:?}-{}-{}-{}-{}-{}-{}-{}",
+                receipt.transaction_hash,
+                receipt.status,
+                receipt.actual_fee,
+                receipt.tip,
+                receipt.fee_payer,
+                receipt.gas_consumed.l1_gas,
+                receipt.gas_consumed.l1_data_gas,
+                receipt.gas_consumed.l2_gas,
+                receipt.revert_reason.as_deref().unwrap_or_default()
+            ));
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// L2→L1 messages sent by the block's transactions
+    pub fn l2_to_l1_messages(&self) -> Vec<L2ToL1Message> {
+        self.receipts
+            .iter()
+            .flat_map(|r| r.messages_sent.iter().cloned())
+            .collect()
+    }
+
+    /// Events emitted by the block's transactions
+    pub fn events(&self) -> Vec<Event> {
+        self.receipts
+            .iter()
+            .flat_map(|r| r.events.iter().cloned())
+            .collect()
+    }
+
+    /// Recomputes the commitments in the header from the block body, so a block edited
+    /// after it was sealed no longer matches its own header
+    pub fn verify_integrity(&self) -> Result<(), String> {
+        if self.header.transaction_count != self.transactions.len()
+            || self.header.transaction_commitment != Self::calculate_commitment(&self.transactions)
+        {
+            return Err(format!("transactions of block #{} do not match its header", self.header.block_number));
+        }
+        if self.header.message_commitment != Self::calculate_message_commitment(&self.receipts) {
+            return Err(format!("L2→L1 messages of block #{} do not match its header", self.header.block_number));
+        }
+        if self.header.event_count != self.receipts.iter().map(|r| r.events.len()).sum::<usize>()
+            || self.header.event_commitment != Self::calculate_event_commitment(&self.receipts)
+        {
+            return Err(format!("events of block #{} do not match its header", self.header.block_number));
+        }
+        if self.header.receipt_commitment != Self::calculate_receipt_commitment(&self.receipts) {
+            return Err(format!("receipts of block #{} do not match its header", self.header.block_number));
+        }
+        let da_encoding = da::encode_state_diff(&self.state_diff);
+        if self.da_encoding != da_encoding || self.header.state_diff_length != da_encoding.len() {
+            return Err(format!("state diff of block #{} does not match its DA encoding", self.header.block_number));
+        }
+        Ok(())
+    }
+
     /// Generates a unique block hash
     pub fn get_block_hash(&self) -> String {
         let input = format!(
-            "{}-{}-{}-{}",
+            "{}-{}-{}-{}-{}-{}-{}-{}-{}-{}-{}-{}-{}-{}",
             self.header.block_number,
+            self.header.block_timestamp,
             self.header.transaction_commitment,
+            self.header.message_commitment,
+            self.header.event_commitment,
+            self.header.receipt_commitment,
             self.header.sequencer_address,
-            self.header.parent_block_hash
+            self.header.parent_block_hash,
+            self.header.parent_state_root,
+            self.header.state_root,
+            self.header.l1_gas_price,
+            self.header.l1_data_gas_price,
+            self.header.l2_gas_price,
+            self.header.state_diff_length
         );
         let mut hasher = Sha256::new();
         hasher.update(input);
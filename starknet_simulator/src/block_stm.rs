@@ -0,0 +1,382 @@
+//block-stm
+//optimistic parallel execution: transactions run concurrently against a multi-version store and are re-executed when something they read changes
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use crate::execution::{self, BaseState, StateView};
+use crate::gas::{self, ExecutionResources, GasPrices, ResourceMeter};
+use crate::transaction::{Transaction, TransactionPhases, TransactionStatus, TransactionType};
+use crate::utils::U256;
+
+/// A piece of state a transaction can read or write
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum StateKey {
+    Balance(String),
+    Nonce(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateValue {
+    Balance(U256),
+    Nonce(u64),
+}
+
+/// The write a read saw: `(transaction index, incarnation)`, or `None` for the state before the block
+pub type Version = Option<(usize, usize)>;
+
+fn base_value(base: &BaseState, key: &StateKey) -> Option<StateValue> {
+    match key {
+        StateKey::Balance(account) => base.balances.get(account).copied().map(StateValue::Balance),
+        StateKey::Nonce(account) => base.nonces.get(account).copied().map(StateValue::Nonce),
+    }
+}
+
+/// Every write of the latest incarnation of every transaction, by key and transaction index
+#[derive(Debug, Default)]
+struct MultiVersionMemory {
+    data: HashMap<StateKey, BTreeMap<usize, (usize, StateValue)>>, // key -> tx index -> (incarnation, value)
+}
+
+impl MultiVersionMemory {
+    /// The latest write to `key` by a transaction before `tx_index`
+    fn read(&self, key: &StateKey, tx_index: usize) -> Option<((usize, usize), StateValue)> {
+        let (index, (incarnation, value)) = self.data.get(key)?.range(..tx_index).next_back()?;
+        Some(((*index, *incarnation), *value))
+    }
+
+    /// Replaces the writes of a transaction's previous incarnation with those of its new one
+    fn record(&mut self, tx_index: usize, incarnation: usize, old_writes: Option<&BTreeMap<StateKey, StateValue>>, writes: &BTreeMap<StateKey, StateValue>) {
+        for key in old_writes.into_iter().flat_map(|w| w.keys()).filter(|key| !writes.contains_key(*key)) {
+            if let Some(versions) = self.data.get_mut(key) {
+                versions.remove(&tx_index);
+            }
+        }
+        for (key, value) in writes {
+            self.data.entry(key.clone()).or_default().insert(tx_index, (incarnation, *value));
+        }
+    }
+
+    /// Whether every read would still see the same write
+    fn validate(&self, tx_index: usize, reads: &BTreeMap<StateKey, Version>) -> bool {
+        reads
+            .iter()
+            .all(|(key, version)| self.read(key, tx_index).map(|(version, _)| version) == *version)
+    }
+}
+
+/// What one transaction sees while it runs: its own writes first, then the latest write of an
+/// earlier transaction, then the state before the block. Every read and write is recorded.
+pub struct TxView<'a> {
+    tx_index: usize,
+    memory: &'a MultiVersionMemory,
+    base: BaseState<'a>,
+    reads: BTreeMap<StateKey, Version>,
+    writes: BTreeMap<StateKey, StateValue>,
+}
+
+impl<'a> TxView<'a> {
+    fn new(tx_index: usize, memory: &'a MultiVersionMemory, base: BaseState<'a>) -> Self {
+        TxView { tx_index, memory, base, reads: BTreeMap::new(), writes: BTreeMap::new() }
+    }
+
+    fn read(&mut self, key: StateKey) -> Option<StateValue> {
+        if let Some(value) = self.writes.get(&key) {
+            return Some(*value);
+        }
+        let (version, value) = match self.memory.read(&key, self.tx_index) {
+            Some((version, value)) => (Some(version), Some(value)),
+            None => (None, base_value(&self.base, &key)),
+        };
+        self.reads.entry(key).or_insert(version);
+        value
+    }
+
+    /// Balance of an account, `None` if it has never held one
+    pub fn balance(&mut self, account: &str) -> Option<U256> {
+        match self.read(StateKey::Balance(account.to_string())) {
+            Some(StateValue::Balance(balance)) => Some(balance),
+            _ => None,
+        }
+    }
+
+    pub fn nonce(&mut self, account: &str) -> u64 {
+        match self.read(StateKey::Nonce(account.to_string())) {
+            Some(StateValue::Nonce(nonce)) => nonce,
+            _ => 0,
+        }
+    }
+
+    pub fn set_balance(&mut self, account: &str, balance: U256) {
+        self.writes.insert(StateKey::Balance(account.to_string()), StateValue::Balance(balance));
+    }
+
+    pub fn set_nonce(&mut self, account: &str, nonce: u64) {
+        self.writes.insert(StateKey::Nonce(account.to_string()), StateValue::Nonce(nonce));
+    }
+
+    /// Copies the balances of `accounts` into a map the `state` helpers can work on
+    pub fn load_balances(&mut self, accounts: &[&str]) -> HashMap<String, U256> {
+        accounts
+            .iter()
+            .filter_map(|account| self.balance(account).map(|balance| (account.to_string(), balance)))
+            .collect()
+    }
+
+    /// Writes back every balance of a map from `load_balances`
+    pub fn store_balances(&mut self, balances: &HashMap<String, U256>) {
+        for (account, balance) in balances {
+            self.set_balance(account, *balance);
+        }
+    }
+}
+
+impl StateView for TxView<'_> {
+    fn balance(&mut self, account: &str) -> U256 {
+        TxView::balance(self, account).unwrap_or_default()
+    }
+
+    fn nonce(&mut self, account: &str) -> u64 {
+        TxView::nonce(self, account)
+    }
+}
+
+/// Final result of one transaction: what it returned and what it wrote
+#[derive(Debug, Clone)]
+pub struct TxResult<O> {
+    pub output: O,
+    pub writes: BTreeMap<StateKey, StateValue>,
+    pub incarnations: usize, // how many times it ran
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockStmOutput<O> {
+    pub results: Vec<TxResult<O>>, // in block order
+    pub executions: usize,
+    pub waves: usize, // parallel rounds; `execute_sequential` runs none
+}
+
+impl<O> BlockStmOutput<O> {
+    /// Executions beyond the first one of each transaction
+    pub fn re_executions(&self) -> usize {
+        self.executions - self.results.len()
+    }
+}
+
+struct Execution<O> {
+    incarnation: usize,
+    reads: BTreeMap<StateKey, Version>,
+    writes: BTreeMap<StateKey, StateValue>,
+    output: O,
+}
+
+/// Runs the transactions one after the other, in block order. Each one runs exactly once.
+pub fn execute_sequential<T, O, F>(txs: &[T], base: BaseState, execute: F) -> BlockStmOutput<O>
+where
+    F: Fn(&T, &mut TxView) -> O,
+{
+    let mut memory = MultiVersionMemory::default();
+    let mut results = Vec::with_capacity(txs.len());
+    for (tx_index, tx) in txs.iter().enumerate() {
+        let mut view = TxView::new(tx_index, &memory, base);
+        let output = execute(tx, &mut view);
+        let writes = view.writes;
+        memory.record(tx_index, 0, None, &writes);
+        results.push(TxResult { output, writes, incarnations: 1 });
+    }
+    BlockStmOutput { results, executions: txs.len(), waves: 0 }
+}
+
+/// Runs the transactions optimistically on `workers` threads, with the same result as
+/// `execute_sequential`.
+///
+/// Execution goes in waves. In each wave the transactions that need to (re-)run execute in
+/// parallel against the writes of earlier transactions as they stood when the wave started,
+/// recording what they read. Their writes then replace those of their previous incarnation,
+/// and every transaction is validated: a read that would now see a different write is a
+/// conflict, and the transaction runs again. The transactions from the start of the block up
+/// to the first conflict are final. The first conflicting transaction always runs in the next
+/// wave, since everything before it is final; a later one waits while an earlier transaction
+/// that touched something it read is still due to re-run, so a chain of dependent transactions
+/// is not re-executed over and over. Once too few transactions are left to run in parallel, the
+/// rest of the block is finished in order, re-running only the transactions that read stale state.
+pub fn execute_parallel<T, O, F>(txs: &[T], base: BaseState, workers: usize, execute: F) -> BlockStmOutput<O>
+where
+    T: Sync,
+    O: Send,
+    F: Fn(&T, &mut TxView) -> O + Sync,
+{
+    let mut memory = MultiVersionMemory::default();
+    let mut executions: Vec<Option<Execution<O>>> = txs.iter().map(|_| None).collect();
+    let mut to_run: Vec<usize> = (0..txs.len()).collect();
+    let mut finalized = 0;
+    let (mut execution_count, mut waves) = (0, 0);
+
+    let mut record = |memory: &mut MultiVersionMemory, executions: &mut [Option<Execution<O>>], (tx_index, reads, writes, output): WaveResult<O>| {
+        let previous = executions[tx_index].take();
+        let incarnation = previous.as_ref().map_or(0, |e| e.incarnation + 1);
+        memory.record(tx_index, incarnation, previous.as_ref().map(|e| &e.writes), &writes);
+        executions[tx_index] = Some(Execution { incarnation, reads, writes, output });
+        execution_count += 1;
+    };
+    let is_valid = |memory: &MultiVersionMemory, executions: &[Option<Execution<O>>], tx_index: usize| {
+        executions[tx_index]
+            .as_ref()
+            .is_some_and(|e| memory.validate(tx_index, &e.reads))
+    };
+
+    while !to_run.is_empty() {
+        waves += 1;
+        for result in run_wave(txs, &to_run, &memory, base, workers, &execute) {
+            record(&mut memory, &mut executions, result);
+        }
+        while finalized < txs.len() && is_valid(&memory, &executions, finalized) {
+            finalized += 1;
+        }
+
+        // Keys an invalid transaction touched may be written once it re-runs
+        let mut unsettled_keys = BTreeSet::new();
+        to_run.clear();
+        for tx_index in (finalized..txs.len()).filter(|&i| !is_valid(&memory, &executions, i)) {
+            let execution = executions[tx_index].as_ref().expect("every transaction ran in the first wave");
+            if tx_index == finalized || !execution.reads.keys().any(|key| unsettled_keys.contains(key)) {
+                to_run.push(tx_index);
+            }
+            unsettled_keys.extend(execution.reads.keys().chain(execution.writes.keys()).cloned());
+        }
+        if to_run.len() < 2 {
+            break;
+        }
+    }
+
+    // Everything before `finalized` is final, so each stale transaction re-run here sees final state
+    while finalized < txs.len() {
+        if !is_valid(&memory, &executions, finalized) {
+            let result = run_wave(txs, &[finalized], &memory, base, 1, &execute).remove(0);
+            record(&mut memory, &mut executions, result);
+        }
+        finalized += 1;
+    }
+
+    let results = executions
+        .into_iter()
+        .map(|execution| {
+            let execution = execution.expect("every transaction ran");
+            TxResult { output: execution.output, writes: execution.writes, incarnations: execution.incarnation + 1 }
+        })
+        .collect();
+    BlockStmOutput { results, executions: execution_count, waves }
+}
+
+type WaveResult<O> = (usize, BTreeMap<StateKey, Version>, BTreeMap<StateKey, StateValue>, O);
+
+/// Executes one wave: workers take the next transaction to run until none are left
+fn run_wave<T, O, F>(txs: &[T], to_run: &[usize], memory: &MultiVersionMemory, base: BaseState, workers: usize, execute: &F) -> Vec<WaveResult<O>>
+where
+    T: Sync,
+    O: Send,
+    F: Fn(&T, &mut TxView) -> O + Sync,
+{
+    let run = |tx_index: usize| {
+        let mut view = TxView::new(tx_index, memory, base);
+        let output = execute(&txs[tx_index], &mut view);
+        (tx_index, view.reads, view.writes, output)
+    };
+    let workers = workers.clamp(1, to_run.len().max(1));
+    if workers == 1 {
+        return to_run.iter().map(|&tx_index| run(tx_index)).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+                    while let Some(&tx_index) = to_run.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        done.push(run(tx_index));
+                    }
+                    done
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Block-STM worker panicked"))
+            .collect()
+    })
+}
+
+/// What happened to a plain fee-token transfer
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransferOutcome {
+    /// Refused by mempool validation. The sequencer stops filling the block, as it does when
+    /// the mempool rejects a transaction; `remember` when its hash may not be resent.
+    Rejected { reason: String, remember: bool },
+    /// Ran and was charged. `tip` is still owed to the sequencer.
+    Executed {
+        status: TransactionStatus,
+        revert_reason: Option<String>,
+        resources: ExecutionResources,
+        actual_fee: U256,
+        tip: U256,
+        phases: TransactionPhases,
+    },
+}
+
+/// Whether a transaction is a plain transfer, paid for by its sender, that the parallel executor can run
+pub fn is_plain_transfer(tx: &Transaction) -> bool {
+    tx.tx_type == TransactionType::Invoke
+        && tx.entry_point.is_none()
+        && tx.amount.is_some()
+        && tx.receiver.is_some()
+        && tx.paymaster.is_none()
+}
+
+/// Runs a plain transfer the way the mempool and the sequencer would one after the other:
+/// mempool validation, then `__validate__`, `__execute__` and the fee transfer, through the
+/// same `execution` steps. Crediting the tip to the sequencer is left to the caller, so
+/// transfers do not all conflict on the sequencer's balance.
+pub fn execute_transfer(tx: &Transaction, view: &mut TxView, gas_prices: &GasPrices) -> TransferOutcome {
+    let receiver = tx.receiver.as_deref().unwrap_or_default();
+
+    // Mempool validation
+    if let Err(rejection) = execution::check_admission(tx, view, gas_prices) {
+        return TransferOutcome::Rejected { reason: rejection.reason, remember: rejection.remember };
+    }
+    let nonce = TxView::nonce(view, &tx.sender);
+    view.set_nonce(&tx.sender, nonce + 1);
+
+    // __validate__, then __execute__ on a copy of the balances
+    let mut validated = view.load_balances(&[&tx.sender, receiver]);
+    let mut meter = ResourceMeter::new();
+    gas::meter_validate(&mut meter, &tx.sender);
+    let mut executed = validated.clone();
+    let body = tx
+        .check_gas_limit(&meter.resources().to_gas(), gas_prices)
+        .and_then(|()| execution::run_transfer(tx, &mut executed, &mut meter, gas_prices));
+
+    // Fee transfer
+    let resources = meter.finish();
+    let charge = tx.chargeable_fee(&resources.to_gas(), gas_prices);
+    let fee = execution::transfer_fee(body, &mut executed, &mut validated, &tx.sender, charge);
+    let status = match fee.revert_reason {
+        None => {
+            view.store_balances(&executed);
+            TransactionStatus::Succeeded
+        }
+        Some(_) => {
+            view.store_balances(&validated);
+            TransactionStatus::Reverted
+        }
+    };
+
+    TransferOutcome::Executed {
+        status,
+        revert_reason: fee.revert_reason,
+        resources,
+        actual_fee: fee.charge.total(),
+        tip: fee.charge.tip,
+        phases: fee.phases,
+    }
+}
@@ -0,0 +1,309 @@
+//StarkGate-style token bridge
+//ETH locked in the L1 bridge is minted as the L2 fee token; L2 tokens burned by a withdrawal are released on L1 once the withdrawal is final
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use crate::contract::{parse_felt, Contract, EntryPointType, ExecutionContext};
+use crate::l1::{L1Error, L1ToL2Message, StarknetCoreContract};
+use crate::state::{self, StateError};
+use crate::utils::U256;
+
+/// Address of the bridge contract on L1
+pub const L1_BRIDGE_ADDRESS: &str = "l1_starkgate_bridge";
+/// Address of the bridge contract on L2
+pub const L2_BRIDGE_ADDRESS: &str = "starkgate_bridge";
+
+const TOTAL_MINTED: &str = "total_minted";
+const TOTAL_BURNED: &str = "total_burned";
+
+/// Errors raised by the L1 side of the bridge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BridgeError {
+    ZeroAmount,
+    Balance(StateError), // the L1 account cannot pay
+    L1(L1Error),         // the withdrawal message is not consumable, or the deposit message not cancellable
+    NotDepositor { message_hash: String }, // only the account that made a deposit can take it back
+}
+
+impl fmt::Display for BridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BridgeError::ZeroAmount => write!(f, "amount must be greater than zero"),
+            BridgeError::Balance(err) => write!(f, "{}", err),
+            BridgeError::L1(err) => write!(f, "{}", err),
+            BridgeError::NotDepositor { message_hash } => {
+                write!(f, "deposit {} was not made by this account", message_hash)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BridgeError {}
+
+/// L1 side of the bridge. It holds the locked ETH and talks to L2 through the core contract.
+pub struct L1Bridge {
+    pub core_contract: Arc<Mutex<StarknetCoreContract>>,
+    pub l1_balances: HashMap<String, U256>, // ETH held by L1 accounts
+    pub locked: U256, // ETH held by the bridge, backing the bridged supply on L2
+    pub total_deposited: U256,
+    pub total_withdrawn: U256,
+    pub total_reclaimed: U256, // deposits cancelled before L2 handled them, returned to their senders
+    pub deposits: HashMap<String, (String, U256)>, // deposit message hash -> L1 sender and amount, until it is reclaimed
+}
+
+impl L1Bridge {
+    pub fn new(core_contract: Arc<Mutex<StarknetCoreContract>>, l1_balances: HashMap<String, U256>) -> Self {
+        L1Bridge {
+            core_contract,
+            l1_balances,
+            locked: U256::ZERO,
+            total_deposited: U256::ZERO,
+            total_withdrawn: U256::ZERO,
+            total_reclaimed: U256::ZERO,
+            deposits: HashMap::new(),
+        }
+    }
+
+    /// `deposit`: locks `amount` ETH and asks L2 to mint it to `l2_recipient`.
+    /// The sender also pays `message_fee` for the L1 handler transaction.
+    pub fn deposit(&mut self, l1_sender: &str, l2_recipient: &str, amount: U256, message_fee: U256) -> Result<L1ToL2Message, BridgeError> {
+        if amount.is_zero() {
+            return Err(BridgeError::ZeroAmount);
+        }
+        let locked = self.locked.checked_add(amount).ok_or_else(bridge_overflow)?;
+        let total_deposited = self.total_deposited.checked_add(amount).ok_or_else(bridge_overflow)?;
+        let total = amount.checked_add(message_fee).ok_or(BridgeError::Balance(StateError::BalanceOverflow {
+            account: l1_sender.to_string(),
+        }))?;
+        state::debit(&mut self.l1_balances, l1_sender, total).map_err(BridgeError::Balance)?;
+        self.locked = locked;
+        self.total_deposited = total_deposited;
+
+        let message = self.core_contract.lock().unwrap().send_message_to_l2(
+            L1_BRIDGE_ADDRESS,
+            L2_BRIDGE_ADDRESS,
+            "handle_deposit",
+            vec![l2_recipient.to_string(), amount.to_string()],
+            message_fee,
+        );
+        self.deposits.insert(message.hash(), (l1_sender.to_string(), amount));
+        println!(
+            "[L1 Bridge] 🔒 Locked {} ETH from {} for {} on L2 (message #{})",
+            amount, l1_sender, l2_recipient, message.nonce
+        );
+        Ok(message)
+    }
+
+    /// `withdraw`: releases ETH for a withdrawal whose L2 block was accepted on L1.
+    /// Each withdrawal message can be claimed once.
+    pub fn withdraw(&mut self, l1_recipient: &str, amount: U256) -> Result<(), BridgeError> {
+        let (locked, recipient_balance) = self.release(l1_recipient, amount)?;
+        let total_withdrawn = self.total_withdrawn.checked_add(amount).ok_or_else(bridge_overflow)?;
+        let payload = vec![l1_recipient.to_string(), amount.to_string()];
+        self.core_contract
+            .lock()
+            .unwrap()
+            .consume_message_from_l2(L1_BRIDGE_ADDRESS, L2_BRIDGE_ADDRESS, &payload)
+            .map_err(BridgeError::L1)?;
+
+        self.locked = locked;
+        self.total_withdrawn = total_withdrawn;
+        self.l1_balances.insert(l1_recipient.to_string(), recipient_balance);
+        println!("[L1 Bridge] 🔓 Released {} ETH to {}", amount, l1_recipient);
+        Ok(())
+    }
+
+    /// `depositCancelRequest`: the sender of a deposit that L2 has not handled asks for it
+    /// back. L2 can still handle it until the core contract's cancellation delay has passed.
+    pub fn start_deposit_cancellation(&mut self, l1_sender: &str, message: &L1ToL2Message) -> Result<(), BridgeError> {
+        self.deposited_amount(l1_sender, message)?;
+        self.core_contract
+            .lock()
+            .unwrap()
+            .start_l1_to_l2_message_cancellation(message, L1_BRIDGE_ADDRESS)
+            .map_err(BridgeError::L1)?;
+        println!("[L1 Bridge] ⏳ {} asked for deposit message #{} back", l1_sender, message.nonce);
+        Ok(())
+    }
+
+    /// `depositReclaim`: once the cancellation delay has passed, cancels the deposit message
+    /// and returns the locked ETH to its sender. The message fee is not refunded.
+    pub fn reclaim_deposit(&mut self, l1_sender: &str, message: &L1ToL2Message) -> Result<(), BridgeError> {
+        let amount = self.deposited_amount(l1_sender, message)?;
+        let (locked, sender_balance) = self.release(l1_sender, amount)?;
+        let total_reclaimed = self.total_reclaimed.checked_add(amount).ok_or_else(bridge_overflow)?;
+        self.core_contract
+            .lock()
+            .unwrap()
+            .cancel_l1_to_l2_message(message, L1_BRIDGE_ADDRESS)
+            .map_err(BridgeError::L1)?;
+
+        self.locked = locked;
+        self.total_reclaimed = total_reclaimed;
+        self.l1_balances.insert(l1_sender.to_string(), sender_balance);
+        self.deposits.remove(&message.hash());
+        println!("[L1 Bridge] ↩️ Returned {} ETH of deposit message #{} to {}", amount, message.nonce, l1_sender);
+        Ok(())
+    }
+
+    /// Amount of the deposit sent by `message`, if `l1_sender` made it
+    fn deposited_amount(&self, l1_sender: &str, message: &L1ToL2Message) -> Result<U256, BridgeError> {
+        let message_hash = message.hash();
+        match self.deposits.get(&message_hash) {
+            Some((depositor, amount)) if depositor == l1_sender => Ok(*amount),
+            _ => Err(BridgeError::NotDepositor { message_hash }),
+        }
+    }
+
+    /// The locked ETH and the balance of `l1_recipient` after releasing `amount` to it.
+    /// Nothing is written, so the caller can still fail before applying them.
+    fn release(&self, l1_recipient: &str, amount: U256) -> Result<(U256, U256), BridgeError> {
+        let locked = self.locked.checked_sub(amount).ok_or_else(|| {
+            BridgeError::Balance(StateError::InsufficientBalance {
+                account: L1_BRIDGE_ADDRESS.to_string(),
+                balance: self.locked,
+                required: amount,
+            })
+        })?;
+        let recipient_balance = state::get_balance(&self.l1_balances, l1_recipient)
+            .checked_add(amount)
+            .ok_or_else(|| BridgeError::Balance(StateError::BalanceOverflow { account: l1_recipient.to_string() }))?;
+        Ok((locked, recipient_balance))
+    }
+}
+
+/// L2 side of the bridge: mints on deposits from L1 and burns on withdrawals.
+/// It never burns more than it minted, so every withdrawal is backed on L1.
+pub struct L2Bridge;
+
+impl Contract for L2Bridge {
+    fn class_hash(&self) -> String {
+        "starkgate_bridge_class".to_string()
+    }
+
+    fn entry_point_type(&self, selector: &str) -> Option<EntryPointType> {
+        match selector {
+            "handle_deposit" => Some(EntryPointType::L1Handler),
+            "initiate_withdraw" => Some(EntryPointType::External),
+            _ => None,
+        }
+    }
+
+    fn execute(&self, selector: &str, calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+        match selector {
+            "handle_deposit" => {
+                // calldata: [from_address, l2_recipient, amount]
+                if parse_address(calldata, 0)? != L1_BRIDGE_ADDRESS {
+                    return Err("deposit was not sent by the L1 bridge".to_string());
+                }
+                let recipient = parse_address(calldata, 1)?;
+                let amount = parse_felt(calldata, 2)?;
+
+                let minted = ctx.storage_read(TOTAL_MINTED).checked_add(amount).ok_or("total minted overflows")?;
+                ctx.mint(&recipient, amount)?;
+                ctx.storage_write(TOTAL_MINTED, minted);
+                ctx.emit_event(vec!["DepositHandled".to_string(), recipient], vec![amount.to_string()]);
+                Ok(vec![])
+            }
+            "initiate_withdraw" => {
+                // calldata: [l1_recipient, amount]
+                let l1_recipient = parse_address(calldata, 0)?;
+                let amount = parse_felt(calldata, 1)?;
+                if amount.is_zero() {
+                    return Err("withdrawal amount must be greater than zero".to_string());
+                }
+
+                let burned = ctx.storage_read(TOTAL_BURNED).checked_add(amount).ok_or("total burned overflows")?;
+                if burned > ctx.storage_read(TOTAL_MINTED) {
+                    return Err("withdrawal exceeds the bridged supply".to_string());
+                }
+                let caller = ctx.caller_address.clone();
+                ctx.burn(&caller, amount)?;
+                ctx.storage_write(TOTAL_BURNED, burned);
+                ctx.send_message_to_l1(L1_BRIDGE_ADDRESS, vec![l1_recipient.clone(), amount.to_string()]);
+                ctx.emit_event(vec!["WithdrawInitiated".to_string(), l1_recipient, caller], vec![amount.to_string()]);
+                Ok(vec![])
+            }
+            _ => Err(format!("unknown entry point {}", selector)),
+        }
+    }
+}
+
+fn bridge_overflow() -> BridgeError {
+    BridgeError::Balance(StateError::BalanceOverflow { account: L1_BRIDGE_ADDRESS.to_string() })
+}
+
+fn parse_address(calldata: &[String], index: usize) -> Result<String, String> {
+    calldata
+        .get(index)
+        .cloned()
+        .ok_or_else(|| format!("missing calldata argument {}", index))
+}
+
+/// Bridged supply on both layers. Every ETH locked on L1 is either circulating on
+/// L2 or in flight between the layers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BridgeLedger {
+    pub l1_locked: U256,
+    pub l1_total_deposited: U256,
+    pub l1_total_withdrawn: U256,
+    pub l1_total_reclaimed: U256,
+    pub l2_total_minted: U256,
+    pub l2_total_burned: U256,
+}
+
+impl BridgeLedger {
+    /// Reads both sides of the bridge; `l2_storage` is the L2 contract storage
+    pub fn new(l1_bridge: &L1Bridge, l2_storage: &HashMap<String, HashMap<String, U256>>) -> Self {
+        let l2_slot = |key: &str| {
+            l2_storage
+                .get(L2_BRIDGE_ADDRESS)
+                .and_then(|slots| slots.get(key))
+                .copied()
+                .unwrap_or_default()
+        };
+        BridgeLedger {
+            l1_locked: l1_bridge.locked,
+            l1_total_deposited: l1_bridge.total_deposited,
+            l1_total_withdrawn: l1_bridge.total_withdrawn,
+            l1_total_reclaimed: l1_bridge.total_reclaimed,
+            l2_total_minted: l2_slot(TOTAL_MINTED),
+            l2_total_burned: l2_slot(TOTAL_BURNED),
+        }
+    }
+
+    /// Bridged tokens circulating on L2
+    pub fn l2_bridged_supply(&self) -> U256 {
+        self.l2_total_minted.saturating_sub(self.l2_total_burned)
+    }
+
+    /// Deposits that were not reclaimed, so L2 mints them once it handles them
+    pub fn l1_deposits_kept(&self) -> U256 {
+        self.l1_total_deposited.saturating_sub(self.l1_total_reclaimed)
+    }
+
+    /// Locked on L1 but not minted on L2 yet
+    pub fn deposits_in_flight(&self) -> U256 {
+        self.l1_deposits_kept().saturating_sub(self.l2_total_minted)
+    }
+
+    /// Burned on L2 but not released on L1 yet
+    pub fn withdrawals_in_flight(&self) -> U256 {
+        self.l2_total_burned.saturating_sub(self.l1_total_withdrawn)
+    }
+
+    /// Checks that no layer created or lost bridged ETH
+    pub fn is_reconciled(&self) -> bool {
+        let accounted = self
+            .l2_bridged_supply()
+            .checked_add(self.deposits_in_flight())
+            .and_then(|sum| sum.checked_add(self.withdrawals_in_flight()));
+
+        self.l1_total_reclaimed <= self.l1_total_deposited
+            && self.l2_total_minted <= self.l1_deposits_kept()
+            && self.l2_total_burned <= self.l2_total_minted
+            && self.l1_total_withdrawn <= self.l2_total_burned
+            && accounted == Some(self.l1_locked)
+    }
+}
@@ -0,0 +1,593 @@
+//codec
+//turns blocks, transactions and state into the bytes a storage backend keeps, and back
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::str::FromStr;
+use crate::block::{BlockHeader, BlockStatus, L2Block};
+use crate::event::Event;
+use crate::gas::{Builtin, ExecutionResources, GasPrices, GasVector, ResourceBounds, ResourceBoundsMapping};
+use crate::l1::L2ToL1Message;
+use crate::state::{StateDiff, StateSnapshot};
+use crate::transaction::{PhaseOutcome, Transaction, TransactionPhases, TransactionReceipt, TransactionStatus, TransactionType};
+use crate::utils::U256;
+
+/// A value that can be stored. It is written as a flat list of fields, each prefixed
+/// with its length, so any text fits in a field.
+pub trait Codec: Sized {
+    fn encode(&self, out: &mut Encoder);
+    fn decode(input: &mut Decoder) -> Result<Self, String>;
+}
+
+pub fn to_bytes<T: Codec>(value: &T) -> Vec<u8> {
+    let mut out = Encoder::default();
+    value.encode(&mut out);
+    out.bytes
+}
+
+/// Decodes a value written by `to_bytes`, refusing anything left over after it
+pub fn from_bytes<T: Codec>(bytes: &[u8]) -> Result<T, String> {
+    let mut input = Decoder { bytes };
+    let value = T::decode(&mut input)?;
+    if !input.bytes.is_empty() {
+        return Err(format!("{} unexpected byte(s) after the value", input.bytes.len()));
+    }
+    Ok(value)
+}
+
+#[derive(Debug, Default)]
+pub struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    /// Writes one field as `<length>:<value>`
+    pub fn field(&mut self, value: &str) {
+        self.bytes.extend_from_slice(format!("{}:", value.len()).as_bytes());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+}
+
+#[derive(Debug)]
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    /// Reads the next field
+    pub fn field(&mut self) -> Result<&'a str, String> {
+        let colon = self.bytes.iter().position(|&b| b == b':').ok_or("missing field")?;
+        let len: usize = std::str::from_utf8(&self.bytes[..colon])
+            .ok()
+            .and_then(|len| len.parse().ok())
+            .ok_or("invalid field length")?;
+        let rest = &self.bytes[colon + 1..];
+        if rest.len() < len {
+            return Err("field is cut short".to_string());
+        }
+        self.bytes = &rest[len..];
+        std::str::from_utf8(&rest[..len]).map_err(|_| "field is not UTF-8".to_string())
+    }
+
+    /// Reads the next field and parses it
+    pub fn parse<T: FromStr>(&mut self) -> Result<T, String> {
+        let field = self.field()?;
+        field.parse().map_err(|_| format!("invalid value {}", field))
+    }
+}
+
+impl Codec for String {
+    fn encode(&self, out: &mut Encoder) {
+        out.field(self);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        Ok(input.field()?.to_string())
+    }
+}
+
+impl Codec for u64 {
+    fn encode(&self, out: &mut Encoder) {
+        out.field(&self.to_string());
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        input.parse()
+    }
+}
+
+impl Codec for usize {
+    fn encode(&self, out: &mut Encoder) {
+        out.field(&self.to_string());
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        input.parse()
+    }
+}
+
+impl Codec for U256 {
+    fn encode(&self, out: &mut Encoder) {
+        out.field(&self.to_string());
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        input.parse()
+    }
+}
+
+impl<T: Codec> Codec for Option<T> {
+    fn encode(&self, out: &mut Encoder) {
+        match self {
+            None => out.field("none"),
+            Some(value) => {
+                out.field("some");
+                value.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        match input.field()? {
+            "none" => Ok(None),
+            "some" => Ok(Some(T::decode(input)?)),
+            tag => Err(format!("invalid option tag {}", tag)),
+        }
+    }
+}
+
+impl<T: Codec> Codec for Vec<T> {
+    fn encode(&self, out: &mut Encoder) {
+        self.len().encode(out);
+        for item in self {
+            item.encode(out);
+        }
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        let len = usize::decode(input)?;
+        (0..len).map(|_| T::decode(input)).collect()
+    }
+}
+
+impl<K: Codec + Ord, V: Codec> Codec for BTreeMap<K, V> {
+    fn encode(&self, out: &mut Encoder) {
+        self.len().encode(out);
+        for (key, value) in self {
+            key.encode(out);
+            value.encode(out);
+        }
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        let len = usize::decode(input)?;
+        (0..len).map(|_| Ok((K::decode(input)?, V::decode(input)?))).collect()
+    }
+}
+
+impl<K: Codec + Eq + Hash, V: Codec> Codec for HashMap<K, V> {
+    fn encode(&self, out: &mut Encoder) {
+        self.len().encode(out);
+        for (key, value) in self {
+            key.encode(out);
+            value.encode(out);
+        }
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        let len = usize::decode(input)?;
+        (0..len).map(|_| Ok((K::decode(input)?, V::decode(input)?))).collect()
+    }
+}
+
+impl Codec for TransactionType {
+    fn encode(&self, out: &mut Encoder) {
+        out.field(&format!("{:?}", self));
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        match input.field()? {
+            "Declare" => Ok(TransactionType::Declare),
+            "Invoke" => Ok(TransactionType::Invoke),
+            "DeployAccount" => Ok(TransactionType::DeployAccount),
+            "L1Handler" => Ok(TransactionType::L1Handler),
+            other => Err(format!("invalid transaction type {}", other)),
+        }
+    }
+}
+
+impl Codec for TransactionStatus {
+    fn encode(&self, out: &mut Encoder) {
+        out.field(&format!("{:?}", self));
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        match input.field()? {
+            "Received" => Ok(TransactionStatus::Received),
+            "Rejected" => Ok(TransactionStatus::Rejected),
+            "Validated" => Ok(TransactionStatus::Validated),
+            "Executed" => Ok(TransactionStatus::Executed),
+            "Reverted" => Ok(TransactionStatus::Reverted),
+            "Succeeded" => Ok(TransactionStatus::Succeeded),
+            "ProofGenerated" => Ok(TransactionStatus::ProofGenerated),
+            "AcceptedOnL1" => Ok(TransactionStatus::AcceptedOnL1),
+            other => Err(format!("invalid transaction status {}", other)),
+        }
+    }
+}
+
+impl Codec for ResourceBounds {
+    fn encode(&self, out: &mut Encoder) {
+        self.max_amount.encode(out);
+        self.max_price_per_unit.encode(out);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        Ok(ResourceBounds {
+            max_amount: u64::decode(input)?,
+            max_price_per_unit: U256::decode(input)?,
+        })
+    }
+}
+
+impl Codec for ResourceBoundsMapping {
+    fn encode(&self, out: &mut Encoder) {
+        self.l1_gas.encode(out);
+        self.l1_data_gas.encode(out);
+        self.l2_gas.encode(out);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        Ok(ResourceBoundsMapping {
+            l1_gas: ResourceBounds::decode(input)?,
+            l1_data_gas: ResourceBounds::decode(input)?,
+            l2_gas: ResourceBounds::decode(input)?,
+        })
+    }
+}
+
+impl Codec for Transaction {
+    fn encode(&self, out: &mut Encoder) {
+        self.id.encode(out);
+        self.sender.encode(out);
+        self.receiver.encode(out);
+        self.contract_address.encode(out);
+        self.amount.encode(out);
+        self.entry_point.encode(out);
+        self.calldata.encode(out);
+        self.signature.encode(out);
+        self.fee.encode(out);
+        self.resource_bounds.encode(out);
+        self.tip.encode(out);
+        self.paymaster.encode(out);
+        self.nonce.encode(out);
+        self.tx_type.encode(out);
+        self.status.encode(out);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        Ok(Transaction {
+            id: usize::decode(input)?,
+            sender: String::decode(input)?,
+            receiver: Option::decode(input)?,
+            contract_address: Option::decode(input)?,
+            amount: Option::decode(input)?,
+            entry_point: Option::decode(input)?,
+            calldata: Vec::decode(input)?,
+            signature: Vec::decode(input)?,
+            fee: U256::decode(input)?,
+            resource_bounds: Option::decode(input)?,
+            tip: U256::decode(input)?,
+            paymaster: Option::decode(input)?,
+            nonce: u64::decode(input)?,
+            tx_type: TransactionType::decode(input)?,
+            status: TransactionStatus::decode(input)?,
+        })
+    }
+}
+
+impl Codec for Builtin {
+    fn encode(&self, out: &mut Encoder) {
+        out.field(&format!("{:?}", self));
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        match input.field()? {
+            "Pedersen" => Ok(Builtin::Pedersen),
+            "RangeCheck" => Ok(Builtin::RangeCheck),
+            "Ecdsa" => Ok(Builtin::Ecdsa),
+            "Poseidon" => Ok(Builtin::Poseidon),
+            "Bitwise" => Ok(Builtin::Bitwise),
+            other => Err(format!("invalid builtin {}", other)),
+        }
+    }
+}
+
+impl Codec for ExecutionResources {
+    fn encode(&self, out: &mut Encoder) {
+        self.steps.encode(out);
+        self.builtins.encode(out);
+        self.storage_writes.encode(out);
+        self.l1_data_felts.encode(out);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        Ok(ExecutionResources {
+            steps: u64::decode(input)?,
+            builtins: BTreeMap::decode(input)?,
+            storage_writes: u64::decode(input)?,
+            l1_data_felts: u64::decode(input)?,
+        })
+    }
+}
+
+impl Codec for GasVector {
+    fn encode(&self, out: &mut Encoder) {
+        self.l1_gas.encode(out);
+        self.l1_data_gas.encode(out);
+        self.l2_gas.encode(out);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        Ok(GasVector {
+            l1_gas: u64::decode(input)?,
+            l1_data_gas: u64::decode(input)?,
+            l2_gas: u64::decode(input)?,
+        })
+    }
+}
+
+impl Codec for GasPrices {
+    fn encode(&self, out: &mut Encoder) {
+        self.l1_gas_price.encode(out);
+        self.l1_data_gas_price.encode(out);
+        self.l2_gas_price.encode(out);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        Ok(GasPrices {
+            l1_gas_price: U256::decode(input)?,
+            l1_data_gas_price: U256::decode(input)?,
+            l2_gas_price: U256::decode(input)?,
+        })
+    }
+}
+
+impl Codec for L2ToL1Message {
+    fn encode(&self, out: &mut Encoder) {
+        self.from_address.encode(out);
+        self.to_address.encode(out);
+        self.payload.encode(out);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        Ok(L2ToL1Message {
+            from_address: String::decode(input)?,
+            to_address: String::decode(input)?,
+            payload: Vec::decode(input)?,
+        })
+    }
+}
+
+impl Codec for Event {
+    fn encode(&self, out: &mut Encoder) {
+        self.from_address.encode(out);
+        self.keys.encode(out);
+        self.data.encode(out);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        Ok(Event {
+            from_address: String::decode(input)?,
+            keys: Vec::decode(input)?,
+            data: Vec::decode(input)?,
+        })
+    }
+}
+
+impl Codec for PhaseOutcome {
+    fn encode(&self, out: &mut Encoder) {
+        match self {
+            PhaseOutcome::NotRun => out.field("NotRun"),
+            PhaseOutcome::Succeeded => out.field("Succeeded"),
+            PhaseOutcome::Reverted(reason) => {
+                out.field("Reverted");
+                reason.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        match input.field()? {
+            "NotRun" => Ok(PhaseOutcome::NotRun),
+            "Succeeded" => Ok(PhaseOutcome::Succeeded),
+            "Reverted" => Ok(PhaseOutcome::Reverted(String::decode(input)?)),
+            other => Err(format!("invalid phase outcome {}", other)),
+        }
+    }
+}
+
+impl Codec for TransactionPhases {
+    fn encode(&self, out: &mut Encoder) {
+        self.validate.encode(out);
+        self.execute.encode(out);
+        self.fee_transfer.encode(out);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        Ok(TransactionPhases {
+            validate: PhaseOutcome::decode(input)?,
+            execute: PhaseOutcome::decode(input)?,
+            fee_transfer: PhaseOutcome::decode(input)?,
+        })
+    }
+}
+
+impl Codec for TransactionReceipt {
+    fn encode(&self, out: &mut Encoder) {
+        self.transaction_hash.encode(out);
+        self.tx_id.encode(out);
+        self.status.encode(out);
+        self.resources.encode(out);
+        self.gas_consumed.encode(out);
+        self.actual_fee.encode(out);
+        self.tip.encode(out);
+        self.messages_sent.encode(out);
+        self.events.encode(out);
+        self.revert_reason.encode(out);
+        self.fee_payer.encode(out);
+        self.phases.encode(out);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        Ok(TransactionReceipt {
+            transaction_hash: String::decode(input)?,
+            tx_id: usize::decode(input)?,
+            status: TransactionStatus::decode(input)?,
+            resources: ExecutionResources::decode(input)?,
+            gas_consumed: GasVector::decode(input)?,
+            actual_fee: U256::decode(input)?,
+            tip: U256::decode(input)?,
+            messages_sent: Vec::decode(input)?,
+            events: Vec::decode(input)?,
+            revert_reason: Option::decode(input)?,
+            fee_payer: String::decode(input)?,
+            phases: TransactionPhases::decode(input)?,
+        })
+    }
+}
+
+impl Codec for BlockStatus {
+    fn encode(&self, out: &mut Encoder) {
+        match self {
+            BlockStatus::Rejected { reason } => {
+                out.field("Rejected");
+                reason.encode(out);
+            }
+            status => out.field(&format!("{:?}", status)),
+        }
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        match input.field()? {
+            "Pending" => Ok(BlockStatus::Pending),
+            "AcceptedOnL2" => Ok(BlockStatus::AcceptedOnL2),
+            "ProofRequested" => Ok(BlockStatus::ProofRequested),
+            "Proven" => Ok(BlockStatus::Proven),
+            "SubmittedToL1" => Ok(BlockStatus::SubmittedToL1),
+            "AcceptedOnL1" => Ok(BlockStatus::AcceptedOnL1),
+            "Rejected" => Ok(BlockStatus::Rejected { reason: String::decode(input)? }),
+            other => Err(format!("invalid block status {}", other)),
+        }
+    }
+}
+
+impl Codec for BlockHeader {
+    fn encode(&self, out: &mut Encoder) {
+        self.block_number.encode(out);
+        self.parent_block_hash.encode(out);
+        self.sequencer_address.encode(out);
+        self.block_timestamp.encode(out);
+        self.transaction_count.encode(out);
+        self.transaction_commitment.encode(out);
+        self.message_commitment.encode(out);
+        self.event_count.encode(out);
+        self.event_commitment.encode(out);
+        self.receipt_commitment.encode(out);
+        self.parent_state_root.encode(out);
+        self.state_root.encode(out);
+        self.block_status.encode(out);
+        self.l1_gas_price.encode(out);
+        self.l1_data_gas_price.encode(out);
+        self.l2_gas_price.encode(out);
+        self.l2_gas_used.encode(out);
+        self.state_diff_length.encode(out);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        Ok(BlockHeader {
+            block_number: u64::decode(input)?,
+            parent_block_hash: String::decode(input)?,
+            sequencer_address: String::decode(input)?,
+            block_timestamp: u64::decode(input)?,
+            transaction_count: usize::decode(input)?,
+            transaction_commitment: String::decode(input)?,
+            message_commitment: String::decode(input)?,
+            event_count: usize::decode(input)?,
+            event_commitment: String::decode(input)?,
+            receipt_commitment: String::decode(input)?,
+            parent_state_root: String::decode(input)?,
+            state_root: String::decode(input)?,
+            block_status: BlockStatus::decode(input)?,
+            l1_gas_price: U256::decode(input)?,
+            l1_data_gas_price: U256::decode(input)?,
+            l2_gas_price: U256::decode(input)?,
+            l2_gas_used: u64::decode(input)?,
+            state_diff_length: usize::decode(input)?,
+        })
+    }
+}
+
+impl Codec for StateDiff {
+    fn encode(&self, out: &mut Encoder) {
+        self.deployed_contracts.encode(out);
+        self.storage_updates.encode(out);
+        self.nonce_updates.encode(out);
+        self.declared_classes.encode(out);
+        self.replaced_classes.encode(out);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        Ok(StateDiff {
+            deployed_contracts: BTreeMap::decode(input)?,
+            storage_updates: BTreeMap::decode(input)?,
+            nonce_updates: BTreeMap::decode(input)?,
+            declared_classes: BTreeMap::decode(input)?,
+            replaced_classes: BTreeMap::decode(input)?,
+        })
+    }
+}
+
+impl Codec for StateSnapshot {
+    fn encode(&self, out: &mut Encoder) {
+        self.balances.encode(out);
+        self.nonces.encode(out);
+        self.storage.encode(out);
+        self.contract_classes.encode(out);
+        self.declared_classes.encode(out);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        Ok(StateSnapshot {
+            balances: HashMap::decode(input)?,
+            nonces: HashMap::decode(input)?,
+            storage: HashMap::decode(input)?,
+            contract_classes: HashMap::decode(input)?,
+            declared_classes: HashMap::decode(input)?,
+        })
+    }
+}
+
+/// Proofs are not stored: a block is saved when it is sealed, before it has one
+impl Codec for L2Block {
+    fn encode(&self, out: &mut Encoder) {
+        self.header.encode(out);
+        self.transactions.encode(out);
+        self.receipts.encode(out);
+        self.state_diff.encode(out);
+        self.da_encoding.encode(out);
+        self.l1_to_l2_messages.encode(out);
+    }
+
+    fn decode(input: &mut Decoder) -> Result<Self, String> {
+        Ok(L2Block {
+            header: BlockHeader::decode(input)?,
+            transactions: Vec::decode(input)?,
+            receipts: Vec::decode(input)?,
+            state_diff: StateDiff::decode(input)?,
+            da_encoding: Vec::decode(input)?,
+            l1_to_l2_messages: Vec::decode(input)?,
+            proof: None,
+        })
+    }
+}
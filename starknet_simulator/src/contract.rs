@@ -0,0 +1,191 @@
+//contract execution
+//contracts are Rust implementations of Cairo classes; the sequencer dispatches calls to them by entry point
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use sha2::{Sha256, Digest};
+use crate::event::Event;
+use crate::gas::{Builtin, ResourceMeter};
+use crate::l1::L2ToL1Message;
+use crate::state::{self, StateOverlay};
+use crate::transaction::Transaction;
+use crate::utils::U256;
+
+/// Class hash of the accounts created by `DeployAccount` transactions
+pub const ACCOUNT_CLASS_HASH: &str = "account_class";
+
+/// Kind of an entry point, mirroring Cairo's `#[external]` and `#[l1_handler]` attributes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryPointType {
+    External,
+    L1Handler,
+}
+
+/// What `get_tx_info` and `get_block_info` return while a transaction runs
+#[derive(Debug, Clone, Default)]
+pub struct TxInfo {
+    pub transaction_hash: String,
+    pub account_address: String,
+    pub signature: Vec<String>,
+    pub nonce: u64,
+    pub max_fee: U256,
+    pub block_timestamp: u64, // seconds since the Unix epoch
+}
+
+impl TxInfo {
+    /// What `tx` sees when it runs in a block with the given timestamp
+    pub fn new(tx: &Transaction, block_timestamp: u64) -> Self {
+        TxInfo {
+            transaction_hash: tx.get_hash(),
+            account_address: tx.sender.clone(),
+            signature: tx.signature.clone(),
+            nonce: tx.nonce,
+            max_fee: tx.fee,
+            block_timestamp,
+        }
+    }
+}
+
+/// Everything a contract can touch while one of its entry points runs.
+/// Writes go to the transaction's state overlay, and every access is metered.
+pub struct ExecutionContext<'a> {
+    pub contract_address: String,
+    pub caller_address: String,
+    pub state: &'a mut StateOverlay, // the transaction's writes so far
+    pub committed_balances: &'a HashMap<String, U256>, // balances as of the previous transaction
+    pub committed_storage: &'a HashMap<String, HashMap<String, U256>>, // storage as of the previous transaction
+    pub contracts: &'a HashMap<String, Arc<dyn Contract>>, // deployed contracts, for calls to other contracts
+    pub meter: &'a mut ResourceMeter,
+    pub messages: &'a mut Vec<L2ToL1Message>, // L2→L1 messages sent so far
+    pub events: &'a mut Vec<Event>, // events emitted so far
+    pub tx_info: &'a TxInfo,
+    pub step_limit: Option<u64>, // steps the meter may reach, only set while `__validate__` runs
+}
+
+impl ExecutionContext<'_> {
+    /// Meters `steps` steps of the contract's own logic. Fails once the step limit is
+    /// passed, so a contract looping over its input stops as soon as it runs out.
+    pub fn steps(&mut self, steps: u64) -> Result<(), String> {
+        self.meter.steps(steps);
+        match self.step_limit {
+            Some(limit) if self.meter.resources().steps > limit => Err(format!("out of steps: limit {}", limit)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn storage_read(&mut self, key: &str) -> U256 {
+        self.meter.steps(50);
+        self.meter.builtin(Builtin::Pedersen, 1);
+        self.state.storage_read(self.committed_storage, &self.contract_address, key)
+    }
+
+    pub fn storage_write(&mut self, key: &str, value: U256) {
+        self.meter.steps(50);
+        self.meter.builtin(Builtin::Pedersen, 1);
+        self.meter.storage_write(&self.contract_address, key);
+        self.state.storage_write(&self.contract_address, key, value);
+    }
+
+    /// `call_contract_syscall`: runs an external entry point of another contract, with this
+    /// contract as the caller. If the inner call fails, everything it wrote, sent or emitted is undone
+    /// and the error is returned to this contract.
+    pub fn call_contract(&mut self, contract_address: &str, selector: &str, calldata: &[String]) -> Result<Vec<String>, String> {
+        self.meter.steps(150);
+        self.meter.builtin(Builtin::RangeCheck, 2);
+        let contract = self
+            .contracts
+            .get(contract_address)
+            .cloned()
+            .ok_or_else(|| format!("no contract deployed at {}", contract_address))?;
+        if contract.entry_point_type(selector) != Some(EntryPointType::External) {
+            return Err(format!("{} has no External entry point named {}", contract_address, selector));
+        }
+
+        let checkpoint = self.state.clone();
+        let messages_sent = self.messages.len();
+        let events_emitted = self.events.len();
+        let mut inner = ExecutionContext {
+            contract_address: contract_address.to_string(),
+            caller_address: self.contract_address.clone(),
+            state: &mut *self.state,
+            committed_balances: self.committed_balances,
+            committed_storage: self.committed_storage,
+            contracts: self.contracts,
+            meter: &mut *self.meter,
+            messages: &mut *self.messages,
+            events: &mut *self.events,
+            tx_info: self.tx_info,
+            step_limit: self.step_limit,
+        };
+        let result = contract.execute(selector, calldata, &mut inner);
+        if result.is_err() {
+            *self.state = checkpoint;
+            self.messages.truncate(messages_sent);
+            self.events.truncate(events_emitted);
+        }
+        result
+    }
+
+    /// Mints fee tokens to an account. Only the bridge calls this.
+    pub fn mint(&mut self, account: &str, amount: U256) -> Result<(), String> {
+        self.meter.steps(200);
+        self.meter.builtin(Builtin::RangeCheck, 2);
+        self.meter.storage_write(account, "balance");
+        state::credit(self.state.balances_for(self.committed_balances, &[account]), account, amount).map_err(|err| err.to_string())
+    }
+
+    /// Burns fee tokens from an account. Only the bridge calls this.
+    pub fn burn(&mut self, account: &str, amount: U256) -> Result<(), String> {
+        self.meter.steps(200);
+        self.meter.builtin(Builtin::RangeCheck, 2);
+        self.meter.storage_write(account, "balance");
+        state::debit(self.state.balances_for(self.committed_balances, &[account]), account, amount).map_err(|err| err.to_string())
+    }
+
+    /// `send_message_to_l1_syscall`: the payload is published to L1 with the block
+    pub fn send_message_to_l1(&mut self, to_address: &str, payload: Vec<String>) {
+        self.meter.steps(100);
+        self.meter.builtin(Builtin::RangeCheck, 1);
+        self.meter.l1_data(payload.len() as u64 + 2); // payload plus sender and recipient
+        self.messages.push(L2ToL1Message {
+            from_address: self.contract_address.clone(),
+            to_address: to_address.to_string(),
+            payload,
+        });
+    }
+
+    /// `emit_event_syscall`: the event is kept in the receipt if the transaction succeeds
+    pub fn emit_event(&mut self, keys: Vec<String>, data: Vec<String>) {
+        self.meter.steps(50 + 10 * (keys.len() + data.len()) as u64);
+        self.events.push(Event {
+            from_address: self.contract_address.clone(),
+            keys,
+            data,
+        });
+    }
+}
+
+/// A contract class. `execute` runs the entry point named by `selector`; the
+/// sequencer checks `entry_point_type` first, so an `#[l1_handler]` cannot be
+/// called as an external function and vice versa.
+pub trait Contract: Send + Sync {
+    fn class_hash(&self) -> String;
+    fn entry_point_type(&self, selector: &str) -> Option<EntryPointType>;
+    fn execute(&self, selector: &str, calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String>;
+}
+
+/// Parses a calldata felt as an amount
+pub fn parse_felt(calldata: &[String], index: usize) -> Result<U256, String> {
+    calldata
+        .get(index)
+        .ok_or_else(|| format!("missing calldata argument {}", index))?
+        .parse::<U256>()
+        .map_err(|err| format!("calldata argument {}: {}", index, err))
+}
+
+/// Hash of the CASM compiled from a Sierra class, published with the declaration
+pub fn compiled_class_hash(class_hash: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("casm-{}", class_hash));
+    format!("{:x}", hasher.finalize())
+}
@@ -0,0 +1,184 @@
+//data availability
+//state diffs are published to L1 as felts, in the layout Starknet posts as blob data
+
+use std::collections::BTreeSet;
+use std::fmt;
+use sha2::{Sha256, Digest};
+use crate::block::L2Block;
+use crate::gas::GasPrices;
+use crate::state::StateDiff;
+use crate::utils::U256;
+
+/// Size of one felt in the published data
+pub const BYTES_PER_FELT: usize = 32;
+
+/// Where state diffs are published, with the pricing and capacity of each option
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DaMode {
+    /// Posted as transaction calldata, paid in L1 gas per byte
+    Calldata { gas_per_byte: u64, max_bytes_per_l1_block: usize },
+    /// Posted in EIP-4844 blobs, paid in blob gas per whole blob
+    Blob { blob_gas_per_blob: u64, bytes_per_blob: usize, max_blobs_per_l1_block: usize },
+    /// Volition off-chain mode: a data availability committee keeps the data, L1 only sees the state root
+    Offchain { fee_per_kilobyte: U256 },
+}
+
+impl DaMode {
+    /// 16 gas per calldata byte, at most 128 KiB of calldata per L1 transaction
+    pub fn calldata() -> Self {
+        DaMode::Calldata { gas_per_byte: 16, max_bytes_per_l1_block: 128 * 1024 }
+    }
+
+    /// 128 KiB blobs, each costing 2^17 blob gas, at most 6 per L1 block
+    pub fn blob() -> Self {
+        DaMode::Blob { blob_gas_per_blob: 131_072, bytes_per_blob: 131_072, max_blobs_per_l1_block: 6 }
+    }
+
+    pub fn offchain() -> Self {
+        DaMode::Offchain { fee_per_kilobyte: U256::ONE }
+    }
+
+    /// Capacity units (bytes of calldata or blobs) `bytes` of data take in this mode
+    pub fn units_for(&self, bytes: usize) -> usize {
+        match self {
+            DaMode::Calldata { .. } => bytes,
+            DaMode::Blob { bytes_per_blob, .. } => bytes.div_ceil(*bytes_per_blob),
+            DaMode::Offchain { .. } => 0,
+        }
+    }
+
+    /// Capacity units one L1 block can carry, `None` when there is no limit
+    pub fn units_per_l1_block(&self) -> Option<usize> {
+        match self {
+            DaMode::Calldata { max_bytes_per_l1_block, .. } => Some(*max_bytes_per_l1_block),
+            DaMode::Blob { max_blobs_per_l1_block, .. } => Some(*max_blobs_per_l1_block),
+            DaMode::Offchain { .. } => None,
+        }
+    }
+
+    /// Cost of publishing `bytes` of state diff at the given L1 prices
+    pub fn publication_cost(&self, bytes: usize, gas_prices: &GasPrices) -> DaCost {
+        let units = self.units_for(bytes);
+        let l1_blocks = match self.units_per_l1_block() {
+            Some(capacity) => units.div_ceil(capacity),
+            None => 0,
+        };
+        let cost = match self {
+            DaMode::Calldata { gas_per_byte, .. } => U256::from(bytes as u64 * gas_per_byte).checked_mul(gas_prices.l1_gas_price),
+            DaMode::Blob { blob_gas_per_blob, .. } => U256::from(units as u64 * blob_gas_per_blob).checked_mul(gas_prices.l1_data_gas_price),
+            DaMode::Offchain { fee_per_kilobyte } => U256::from(bytes.div_ceil(1024) as u64).checked_mul(*fee_per_kilobyte),
+        };
+
+        DaCost {
+            mode: *self,
+            bytes,
+            units,
+            l1_blocks,
+            cost: cost.unwrap_or(U256::MAX),
+        }
+    }
+}
+
+impl fmt::Display for DaMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DaMode::Calldata { .. } => write!(f, "calldata"),
+            DaMode::Blob { .. } => write!(f, "blob"),
+            DaMode::Offchain { .. } => write!(f, "off-chain"),
+        }
+    }
+}
+
+/// What publishing some DA data costs in one mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DaCost {
+    pub mode: DaMode,
+    pub bytes: usize,
+    pub units: usize,     // calldata bytes or blobs used
+    pub l1_blocks: usize, // L1 blocks needed to carry the data
+    pub cost: U256,
+}
+
+/// Total DA cost of a run of blocks in each of the given modes. Every block is priced
+/// at the L1 prices recorded in its header.
+pub fn compare_da_costs(blocks: &[L2Block], modes: &[DaMode]) -> Vec<DaCost> {
+    modes
+        .iter()
+        .map(|mode| {
+            let mut total = DaCost { mode: *mode, bytes: 0, units: 0, l1_blocks: 0, cost: U256::ZERO };
+            for block in blocks {
+                let cost = mode.publication_cost(block.da_size_bytes(), &block.gas_prices());
+                total.bytes += cost.bytes;
+                total.units += cost.units;
+                total.l1_blocks += cost.l1_blocks;
+                total.cost = total.cost.checked_add(cost.cost).unwrap_or(U256::MAX);
+            }
+            total
+        })
+        .collect()
+}
+
+/// Maps a name (address, storage key, class hash) to a field element: its sha256
+/// digest truncated to 250 bits, the way `starknet_keccak` derives selectors
+pub fn felt_from_str(value: &str) -> U256 {
+    let digest = Sha256::digest(value.as_bytes());
+    let mut high = [0u8; 16];
+    let mut low = [0u8; 16];
+    high.copy_from_slice(&digest[..16]);
+    low.copy_from_slice(&digest[16..]);
+    U256::from_low_high(u128::from_be_bytes(low), u128::from_be_bytes(high) >> 6)
+}
+
+/// Packs the per-contract header felt: `class_flag << 128 | nonce << 64 | n_updates`
+pub fn pack_contract_header(class_updated: bool, nonce: u64, n_updates: u64) -> U256 {
+    U256::from_low_high(((nonce as u128) << 64) | n_updates as u128, class_updated as u128)
+}
+
+/// Encodes a state diff in Starknet's compressed DA layout:
+///
+/// ```text
+/// [n_contracts,
+///  (address, header, [class_hash if class_flag], (key, value) * n_updates) * n_contracts,
+///  n_declared,
+///  (class_hash, compiled_class_hash) * n_declared]
+/// ```
+///
+/// Nonce, update count and class flag share one header felt per contract. Balances
+/// show up as storage of the fee token. Contracts whose nonce did not change carry 0.
+pub fn encode_state_diff(diff: &StateDiff) -> Vec<U256> {
+    let contracts: BTreeSet<&String> = diff
+        .storage_updates
+        .keys()
+        .chain(diff.nonce_updates.keys())
+        .chain(diff.deployed_contracts.keys())
+        .chain(diff.replaced_classes.keys())
+        .collect();
+
+    let mut felts = vec![U256::from(contracts.len() as u64)];
+    for contract in contracts {
+        let class_hash = diff
+            .deployed_contracts
+            .get(contract)
+            .or_else(|| diff.replaced_classes.get(contract));
+        let updates = diff.storage_updates.get(contract);
+        let nonce = diff.nonce_updates.get(contract).copied().unwrap_or(0);
+        let n_updates = updates.map(|updates| updates.len() as u64).unwrap_or(0);
+
+        felts.push(felt_from_str(contract));
+        felts.push(pack_contract_header(class_hash.is_some(), nonce, n_updates));
+        if let Some(class_hash) = class_hash {
+            felts.push(felt_from_str(class_hash));
+        }
+        for (key, value) in updates.into_iter().flatten() {
+            felts.push(felt_from_str(key));
+            felts.push(*value);
+        }
+    }
+
+    felts.push(U256::from(diff.declared_classes.len() as u64));
+    for (class_hash, compiled_class_hash) in &diff.declared_classes {
+        felts.push(felt_from_str(class_hash));
+        felts.push(felt_from_str(compiled_class_hash));
+    }
+    felts
+}
@@ -0,0 +1,150 @@
+//events
+//events emitted by contracts: kept with receipts and blocks, and queried the way starknet_getEvents does
+
+use std::fmt;
+use sha2::{Sha256, Digest};
+use crate::block::L2Block;
+
+/// An event emitted by a contract. The events of a reverted transaction are dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub from_address: String, // emitting contract
+    pub keys: Vec<String>,    // indexed fields, the event name first by convention
+    pub data: Vec<String>,
+}
+
+impl Event {
+    pub fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(format!(
+            "{}-{}-{}",
+            self.from_address,
+            self.keys.join(","),
+            self.data.join(",")
+        ));
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// An event returned by `get_events`, with the block and transaction that emitted it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmittedEvent {
+    pub event: Event,
+    pub block_number: u64,
+    pub block_hash: String,
+    pub transaction_hash: String,
+}
+
+/// Largest page `get_events` returns
+pub const MAX_CHUNK_SIZE: usize = 1_000;
+
+/// Which events to return and how many at a time, as in a `starknet_getEvents` request
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    pub from_block: Option<u64>, // inclusive; from the first block if unset
+    pub to_block: Option<u64>,   // inclusive; up to the latest block if unset
+    pub address: Option<String>, // only events emitted by this contract
+    pub keys: Vec<Vec<String>>,  // keys[i] lists the values allowed for key i; an empty list allows any
+    pub chunk_size: usize,
+    pub continuation_token: Option<String>, // returned with the previous page
+}
+
+impl EventFilter {
+    /// Events of every block, `chunk_size` at a time
+    pub fn new(chunk_size: usize) -> Self {
+        EventFilter { chunk_size, ..Self::default() }
+    }
+
+    /// Whether `event` passes the address and key filters
+    pub fn matches(&self, event: &Event) -> bool {
+        if self.address.as_ref().is_some_and(|address| *address != event.from_address) {
+            return false;
+        }
+        self.keys.iter().enumerate().all(|(index, allowed)| {
+            allowed.is_empty() || event.keys.get(index).is_some_and(|key| allowed.contains(key))
+        })
+    }
+}
+
+/// One page of events. `continuation_token` is set when more events match; passing it
+/// back in the filter returns the next page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventsChunk {
+    pub events: Vec<EmittedEvent>,
+    pub continuation_token: Option<String>,
+}
+
+/// Reasons an events query is refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventQueryError {
+    InvalidChunkSize { chunk_size: usize },
+    InvalidContinuationToken { token: String },
+}
+
+impl fmt::Display for EventQueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventQueryError::InvalidChunkSize { chunk_size } => {
+                write!(f, "chunk size {} must be between 1 and {}", chunk_size, MAX_CHUNK_SIZE)
+            }
+            EventQueryError::InvalidContinuationToken { token } => {
+                write!(f, "invalid continuation token {}", token)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EventQueryError {}
+
+/// A continuation token is the position of the next matching event: `<block number>-<event index in the block>`
+fn parse_continuation_token(token: &str) -> Result<(u64, usize), EventQueryError> {
+    let invalid = || EventQueryError::InvalidContinuationToken { token: token.to_string() };
+    let (block_number, index) = token.split_once('-').ok_or_else(invalid)?;
+    Ok((block_number.parse().map_err(|_| invalid())?, index.parse().map_err(|_| invalid())?))
+}
+
+/// Returns the events of `blocks` that pass `filter`, in the order they were emitted,
+/// one page of `filter.chunk_size` at a time
+pub fn get_events(blocks: &[L2Block], filter: &EventFilter) -> Result<EventsChunk, EventQueryError> {
+    if filter.chunk_size == 0 || filter.chunk_size > MAX_CHUNK_SIZE {
+        return Err(EventQueryError::InvalidChunkSize { chunk_size: filter.chunk_size });
+    }
+    let start = match &filter.continuation_token {
+        Some(token) => parse_continuation_token(token)?,
+        None => (0, 0),
+    };
+
+    let mut events = vec![];
+    for block in blocks {
+        let block_number = block.header.block_number;
+        if block_number < start.0
+            || filter.from_block.is_some_and(|from| block_number < from)
+            || filter.to_block.is_some_and(|to| block_number > to)
+        {
+            continue;
+        }
+        let mut block_hash = None;
+        let emitted = block
+            .receipts
+            .iter()
+            .flat_map(|receipt| receipt.events.iter().map(move |event| (&receipt.transaction_hash, event)));
+        for (index, (transaction_hash, event)) in emitted.enumerate() {
+            if (block_number, index) < start || !filter.matches(event) {
+                continue;
+            }
+            if events.len() == filter.chunk_size {
+                return Ok(EventsChunk {
+                    events,
+                    continuation_token: Some(format!("{}-{}", block_number, index)),
+                });
+            }
+            events.push(EmittedEvent {
+                event: event.clone(),
+                block_number,
+                block_hash: block_hash.get_or_insert_with(|| block.get_block_hash()).clone(),
+                transaction_hash: transaction_hash.clone(),
+            });
+        }
+    }
+    Ok(EventsChunk { events, continuation_token: None })
+}
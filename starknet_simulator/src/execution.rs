@@ -0,0 +1,130 @@
+//execution
+//the steps every way of running a transaction shares: the mempool's admission checks, the transfer body and the fee transfer
+
+use std::collections::HashMap;
+use crate::gas::{self, FeeCharge, GasPrices, ResourceMeter};
+use crate::state;
+use crate::transaction::{PhaseOutcome, Transaction, TransactionPhases};
+use crate::utils::U256;
+
+/// Read access to the balances and nonces a transaction runs against. The mempool and the
+/// sequencer read the committed maps; Block-STM reads through a view that records every read.
+pub trait StateView {
+    fn balance(&mut self, account: &str) -> U256;
+    fn nonce(&mut self, account: &str) -> u64;
+}
+
+/// State before the block, read straight from the mempool's maps
+#[derive(Debug, Clone, Copy)]
+pub struct BaseState<'a> {
+    pub balances: &'a HashMap<String, U256>,
+    pub nonces: &'a HashMap<String, u64>,
+}
+
+impl StateView for BaseState<'_> {
+    fn balance(&mut self, account: &str) -> U256 {
+        state::get_balance(self.balances, account)
+    }
+
+    fn nonce(&mut self, account: &str) -> u64 {
+        self.nonces.get(account).copied().unwrap_or_default()
+    }
+}
+
+/// Why a transaction was refused before it ran
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    pub reason: String,
+    pub remember: bool, // its hash may not be resent
+}
+
+impl Rejection {
+    fn new(reason: String, remember: bool) -> Self {
+        Rejection { reason, remember }
+    }
+}
+
+/// Checks the transaction uses the sender's next nonce, and returns it
+pub fn check_nonce(tx: &Transaction, state: &mut impl StateView) -> Result<u64, Rejection> {
+    let nonce = state.nonce(&tx.sender);
+    if tx.nonce != nonce {
+        return Err(Rejection::new(format!("Incorrect nonce. Expected: {}", nonce), false));
+    }
+    Ok(nonce)
+}
+
+/// The mempool's checks: the nonce, the fee bounds against the current gas prices, and that
+/// the fee payer can pay the max fee. Nothing is charged here: the actual fee is transferred
+/// once, after execution.
+pub fn check_admission(tx: &Transaction, state: &mut impl StateView, gas_prices: &GasPrices) -> Result<(), Rejection> {
+    check_nonce(tx, state)?;
+    tx.check_fee_bounds(gas_prices).map_err(|reason| Rejection::new(reason, true))?;
+    if state.balance(tx.fee_payer()) <= tx.fee {
+        let reason = format!("{}'s balance must be greater than the fee.", tx.fee_payer());
+        return Err(Rejection::new(reason, true));
+    }
+    Ok(())
+}
+
+/// `__execute__` of a plain transfer of `tx.amount` to `tx.receiver`, metered and checked
+/// against the transaction's gas limits at every step
+pub fn run_transfer(
+    tx: &Transaction,
+    balances: &mut HashMap<String, U256>,
+    meter: &mut ResourceMeter,
+    gas_prices: &GasPrices,
+) -> Result<(), String> {
+    let amount = tx.amount.ok_or("transfer has no amount")?;
+    let receiver = tx.receiver.as_deref().ok_or("transfer has no receiver")?;
+    gas::meter_transfer_checks(meter);
+    tx.check_gas_limit(&meter.resources().to_gas(), gas_prices)?;
+    state::check_transfer(balances, &tx.sender, receiver, amount).map_err(|err| err.to_string())?;
+
+    gas::meter_transfer_writes(meter, &tx.sender, receiver);
+    tx.check_gas_limit(&meter.resources().to_gas(), gas_prices)?;
+    state::transfer(balances, &tx.sender, receiver, amount).map_err(|err| err.to_string())
+}
+
+/// What the fee transfer charged, and whether the execution was kept
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeeTransfer {
+    pub charge: FeeCharge,
+    pub revert_reason: Option<String>, // set if the execution reverted: `validated` holds the result
+    pub phases: TransactionPhases,
+}
+
+/// The fee transfer phase. If `__execute__` succeeded, the fee is charged to `payer` out of
+/// `executed`, what it left. If it reverted, or `executed` cannot pay, the fee is charged out
+/// of `validated`, the state from before it, capped at what the payer has there, so a
+/// transaction that reached `__execute__` is always included.
+pub fn transfer_fee(
+    execution: Result<(), String>,
+    executed: &mut HashMap<String, U256>,
+    validated: &mut HashMap<String, U256>,
+    payer: &str,
+    charge: FeeCharge,
+) -> FeeTransfer {
+    let execution = execution.and_then(|()| {
+        state::debit(executed, payer, charge.total()).map_err(|err| format!("fee transfer failed: {}", err))
+    });
+    let reason = match execution {
+        Ok(()) => return FeeTransfer { charge, revert_reason: None, phases: TransactionPhases::charged(None) },
+        Err(reason) => reason,
+    };
+
+    let mut phases = TransactionPhases::charged(Some(&reason));
+    let available = state::get_balance(validated, payer);
+    let charge = if available < charge.total() {
+        phases.fee_transfer = PhaseOutcome::Reverted(format!(
+            "{} could only pay {} of the fee of {}",
+            payer,
+            available,
+            charge.total()
+        ));
+        charge.capped_at(available)
+    } else {
+        charge
+    };
+    state::debit(validated, payer, charge.total()).expect("the fee was capped at the payer's balance");
+    FeeTransfer { charge, revert_reason: Some(reason), phases }
+}
@@ -0,0 +1,292 @@
+//gas metering and fee calculation
+//execution records the resources it uses, and the fee is derived from those resources and the gas prices
+
+use std::collections::{BTreeMap, HashSet};
+use crate::utils::U256;
+
+/// Builtins whose usage is metered separately from plain Cairo steps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Builtin {
+    Pedersen,
+    RangeCheck,
+    Ecdsa,
+    Poseidon,
+    Bitwise,
+}
+
+impl Builtin {
+    /// Gas per application, in units of 1/10_000 gas
+    fn weight(&self) -> u64 {
+        match self {
+            Builtin::Pedersen => 800,
+            Builtin::RangeCheck => 400,
+            Builtin::Ecdsa => 25_600,
+            Builtin::Poseidon => 800,
+            Builtin::Bitwise => 1_600,
+        }
+    }
+}
+
+/// Gas per Cairo step, in units of 1/10_000 gas
+const STEP_WEIGHT: u64 = 25;
+const WEIGHT_SCALE: u64 = 10_000;
+
+/// L1 gas charged for every felt that has to be published on L1
+pub const L1_GAS_PER_FELT: u64 = 1;
+/// L1 data (blob) gas charged for every extra felt published with the block
+pub const L1_DATA_GAS_PER_FELT: u64 = 1;
+/// A storage write publishes its key and its new value
+pub const FELTS_PER_STORAGE_WRITE: u64 = 2;
+
+/// Resources consumed while executing a single transaction
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecutionResources {
+    pub steps: u64,
+    pub builtins: BTreeMap<Builtin, u64>,
+    pub storage_writes: u64, // distinct storage slots modified
+    pub l1_data_felts: u64,  // extra felts published on L1 (declared classes, deployed contracts, ...)
+}
+
+impl ExecutionResources {
+    /// Converts the resources into gas.
+    ///
+    /// Computation follows Starknet's bottleneck rule: the most expensive of steps and
+    /// each builtin decides the L2 gas. Storage writes in the state diff are paid for
+    /// as L1 gas, and the extra data published with the block as L1 data gas.
+    pub fn to_gas(&self) -> GasVector {
+        let steps_cost = self.steps * STEP_WEIGHT;
+        let bottleneck = self
+            .builtins
+            .iter()
+            .map(|(builtin, count)| count * builtin.weight())
+            .fold(steps_cost, u64::max);
+        let l2_gas = bottleneck.div_ceil(WEIGHT_SCALE);
+
+        let l1_gas = self.storage_writes * FELTS_PER_STORAGE_WRITE * L1_GAS_PER_FELT;
+        let l1_data_gas = self.l1_data_felts * L1_DATA_GAS_PER_FELT;
+
+        GasVector { l1_gas, l1_data_gas, l2_gas }
+    }
+}
+
+/// Gas consumed, split by the layer that is paid for it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GasVector {
+    pub l1_gas: u64,
+    pub l1_data_gas: u64,
+    pub l2_gas: u64,
+}
+
+/// Prices (in tokens per unit of gas) used to turn gas into a fee
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GasPrices {
+    pub l1_gas_price: U256,
+    pub l1_data_gas_price: U256, // blob gas on L1, quoted by the L1 oracle alongside the L1 gas price
+    pub l2_gas_price: U256,
+}
+
+impl Default for GasPrices {
+    fn default() -> Self {
+        GasPrices {
+            l1_gas_price: U256::ONE,
+            l1_data_gas_price: U256::ONE,
+            l2_gas_price: U256::ONE,
+        }
+    }
+}
+
+impl GasPrices {
+    /// Fee for the given gas:
+    /// `l1_gas * l1_gas_price + l1_data_gas * l1_data_gas_price + l2_gas * l2_gas_price`.
+    /// Returns `None` if the fee does not fit in a u256.
+    pub fn fee_for(&self, gas: &GasVector) -> Option<U256> {
+        let l1_fee = U256::from(gas.l1_gas).checked_mul(self.l1_gas_price)?;
+        let l1_data_fee = U256::from(gas.l1_data_gas).checked_mul(self.l1_data_gas_price)?;
+        let l2_fee = U256::from(gas.l2_gas).checked_mul(self.l2_gas_price)?;
+        l1_fee.checked_add(l1_data_fee)?.checked_add(l2_fee)
+    }
+}
+
+/// Fee actually charged for a transaction, split between the burned base fee
+/// and the priority tip that goes to the sequencer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeeCharge {
+    pub base_fee: U256,
+    pub tip: U256,
+}
+
+impl FeeCharge {
+    pub fn total(&self) -> U256 {
+        self.base_fee.checked_add(self.tip).unwrap_or(U256::MAX)
+    }
+
+    /// The part of the charge that `available` can pay, base fee first
+    pub fn capped_at(&self, available: U256) -> FeeCharge {
+        let base_fee = self.base_fee.min(available);
+        FeeCharge { base_fee, tip: self.tip.min(available.saturating_sub(base_fee)) }
+    }
+}
+
+/// EIP-1559 style market for the L2 gas price. After every block the base fee moves
+/// towards keeping blocks at `target_l2_gas` used, by at most 1/`adjustment_denominator`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeMarket {
+    pub target_l2_gas: u64,
+    pub max_l2_gas: u64,
+    pub adjustment_denominator: u64,
+    pub min_l2_gas_price: U256,
+}
+
+impl Default for FeeMarket {
+    fn default() -> Self {
+        FeeMarket {
+            target_l2_gas: 20,
+            max_l2_gas: 40,
+            adjustment_denominator: 8,
+            min_l2_gas_price: U256::ONE,
+        }
+    }
+}
+
+impl FeeMarket {
+    /// Base fee for the next block, given the current one and the L2 gas the block used
+    pub fn next_l2_gas_price(&self, current: U256, l2_gas_used: u64) -> U256 {
+        let used = l2_gas_used.min(self.max_l2_gas);
+        let divisor = self.target_l2_gas.max(1) * self.adjustment_denominator.max(1);
+
+        let next = if used > self.target_l2_gas {
+            let delta = current
+                .checked_mul(U256::from(used - self.target_l2_gas))
+                .unwrap_or(U256::MAX)
+                .div_rem_u64(divisor)
+                .0;
+            // Always move up by at least one unit so a price of 1 can still rise
+            current.checked_add(delta.max(U256::ONE)).unwrap_or(U256::MAX)
+        } else {
+            let delta = current
+                .checked_mul(U256::from(self.target_l2_gas - used))
+                .unwrap_or(U256::MAX)
+                .div_rem_u64(divisor)
+                .0;
+            current.saturating_sub(delta)
+        };
+        next.max(self.min_l2_gas_price)
+    }
+}
+
+/// Limit a v3 transaction sets on one resource
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceBounds {
+    pub max_amount: u64,
+    pub max_price_per_unit: U256,
+}
+
+/// Per-resource limits of a v3 transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceBoundsMapping {
+    pub l1_gas: ResourceBounds,
+    pub l1_data_gas: ResourceBounds,
+    pub l2_gas: ResourceBounds,
+}
+
+impl ResourceBoundsMapping {
+    /// The most a transaction with these bounds can ever be charged
+    pub fn max_fee(&self) -> Option<U256> {
+        let l1 = U256::from(self.l1_gas.max_amount).checked_mul(self.l1_gas.max_price_per_unit)?;
+        let l1_data = U256::from(self.l1_data_gas.max_amount).checked_mul(self.l1_data_gas.max_price_per_unit)?;
+        let l2 = U256::from(self.l2_gas.max_amount).checked_mul(self.l2_gas.max_price_per_unit)?;
+        l1.checked_add(l1_data)?.checked_add(l2)
+    }
+}
+
+/// Accumulates resources while a transaction executes
+#[derive(Debug, Default)]
+pub struct ResourceMeter {
+    resources: ExecutionResources,
+    written_slots: HashSet<(String, String)>,
+}
+
+impl ResourceMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn steps(&mut self, steps: u64) {
+        self.resources.steps += steps;
+    }
+
+    pub fn builtin(&mut self, builtin: Builtin, count: u64) {
+        *self.resources.builtins.entry(builtin).or_insert(0) += count;
+    }
+
+    /// Records a write to `key` in `contract` storage. Writing the same slot
+    /// twice is only paid once, since only the final value is published.
+    pub fn storage_write(&mut self, contract: &str, key: &str) {
+        if self.written_slots.insert((contract.to_string(), key.to_string())) {
+            self.resources.storage_writes += 1;
+        }
+    }
+
+    pub fn l1_data(&mut self, felts: u64) {
+        self.resources.l1_data_felts += felts;
+    }
+
+    pub fn resources(&self) -> &ExecutionResources {
+        &self.resources
+    }
+
+    pub fn finish(self) -> ExecutionResources {
+        self.resources
+    }
+}
+
+// Cost of the built-in operations the simulator executes. The sequencer meters
+// these as it runs a transaction, and fee estimation replays the happy path.
+
+/// Account `__validate__`: signature check and nonce bump
+pub fn meter_validate(meter: &mut ResourceMeter, sender: &str) {
+    meter.steps(600);
+    meter.builtin(Builtin::RangeCheck, 10);
+    meter.builtin(Builtin::Ecdsa, 1);
+    meter.storage_write(sender, "nonce");
+}
+
+/// Reading both balances of a fee-token transfer
+pub fn meter_transfer_checks(meter: &mut ResourceMeter) {
+    meter.steps(300);
+    meter.builtin(Builtin::Pedersen, 2);
+    meter.builtin(Builtin::RangeCheck, 4);
+}
+
+/// Writing both balances of a fee-token transfer
+pub fn meter_transfer_writes(meter: &mut ResourceMeter, sender: &str, receiver: &str) {
+    meter.steps(500);
+    meter.builtin(Builtin::RangeCheck, 2);
+    meter.storage_write(sender, "balance");
+    meter.storage_write(receiver, "balance");
+}
+
+/// Declaring a class: hashing the class and publishing it
+pub fn meter_declare(meter: &mut ResourceMeter) {
+    meter.steps(2_000);
+    meter.builtin(Builtin::Poseidon, 16);
+    meter.l1_data(18); // class hash, compiled class hash and the class itself
+}
+
+/// Deploying an account contract
+pub fn meter_deploy_account(meter: &mut ResourceMeter, sender: &str) {
+    meter.steps(1_500);
+    meter.builtin(Builtin::Pedersen, 4);
+    meter.storage_write(sender, "class_hash");
+    meter.storage_write(sender, "public_key");
+    meter.l1_data(2); // deployed contract address and class hash
+}
+
+/// Dispatching an L1 message to its `#[l1_handler]`: the payload arrives as calldata
+/// and the message hash is checked against L1 when the block is settled
+pub fn meter_l1_handler(meter: &mut ResourceMeter, payload_len: u64) {
+    meter.steps(400 + 10 * payload_len);
+    meter.builtin(Builtin::RangeCheck, 4);
+    meter.builtin(Builtin::Poseidon, 1);
+    meter.l1_data(1); // consumed message hash
+}
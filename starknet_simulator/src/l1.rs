@@ -0,0 +1,476 @@
+//simulated Ethereum L1
+//the sequencer reads L1 gas prices from here at the start of every block,
+//and proven blocks are settled on the Starknet core contract
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::da::DaMode;
+use crate::prover::BlockProof;
+use crate::stark::{self, PublicInput, StarkError};
+use crate::state::{StateDiff, StateSnapshot};
+use crate::utils::U256;
+use sha2::{Sha256, Digest};
+
+/// Gas prices observed on L1 when an L2 block is opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct L1GasQuote {
+    pub l1_gas_price: U256,
+    pub l1_data_gas_price: U256,
+}
+
+/// A source of L1 gas prices. Every call advances the simulated L1 chain by one block.
+pub trait L1GasPriceSource: Send {
+    fn next_quote(&mut self) -> L1GasQuote;
+}
+
+/// Same prices for every block
+pub struct ConstantGasPrice {
+    pub quote: L1GasQuote,
+}
+
+impl ConstantGasPrice {
+    pub fn new(l1_gas_price: U256, l1_data_gas_price: U256) -> Self {
+        ConstantGasPrice {
+            quote: L1GasQuote { l1_gas_price, l1_data_gas_price },
+        }
+    }
+}
+
+impl Default for ConstantGasPrice {
+    fn default() -> Self {
+        Self::new(U256::ONE, U256::ONE)
+    }
+}
+
+impl L1GasPriceSource for ConstantGasPrice {
+    fn next_quote(&mut self) -> L1GasQuote {
+        self.quote
+    }
+}
+
+/// Replays a scripted series of prices, one per block. The last price repeats once
+/// the script runs out.
+pub struct ScriptedGasPrice {
+    quotes: Vec<L1GasQuote>,
+    next: usize,
+}
+
+impl ScriptedGasPrice {
+    pub fn new(quotes: Vec<L1GasQuote>) -> Self {
+        assert!(!quotes.is_empty(), "a scripted gas price needs at least one quote");
+        ScriptedGasPrice { quotes, next: 0 }
+    }
+}
+
+impl L1GasPriceSource for ScriptedGasPrice {
+    fn next_quote(&mut self) -> L1GasQuote {
+        let quote = self.quotes[self.next.min(self.quotes.len() - 1)];
+        self.next += 1;
+        quote
+    }
+}
+
+/// Prices that drift by a random step every block, reproducible from a seed
+pub struct RandomWalkGasPrice {
+    rng: StdRng,
+    l1_gas_price: u128,
+    l1_data_gas_price: u128,
+    max_step: u128,
+    min_price: u128,
+    max_price: u128,
+}
+
+impl RandomWalkGasPrice {
+    pub fn new(seed: u64, start: L1GasQuote, max_step: u128, min_price: u128, max_price: u128) -> Self {
+        let clamp = |price: U256| price.to_u128().unwrap_or(max_price).clamp(min_price, max_price);
+        RandomWalkGasPrice {
+            rng: StdRng::seed_from_u64(seed),
+            l1_gas_price: clamp(start.l1_gas_price),
+            l1_data_gas_price: clamp(start.l1_data_gas_price),
+            max_step,
+            min_price,
+            max_price,
+        }
+    }
+
+    fn step(&mut self, price: u128) -> u128 {
+        let up = self.rng.random_bool(0.5);
+        let delta = self.rng.random_range(0..=self.max_step);
+        let moved = if up { price.saturating_add(delta) } else { price.saturating_sub(delta) };
+        moved.clamp(self.min_price, self.max_price)
+    }
+}
+
+impl L1GasPriceSource for RandomWalkGasPrice {
+    fn next_quote(&mut self) -> L1GasQuote {
+        let quote = L1GasQuote {
+            l1_gas_price: U256::from(self.l1_gas_price),
+            l1_data_gas_price: U256::from(self.l1_data_gas_price),
+        };
+        self.l1_gas_price = self.step(self.l1_gas_price);
+        self.l1_data_gas_price = self.step(self.l1_data_gas_price);
+        quote
+    }
+}
+
+/// Reasons the core contract refuses a state update
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum L1Error {
+    InvalidProof { block_number: u64 },
+    InvalidStarkProof { block_number: u64, error: StarkError },
+    BlockNumberMismatch { expected: u64, got: u64 },
+    StateRootMismatch { expected: String, got: String },
+    NewStateRootMismatch { block_number: u64, expected: String, got: String },
+    StateDiffMismatch { block_number: u64 },
+    MessageNotFound { message_hash: String },
+    NotMessageSender { message_hash: String },
+    CancellationNotRequested { message_hash: String },
+    CancellationTooEarly { message_hash: String },
+    L2ToL1MessageNotFound { message_hash: String },
+}
+
+impl fmt::Display for L1Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            L1Error::InvalidProof { block_number } => {
+                write!(f, "proof for block #{} is invalid", block_number)
+            }
+            L1Error::InvalidStarkProof { block_number, error } => {
+                write!(f, "STARK proof for block #{} does not verify: {}", block_number, error)
+            }
+            L1Error::BlockNumberMismatch { expected, got } => {
+                write!(f, "expected block #{}, got block #{}", expected, got)
+            }
+            L1Error::StateRootMismatch { expected, got } => write!(
+                f,
+                "update starts from state root {} but L1 is at {}",
+                got, expected
+            ),
+            L1Error::NewStateRootMismatch { block_number, expected, got } => write!(
+                f,
+                "block #{} claims state root {} but its state diff leads to {}",
+                block_number, got, expected
+            ),
+            L1Error::StateDiffMismatch { block_number } => write!(
+                f,
+                "state diff published for block #{} does not match the proof",
+                block_number
+            ),
+            L1Error::MessageNotFound { message_hash } => {
+                write!(f, "L1→L2 message {} is not pending on L1", message_hash)
+            }
+            L1Error::NotMessageSender { message_hash } => {
+                write!(f, "only the sender of message {} can cancel it", message_hash)
+            }
+            L1Error::CancellationNotRequested { message_hash } => {
+                write!(f, "cancellation of message {} was never started", message_hash)
+            }
+            L1Error::CancellationTooEarly { message_hash } => {
+                write!(f, "cancellation delay of message {} has not elapsed", message_hash)
+            }
+            L1Error::L2ToL1MessageNotFound { message_hash } => {
+                write!(f, "no L2→L1 message {} is waiting to be consumed", message_hash)
+            }
+        }
+    }
+}
+
+impl std::error::Error for L1Error {}
+
+/// A message sent from L1 to an `#[l1_handler]` entry point of an L2 contract
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct L1ToL2Message {
+    pub from_address: String, // L1 sender
+    pub to_address: String,   // L2 contract
+    pub selector: String,     // l1_handler entry point
+    pub payload: Vec<String>,
+    pub nonce: u64,
+    pub fee: U256, // paid on L1 to cover the L1 handler transaction
+}
+
+impl L1ToL2Message {
+    /// Message hash, as tracked by the core contract. Like on Starknet, the fee is not part of it.
+    pub fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(format!(
+            "{}-{}-{}-{}-{}",
+            self.from_address,
+            self.to_address,
+            self.nonce,
+            self.selector,
+            self.payload.join(",")
+        ));
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// Where an L1→L2 message is in its lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L1ToL2MessageStatus {
+    Pending,               // sent on L1, waiting to be consumed by an accepted L2 block
+    CancellationRequested, // the sender started a cancellation; L2 can still consume it
+    Consumed,              // an L2 block that handled it was accepted on L1
+    Cancelled,             // withdrawn by the sender; L2 can no longer consume it
+}
+
+/// A message sent by an L2 contract to an L1 address. It can be consumed on L1 once
+/// the block that sent it is accepted there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct L2ToL1Message {
+    pub from_address: String, // L2 contract
+    pub to_address: String,   // L1 recipient, the only one allowed to consume it
+    pub payload: Vec<String>,
+}
+
+impl L2ToL1Message {
+    pub fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(format!(
+            "{}-{}-{}",
+            self.from_address,
+            self.to_address,
+            self.payload.join(",")
+        ));
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// An accepted entry in the core contract's state update log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct L1StateUpdate {
+    pub first_block_number: u64, // an aggregated proof settles a whole range at once
+    pub block_number: u64,
+    pub block_hash: String,
+    pub prev_state_root: String,
+    pub new_state_root: String,
+    pub state_diff: StateDiff, // empty when the data was kept off-chain
+    pub da_mode: DaMode,
+    pub l1_to_l2_messages: Vec<String>, // hashes of the messages the block consumed
+    pub l2_to_l1_messages: Vec<String>, // hashes of the messages the block sent
+}
+
+/// Mock of the Starknet core contract on Ethereum. It holds the last state root L1
+/// accepted and only takes updates that chain from it.
+pub struct StarknetCoreContract {
+    pub state_root: String,
+    pub state: StateSnapshot, // the L2 state rebuilt from the accepted diffs; `state_root` commits to it
+    pub block_number: u64,
+    pub state_updates: Vec<L1StateUpdate>, // ordered log of accepted updates
+    pub da_mode: DaMode, // how state diffs are made available
+    pub l1_to_l2_message_nonce: u64,
+    pub l1_to_l2_messages: HashMap<String, L1ToL2MessageStatus>, // message hash -> status
+    pub message_cancellation_delay: Duration,
+    pub l2_to_l1_messages: HashMap<String, u64>, // message hash -> copies not consumed yet
+    pending_l1_to_l2_messages: VecDeque<L1ToL2Message>, // not yet picked up by the sequencer
+    cancellation_requests: HashMap<String, Instant>,
+}
+
+impl StarknetCoreContract {
+    /// Deploys the contract at the genesis state
+    pub fn new(genesis_state: StateSnapshot) -> Self {
+        StarknetCoreContract {
+            state_root: genesis_state.state_root(),
+            state: genesis_state,
+            block_number: 0,
+            state_updates: vec![],
+            da_mode: DaMode::blob(),
+            l1_to_l2_message_nonce: 0,
+            l1_to_l2_messages: HashMap::new(),
+            message_cancellation_delay: Duration::from_secs(5 * 24 * 60 * 60), // 5 days, as on mainnet
+            l2_to_l1_messages: HashMap::new(),
+            pending_l1_to_l2_messages: VecDeque::new(),
+            cancellation_requests: HashMap::new(),
+        }
+    }
+
+    /// `sendMessageToL2`: queues a message for the sequencer and assigns it the next message nonce
+    pub fn send_message_to_l2(&mut self, from_address: &str, to_address: &str, selector: &str, payload: Vec<String>, fee: U256) -> L1ToL2Message {
+        let message = L1ToL2Message {
+            from_address: from_address.to_string(),
+            to_address: to_address.to_string(),
+            selector: selector.to_string(),
+            payload,
+            nonce: self.l1_to_l2_message_nonce,
+            fee,
+        };
+        self.l1_to_l2_message_nonce += 1;
+        self.l1_to_l2_messages.insert(message.hash(), L1ToL2MessageStatus::Pending);
+        self.pending_l1_to_l2_messages.push_back(message.clone());
+        message
+    }
+
+    /// Whether messages are waiting for the sequencer to pick them up
+    pub fn has_pending_messages(&self) -> bool {
+        !self.pending_l1_to_l2_messages.is_empty()
+    }
+
+    /// Hands up to `max` messages that are still consumable to the sequencer, in nonce order.
+    /// The rest stay queued for the next block. Messages the sequencer does not include must
+    /// be handed back with `requeue_pending_messages`.
+    pub fn take_pending_messages(&mut self, max: usize) -> Vec<L1ToL2Message> {
+        let mut taken = vec![];
+        while taken.len() < max {
+            let Some(message) = self.pending_l1_to_l2_messages.pop_front() else {
+                break;
+            };
+            if matches!(
+                self.l1_to_l2_messages.get(&message.hash()),
+                Some(L1ToL2MessageStatus::Pending | L1ToL2MessageStatus::CancellationRequested)
+            ) {
+                taken.push(message);
+            }
+        }
+        taken
+    }
+
+    /// Puts messages the sequencer took but did not include back at the front of the queue,
+    /// in the same order, so they are delivered again with a later block
+    pub fn requeue_pending_messages(&mut self, messages: Vec<L1ToL2Message>) {
+        for message in messages.into_iter().rev() {
+            self.pending_l1_to_l2_messages.push_front(message);
+        }
+    }
+
+    pub fn l1_to_l2_message_status(&self, message_hash: &str) -> Option<L1ToL2MessageStatus> {
+        self.l1_to_l2_messages.get(message_hash).copied()
+    }
+
+    /// `startL1ToL2MessageCancellation`: the sender asks to take a message back.
+    /// It can still be consumed by L2 until the cancellation delay has passed.
+    pub fn start_l1_to_l2_message_cancellation(&mut self, message: &L1ToL2Message, caller: &str) -> Result<(), L1Error> {
+        let message_hash = message.hash();
+        if caller != message.from_address {
+            return Err(L1Error::NotMessageSender { message_hash });
+        }
+        match self.l1_to_l2_messages.get(&message_hash) {
+            Some(L1ToL2MessageStatus::Pending | L1ToL2MessageStatus::CancellationRequested) => {}
+            _ => return Err(L1Error::MessageNotFound { message_hash }),
+        }
+
+        self.l1_to_l2_messages.insert(message_hash.clone(), L1ToL2MessageStatus::CancellationRequested);
+        self.cancellation_requests.insert(message_hash, Instant::now());
+        Ok(())
+    }
+
+    /// `cancelL1ToL2Message`: completes a cancellation once the delay has elapsed
+    pub fn cancel_l1_to_l2_message(&mut self, message: &L1ToL2Message, caller: &str) -> Result<(), L1Error> {
+        let message_hash = message.hash();
+        if caller != message.from_address {
+            return Err(L1Error::NotMessageSender { message_hash });
+        }
+        if self.l1_to_l2_messages.get(&message_hash) != Some(&L1ToL2MessageStatus::CancellationRequested) {
+            return Err(L1Error::CancellationNotRequested { message_hash });
+        }
+        let requested_at = self.cancellation_requests[&message_hash];
+        if requested_at.elapsed() < self.message_cancellation_delay {
+            return Err(L1Error::CancellationTooEarly { message_hash });
+        }
+
+        self.cancellation_requests.remove(&message_hash);
+        self.l1_to_l2_messages.insert(message_hash, L1ToL2MessageStatus::Cancelled);
+        Ok(())
+    }
+
+    /// `consumeMessageFromL2`: called by the message's L1 recipient. Each message sent
+    /// from L2 can be consumed exactly once, and only after its block was accepted here.
+    pub fn consume_message_from_l2(&mut self, caller: &str, from_address: &str, payload: &[String]) -> Result<String, L1Error> {
+        let message = L2ToL1Message {
+            from_address: from_address.to_string(),
+            to_address: caller.to_string(),
+            payload: payload.to_vec(),
+        };
+        let message_hash = message.hash();
+
+        match self.l2_to_l1_messages.get_mut(&message_hash) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                Ok(message_hash)
+            }
+            _ => Err(L1Error::L2ToL1MessageNotFound { message_hash }),
+        }
+    }
+
+    /// Copies of an L2→L1 message that can still be consumed
+    pub fn l2_to_l1_message_count(&self, message_hash: &str) -> u64 {
+        self.l2_to_l1_messages.get(message_hash).copied().unwrap_or(0)
+    }
+
+    /// `updateState(proof, state_diff)`: accepts the next block, or range of blocks, if its
+    /// proof is valid, it starts from the current state root, the diff is the one that was proven,
+    /// the STARK over the diff's balance updates verifies, and applying the diff to the current
+    /// state leads to the new state root it claims
+    pub fn update_state(&mut self, proof: &BlockProof, state_diff: &StateDiff) -> Result<(), L1Error> {
+        if !proof.is_valid() {
+            return Err(L1Error::InvalidProof { block_number: proof.block_number });
+        }
+        if proof.first_block_number != self.block_number + 1 || proof.block_number < proof.first_block_number {
+            return Err(L1Error::BlockNumberMismatch {
+                expected: self.block_number + 1,
+                got: proof.first_block_number,
+            });
+        }
+        if proof.prev_state_root != self.state_root {
+            return Err(L1Error::StateRootMismatch {
+                expected: self.state_root.clone(),
+                got: proof.prev_state_root.clone(),
+            });
+        }
+        if proof.state_diff_hash != state_diff.hash() {
+            return Err(L1Error::StateDiffMismatch { block_number: proof.block_number });
+        }
+        let public_input = PublicInput::for_state_transition(&proof.prev_state_root, state_diff);
+        stark::verify(&public_input, &proof.stark)
+            .map_err(|error| L1Error::InvalidStarkProof { block_number: proof.block_number, error })?;
+        let mut new_state = self.state.clone();
+        new_state.apply(state_diff);
+        let derived_state_root = new_state.state_root();
+        if proof.new_state_root != derived_state_root {
+            return Err(L1Error::NewStateRootMismatch {
+                block_number: proof.block_number,
+                expected: derived_state_root,
+                got: proof.new_state_root.clone(),
+            });
+        }
+        // Every message the block consumed must still be pending here, so L2 cannot
+        // invent deposits or consume a message the sender already cancelled
+        for message_hash in &proof.l1_to_l2_messages {
+            match self.l1_to_l2_messages.get(message_hash) {
+                Some(L1ToL2MessageStatus::Pending | L1ToL2MessageStatus::CancellationRequested) => {}
+                _ => return Err(L1Error::MessageNotFound { message_hash: message_hash.clone() }),
+            }
+        }
+
+        for message_hash in &proof.l1_to_l2_messages {
+            self.cancellation_requests.remove(message_hash);
+            self.l1_to_l2_messages.insert(message_hash.clone(), L1ToL2MessageStatus::Consumed);
+        }
+        // Messages sent from L2 only become consumable once their block is accepted
+        for message_hash in &proof.l2_to_l1_messages {
+            *self.l2_to_l1_messages.entry(message_hash.clone()).or_insert(0) += 1;
+        }
+
+        self.state_root = derived_state_root;
+        self.state = new_state;
+        self.block_number = proof.block_number;
+        // Off-chain, the committee keeps the data and L1 only records the commitment
+        let published_diff = match self.da_mode {
+            DaMode::Offchain { .. } => StateDiff::default(),
+            _ => state_diff.clone(),
+        };
+        self.state_updates.push(L1StateUpdate {
+            first_block_number: proof.first_block_number,
+            block_number: proof.block_number,
+            block_hash: proof.block_hash.clone(),
+            prev_state_root: proof.prev_state_root.clone(),
+            new_state_root: proof.new_state_root.clone(),
+            state_diff: published_diff,
+            da_mode: self.da_mode,
+            l1_to_l2_messages: proof.l1_to_l2_messages.clone(),
+            l2_to_l1_messages: proof.l2_to_l1_messages.clone(),
+        });
+        Ok(())
+    }
+}
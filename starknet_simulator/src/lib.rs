@@ -6,3 +6,16 @@ pub mod transaction;
 pub mod block;
 pub mod state;
 pub mod utils;
+pub mod gas;
+pub mod l1;
+pub mod contract;
+pub mod bridge;
+pub mod da;
+pub mod stark;
+pub mod pipeline;
+pub mod block_stm;
+pub mod account;
+pub mod event;
+pub mod codec;
+pub mod storage;
+pub mod execution;
@@ -3,23 +3,44 @@
 
 use std::collections::{VecDeque, HashMap, HashSet};
 use std::sync::{Arc, Mutex};
+use crate::account::{self, Validation, VALIDATE_ENTRY_POINT, VALIDATE_PAYMASTER_ENTRY_POINT};
+use crate::contract::{Contract, EntryPointType};
+use crate::execution::{self, BaseState};
+use crate::gas::{GasPrices, ResourceMeter};
+use crate::state::{StateOverlay, StateSnapshot};
+use crate::storage::NodeStore;
 use crate::transaction::{Transaction, TransactionStatus};
+use crate::utils::U256;
 
 pub struct Mempool {
     pub transactions: Arc<Mutex<VecDeque<Transaction>>>, //track all txs in mempool
     pub rejected_transactions: Arc<Mutex<HashSet<String>>>, // Track rejected tx hashes
-    pub balances: Arc<Mutex<HashMap<String, u64>>>, //track balances of all accounts
+    pub balances: Arc<Mutex<HashMap<String, U256>>>, //track balances of all accounts
     pub nonces: Arc<Mutex<HashMap<String, u64>>>, //track nonces of all accounts
+    pub storage: Arc<Mutex<HashMap<String, HashMap<String, U256>>>>, //contract storage: contract -> key -> value
+    pub contract_classes: Arc<Mutex<HashMap<String, String>>>, //class hash of every deployed contract
+    pub declared_classes: Arc<Mutex<HashMap<String, String>>>, //class hash -> compiled class hash
+    pub gas_prices: Arc<Mutex<GasPrices>>, //current gas prices, used to check fee bounds
+    pub accounts: Arc<Mutex<HashMap<String, Arc<dyn Contract>>>>, //account contracts by address; other senders are plain accounts
+    pub paymasters: Arc<Mutex<HashMap<String, Arc<dyn Contract>>>>, //paymaster contracts by address
+    pub validation_step_limit: u64, //steps an account's `__validate__` or a paymaster's `__validate_paymaster__` may use
+    pub store: Arc<Mutex<Option<NodeStore>>>, //where the node is saved, if anywhere; set by `Sequencer::open`
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Mempool {
     pub fn new() -> Self {
         let mut initial_balances = HashMap::new();
-        initial_balances.insert("Alice".to_string(), 200);
-        initial_balances.insert("Bob".to_string(), 500);
-        initial_balances.insert("Mark".to_string(), 0);
-        initial_balances.insert("Cyndie".to_string(), 700);
-        initial_balances.insert("Mike".to_string(), 90);
+        initial_balances.insert("Alice".to_string(), U256::from(200u64));
+        initial_balances.insert("Bob".to_string(), U256::from(500u64));
+        initial_balances.insert("Mark".to_string(), U256::ZERO);
+        initial_balances.insert("Cyndie".to_string(), U256::from(700u64));
+        initial_balances.insert("Mike".to_string(), U256::from(90u64));
 
         let mut initial_nonces = HashMap::new();
         initial_nonces.insert("Alice".to_string(), 0);
@@ -33,13 +54,74 @@ impl Mempool {
             rejected_transactions: Arc::new(Mutex::new(HashSet::new())), // Store rejected tx hashes
             balances: Arc::new(Mutex::new(initial_balances)),
             nonces: Arc::new(Mutex::new(initial_nonces)),
+            storage: Arc::new(Mutex::new(HashMap::new())),
+            contract_classes: Arc::new(Mutex::new(HashMap::new())),
+            declared_classes: Arc::new(Mutex::new(HashMap::new())),
+            gas_prices: Arc::new(Mutex::new(GasPrices::default())),
+            accounts: Arc::new(Mutex::new(HashMap::new())),
+            paymasters: Arc::new(Mutex::new(HashMap::new())),
+            validation_step_limit: account::DEFAULT_VALIDATION_STEP_LIMIT,
+            store: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Makes `address` an account contract: its transactions are accepted only if its own
+    /// `__validate__` accepts them. The deployment is published in the next block's state diff.
+    pub fn deploy_account(&self, address: &str, account: Arc<dyn Contract>) -> Result<(), String> {
+        if account.entry_point_type(VALIDATE_ENTRY_POINT) != Some(EntryPointType::External) {
+            return Err(format!("{} has no {} entry point", account.class_hash(), VALIDATE_ENTRY_POINT));
         }
+        println!("[Mempool] 🔐 Deployed account {} at {}", account.class_hash(), address);
+        self.contract_classes.lock().unwrap().insert(address.to_string(), account.class_hash());
+        self.accounts.lock().unwrap().insert(address.to_string(), account);
+        Ok(())
+    }
+
+    /// The account contract deployed at `address`, if any
+    pub fn account(&self, address: &str) -> Option<Arc<dyn Contract>> {
+        self.accounts.lock().unwrap().get(address).cloned()
+    }
+
+    /// Deploys a paymaster at `address`. Transactions naming it have their fee paid out of
+    /// its balance, if its `__validate_paymaster__` agrees.
+    pub fn deploy_paymaster(&self, address: &str, paymaster: Arc<dyn Contract>) -> Result<(), String> {
+        if paymaster.entry_point_type(VALIDATE_PAYMASTER_ENTRY_POINT) != Some(EntryPointType::External) {
+            return Err(format!("{} has no {} entry point", paymaster.class_hash(), VALIDATE_PAYMASTER_ENTRY_POINT));
+        }
+        println!("[Mempool] 🎁 Deployed paymaster {} at {}", paymaster.class_hash(), address);
+        self.contract_classes.lock().unwrap().insert(address.to_string(), paymaster.class_hash());
+        self.paymasters.lock().unwrap().insert(address.to_string(), paymaster);
+        Ok(())
+    }
+
+    /// The paymaster deployed at `address`, if any
+    pub fn paymaster(&self, address: &str) -> Option<Arc<dyn Contract>> {
+        self.paymasters.lock().unwrap().get(address).cloned()
+    }
+
+    /// Copies the current L2 state
+    pub fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            balances: self.balances.lock().unwrap().clone(),
+            nonces: self.nonces.lock().unwrap().clone(),
+            storage: self.storage.lock().unwrap().clone(),
+            contract_classes: self.contract_classes.lock().unwrap().clone(),
+            declared_classes: self.declared_classes.lock().unwrap().clone(),
+        }
+    }
+
+    /// Replaces the current L2 state with `state`
+    pub fn restore(&self, state: StateSnapshot) {
+        *self.balances.lock().unwrap() = state.balances;
+        *self.nonces.lock().unwrap() = state.nonces;
+        *self.storage.lock().unwrap() = state.storage;
+        *self.contract_classes.lock().unwrap() = state.contract_classes;
+        *self.declared_classes.lock().unwrap() = state.declared_classes;
     }
 
     //submit a transaction to the mempool, to be marked as RECEIVED
     pub fn submit_transaction(&self, mut tx: Transaction) {
-        let mut rejected_txs = self.rejected_transactions.lock().unwrap();
-        // let mut nonces = self.nonces.lock().unwrap();
+        let rejected_txs = self.rejected_transactions.lock().unwrap();
     
         let tx_hash = tx.get_hash();
     
@@ -52,70 +134,94 @@ impl Mempool {
             return;
         }
     
-        // Assign the latest nonce and increment it even if rejected
-        // let sender_nonce = nonces.entry(tx.sender.clone()).or_insert(0);
-        // tx.nonce = *sender_nonce;  // Assign nonce to transaction
-        // *sender_nonce += 1; // ✅ Increment nonce immediately
-
-        // ✅ Assign the latest nonce, but DO NOT increment here.
-        let nonces = self.nonces.lock().unwrap();
-        tx.nonce = *nonces.get(&tx.sender).unwrap_or(&0);
-    
+        // The nonce chosen by the sender is kept as-is and checked during validation
         tx.update_status(TransactionStatus::Received);
         let mut txs = self.transactions.lock().unwrap();
+        if let Some(store) = self.store.lock().unwrap().as_mut() {
+            if let Err(err) = store.queue_transaction(&tx) {
+                println!("[Mempool] ⚠️ Transaction {} was not saved: {}", tx.id, err);
+            }
+        }
         txs.push_back(tx);
     }
 
-    //initial check to validate tx requirements
-    pub fn validate_transaction(&self) -> Option<Transaction> {
+    /// Dry-runs the validation entry points of the sender's account contract and of the paymaster
+    /// on top of `balances`, as the sequencer runs them in a block with `block_timestamp`
+    fn run_validation(
+        &self,
+        tx: &Transaction,
+        paymaster: Option<Arc<dyn Contract>>,
+        balances: &HashMap<String, U256>,
+        block_timestamp: u64,
+    ) -> Result<(), String> {
+        let storage = self.storage.lock().unwrap();
+        let validation = Validation {
+            committed_balances: balances,
+            committed_storage: &storage,
+            block_timestamp,
+            step_limit: self.validation_step_limit,
+        };
+        let mut overlay = StateOverlay::default();
+        let mut meter = ResourceMeter::new();
+        if let Some(account) = self.account(&tx.sender) {
+            account::validate(account.as_ref(), tx, &mut overlay, &mut meter, &validation)
+                .map_err(|reason| format!("__validate__ failed: {}", reason))?;
+        }
+        if let Some(paymaster) = paymaster {
+            account::validate_paymaster(paymaster.as_ref(), tx, &mut overlay, &mut meter, &validation)
+                .map_err(|reason| format!("__validate_paymaster__ failed: {}", reason))?;
+        }
+        Ok(())
+    }
+
+    //initial check to validate tx requirements, for the block with timestamp `block_timestamp`
+    pub fn validate_transaction(&self, block_timestamp: u64) -> Option<Transaction> {
         let mut txs = self.transactions.lock().unwrap();
-        let mut balances = self.balances.lock().unwrap();     
+        let balances = self.balances.lock().unwrap();     
         let mut rejected_txs = self.rejected_transactions.lock().unwrap();
     
-        if let Some(mut tx) = txs.pop_front() {
+        let next = Self::next_by_priority(&txs);
+        if let Some(mut tx) = next.and_then(|index| txs.remove(index)) {
             println!("[Mempool] is now validating transaction ID: {}", tx.id);
     
-            // ✅ 1. Check Nonce (Prevents Replay Attacks)
-            let mut nonces = self.nonces.lock().unwrap();
-            let sender_nonce = nonces.get(&tx.sender).unwrap_or(&0);
-            if tx.nonce != *sender_nonce {
-            println!(
-                "[Mempool] ❌ Transaction {} is rejected! Incorrect nonce. Expected: {}",
-                tx.id, *sender_nonce
-            );
-            tx.update_status(TransactionStatus::Rejected);
-            return None;
-            }
-    
-            // ✅ 2. Check If Sender Has Enough Funds for Fee + Amount
-            let sender_balance = balances.get(&tx.sender).unwrap_or(&0);
-            
-            if *sender_balance <= tx.fee {
-                println!(
-                    "[Mempool] ❌ Transaction {} is rejected! Account balance must be greater than the fee.",
-                    tx.id
-                );
+            // ✅ 1. Check The Paymaster Exists, If The Tx Names One: It Pays The Fee Instead Of The Sender
+            let paymaster = match &tx.paymaster {
+                Some(address) => match self.paymaster(address) {
+                    Some(paymaster) => Some(paymaster),
+                    None => {
+                        println!("[Mempool] ❌ Transaction {} is rejected! No paymaster deployed at {}.", tx.id, address);
+                        tx.update_status(TransactionStatus::Rejected);
+                        return None;
+                    }
+                },
+                None => None,
+            };
+
+            // ✅ 2. Check Nonce (Prevents Replay Attacks), The Fee Limits Against The Current Gas
+            // Prices, And That The Fee Payer Can Pay The Max Fee. What the calls spend is up to `__execute__`
+            let nonces = self.nonces.lock().unwrap();
+            let gas_prices = *self.gas_prices.lock().unwrap();
+            let admission = execution::check_admission(&tx, &mut BaseState { balances: &balances, nonces: &nonces }, &gas_prices);
+            if let Err(rejection) = admission {
+                println!("[Mempool] ❌ Transaction {} is rejected! {}", tx.id, rejection.reason);
                 tx.update_status(TransactionStatus::Rejected);
-                rejected_txs.insert(tx.get_hash()); // Store rejected tx hash
-                return None;
-            }
-    
-            if let Some(amount) = tx.amount {
-                let total_cost = amount + tx.fee;
-                if *sender_balance < total_cost {
-                    println!(
-                        "[Mempool] ❌ Transaction {} is rejected! Insufficient balance for transfer + fee.",
-                        tx.id
-                    );
-                    tx.update_status(TransactionStatus::Rejected);
+                if rejection.remember {
                     rejected_txs.insert(tx.get_hash()); // Store rejected tx hash
-                    return None;
                 }
+                return None;
             }
-    
-            // ✅ 3. Deduct Fee After Validation (Only If Passed)
-            *balances.get_mut(&tx.sender).unwrap() -= tx.fee;
-    
+
+            // ✅ 3. Run The Account's Own `__validate__` And The Paymaster's `__validate_paymaster__`,
+            // Each Within The Step Budget. Their writes are dropped: the sequencer runs them again
+            // as the first phase of the transaction. A refused transaction may be resent with a
+            // better signature, which is not part of its hash
+            if let Err(reason) = self.run_validation(&tx, paymaster, &balances, block_timestamp) {
+                println!("[Mempool] ❌ Transaction {} is rejected! {}", tx.id, reason);
+                tx.update_status(TransactionStatus::Rejected);
+                return None;
+            }
+
+            // The nonce is consumed by the sequencer, once the transaction passes its `__validate__` there
             tx.update_status(TransactionStatus::Validated);
             println!("[Mempool] ✅ Transaction {} is validated!", tx.id);
             return Some(tx);
@@ -123,4 +229,58 @@ impl Mempool {
     
         None    
     }
+
+    /// Removes up to `max` transactions in the order `validate_transaction` would pick them,
+    /// stopping at the first one `take` refuses. Nothing is validated.
+    pub fn take_transactions(&self, max: usize, take: impl Fn(&Transaction) -> bool) -> Vec<Transaction> {
+        let mut txs = self.transactions.lock().unwrap();
+        let mut taken = vec![];
+        while taken.len() < max {
+            let Some(index) = Self::next_by_priority(&txs).filter(|&index| take(&txs[index])) else {
+                break;
+            };
+            taken.extend(txs.remove(index));
+        }
+        taken
+    }
+
+    /// Puts transactions from `take_transactions` back at the front of the queue, where they
+    /// are picked again in the same order
+    pub fn return_transactions(&self, returned: Vec<Transaction>) {
+        let mut txs = self.transactions.lock().unwrap();
+        for tx in returned.into_iter().rev() {
+            txs.push_front(tx);
+        }
+    }
+
+    /// Picks the next tx to validate: the highest tip wins, but each sender's txs keep
+    /// their submission order so nonces stay sequential. Equal tips stay FIFO.
+    fn next_by_priority(txs: &VecDeque<Transaction>) -> Option<usize> {
+        let mut seen_senders = HashSet::new();
+        let mut best: Option<usize> = None;
+        for (index, tx) in txs.iter().enumerate() {
+            if !seen_senders.insert(tx.sender.as_str()) {
+                continue;
+            }
+            if best.is_none_or(|best| tx.tip > txs[best].tip) {
+                best = Some(index);
+            }
+        }
+        best
+    }
+
+    /// Suggests the nonce for the next transaction from `sender`, counting transactions that are
+    /// still waiting in the mempool. Only a hint for clients: the mempool never changes the nonce
+    /// a transaction was signed with, and checks it against the committed nonce at validation.
+    pub fn next_nonce(&self, sender: &str) -> u64 {
+        let nonce = *self.nonces.lock().unwrap().get(sender).unwrap_or(&0);
+        let queued = self
+            .transactions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|tx| tx.sender == sender)
+            .count() as u64;
+        nonce + queued
+    }
 }
@@ -0,0 +1,250 @@
+//pipeline
+//the mempool, sequencer, prover and verifier run as worker threads connected by channels,
+//so transactions keep coming in while earlier blocks are being built, proven and settled
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::block::{BlockStatus, L2Block, PendingBlock};
+use crate::mempool::Mempool;
+use crate::prover::Prover;
+use crate::sequencer::Sequencer;
+use crate::transaction::Transaction;
+use crate::verifier::Verifier;
+
+/// How often the workers wake up on their own. When the sequencer seals blocks is up to
+/// its `BlockPolicy`; it checks the policy on every wake-up and whenever a transaction arrives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineConfig {
+    pub poll_interval: Duration,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        PipelineConfig { poll_interval: Duration::from_millis(500) }
+    }
+}
+
+/// Throughput and queue depths at one point in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PipelineStats {
+    pub elapsed: Duration,
+    pub submitted: usize, // sent to the pipeline
+    pub in_channel: usize, // sent but not yet in the mempool
+    pub in_mempool: usize, // waiting for the sequencer
+    pub in_pending_block: usize, // executed, in the block being built
+    pub blocks_produced: usize,
+    pub awaiting_proof: usize, // blocks accepted on L2 or being proven
+    pub awaiting_l1: usize, // blocks proven or submitted to L1
+    pub blocks_settled: usize,
+    pub blocks_rejected: usize,
+    pub transactions_included: usize,
+    pub transactions_settled: usize,
+}
+
+impl PipelineStats {
+    /// Transactions settled on L1 per second
+    pub fn settled_tps(&self) -> f64 {
+        self.transactions_settled as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Transactions included in L2 blocks per second
+    pub fn included_tps(&self) -> f64 {
+        self.transactions_included as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// The running pipeline. Each stage is a thread that waits on the channel from the stage before it
+/// (or times out and rechecks its queue), and only holds a lock while it reads or writes shared state.
+pub struct Pipeline {
+    submissions: Option<Sender<Transaction>>,
+    stopping: Arc<AtomicBool>,
+    submitted: Arc<AtomicUsize>,
+    received: Arc<AtomicUsize>,
+    mempool: Arc<Mempool>,
+    blocks: Arc<Mutex<Vec<L2Block>>>,
+    pending_block: Arc<Mutex<Option<PendingBlock>>>,
+    started: Instant,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Pipeline {
+    /// Spawns the mempool, sequencer, prover and verifier workers
+    pub fn start(sequencer: Arc<Mutex<Sequencer>>, prover: Prover, verifier: Verifier, config: PipelineConfig) -> Self {
+        let (mempool, blocks, pending_block) = {
+            let sequencer = sequencer.lock().unwrap();
+            (sequencer.mempool.clone(), sequencer.blocks.clone(), sequencer.pending_block.clone())
+        };
+        let (submissions, submission_rx) = mpsc::channel::<Transaction>();
+        let (work_tx, work_rx) = mpsc::channel::<()>();
+        let (sealed_tx, sealed_rx) = mpsc::channel::<u64>();
+        let (proven_tx, proven_rx) = mpsc::channel::<()>();
+        let stopping = Arc::new(AtomicBool::new(false));
+        let submitted = Arc::new(AtomicUsize::new(0));
+        let received = Arc::new(AtomicUsize::new(0));
+
+        let workers = vec![
+            Self::spawn_mempool(mempool.clone(), submission_rx, work_tx, stopping.clone(), received.clone(), config),
+            Self::spawn_sequencer(sequencer, work_rx, sealed_tx, config),
+            Self::spawn_prover(prover, sealed_rx, proven_tx, config),
+            Self::spawn_verifier(verifier, proven_rx, config),
+        ];
+
+        Pipeline {
+            submissions: Some(submissions),
+            stopping,
+            submitted,
+            received,
+            mempool,
+            blocks,
+            pending_block,
+            started: Instant::now(),
+            workers,
+        }
+    }
+
+    /// Hands a transaction to the mempool worker without waiting for it
+    pub fn submit(&self, tx: Transaction) {
+        if let Some(submissions) = &self.submissions {
+            if submissions.send(tx).is_ok() {
+                self.submitted.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> PipelineStats {
+        // Read before locking the chain: the sequencer locks the pending block first when it seals
+        let in_pending_block = self.pending_block.lock().unwrap().as_ref().map_or(0, |b| b.transactions.len());
+        let blocks = self.blocks.lock().unwrap();
+        let count = |matches: fn(&BlockStatus) -> bool| blocks.iter().filter(|b| matches(&b.header.block_status)).count();
+        let submitted = self.submitted.load(Ordering::SeqCst);
+        PipelineStats {
+            elapsed: self.started.elapsed(),
+            submitted,
+            in_channel: submitted.saturating_sub(self.received.load(Ordering::SeqCst)),
+            in_mempool: self.mempool.transactions.lock().unwrap().len(),
+            in_pending_block,
+            blocks_produced: blocks.len(),
+            awaiting_proof: count(|s| matches!(s, BlockStatus::AcceptedOnL2 | BlockStatus::ProofRequested)),
+            awaiting_l1: count(|s| matches!(s, BlockStatus::Proven | BlockStatus::SubmittedToL1)),
+            blocks_settled: count(|s| *s == BlockStatus::AcceptedOnL1),
+            blocks_rejected: count(|s| matches!(s, BlockStatus::Rejected { .. })),
+            transactions_included: blocks.iter().map(|b| b.header.transaction_count).sum(),
+            transactions_settled: blocks
+                .iter()
+                .filter(|b| b.header.block_status == BlockStatus::AcceptedOnL1)
+                .map(|b| b.header.transaction_count)
+                .sum(),
+        }
+    }
+
+    /// Stops taking submissions and lets every stage drain in order: the mempool takes what is
+    /// still in its channel, the sequencer seals a last block, and the prover and verifier
+    /// finish whatever is ready
+    pub fn shutdown(mut self) -> PipelineStats {
+        self.submissions = None;
+        self.stopping.store(true, Ordering::SeqCst);
+        for worker in self.workers.drain(..) {
+            worker.join().expect("pipeline worker panicked");
+        }
+        self.stats()
+    }
+
+    fn spawn_mempool(
+        mempool: Arc<Mempool>,
+        submissions: Receiver<Transaction>,
+        work: Sender<()>,
+        stopping: Arc<AtomicBool>,
+        received: Arc<AtomicUsize>,
+        config: PipelineConfig,
+    ) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            match submissions.recv_timeout(config.poll_interval) {
+                Ok(tx) => {
+                    mempool.submit_transaction(tx);
+                    received.fetch_add(1, Ordering::SeqCst);
+                    let _ = work.send(());
+                }
+                Err(RecvTimeoutError::Timeout) if !stopping.load(Ordering::SeqCst) => {}
+                // Dropping `work` tells the sequencer nothing more is coming
+                Err(_) => break,
+            }
+        })
+    }
+
+    fn spawn_sequencer(sequencer: Arc<Mutex<Sequencer>>, work: Receiver<()>, sealed: Sender<u64>, config: PipelineConfig) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            let closed = match work.recv_timeout(config.poll_interval) {
+                Ok(()) => false,
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => true,
+            };
+
+            let mut sequencer = sequencer.lock().unwrap();
+            if !closed {
+                let block_number = sequencer.block_number;
+                if sequencer.produce_block_if_due() {
+                    let _ = sealed.send(block_number);
+                }
+                continue;
+            }
+
+            // Nothing more is coming: seal blocks until the mempool is empty or stops shrinking
+            loop {
+                let waiting = sequencer.mempool.transactions.lock().unwrap().len();
+                if waiting == 0 && !sequencer.core_contract.lock().unwrap().has_pending_messages() {
+                    break;
+                }
+                let block_number = sequencer.block_number;
+                sequencer.process_transactions();
+                if sequencer.block_number > block_number {
+                    let _ = sealed.send(block_number);
+                } else if sequencer.mempool.transactions.lock().unwrap().len() >= waiting {
+                    break;
+                }
+            }
+            break;
+        })
+    }
+
+    fn spawn_prover(prover: Prover, sealed: Receiver<u64>, proven: Sender<()>, config: PipelineConfig) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            let closed = match sealed.recv_timeout(config.poll_interval) {
+                Ok(_) => false,
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => true,
+            };
+            // Blocks the verifier rejected go back in the queue, or settlement would stop at them.
+            // A partial job may become ready only because time passed, so every wake-up rechecks the queue
+            prover.reprove_rejected();
+            if prover.has_job_ready() {
+                if let Err(err) = prover.verify_proof() {
+                    println!("[Prover] ❌ Proof job aborted: {}", err);
+                }
+                let _ = proven.send(());
+            }
+            if closed {
+                break;
+            }
+        })
+    }
+
+    fn spawn_verifier(verifier: Verifier, proven: Receiver<()>, config: PipelineConfig) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            let closed = match proven.recv_timeout(config.poll_interval) {
+                Ok(()) => false,
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => true,
+            };
+            // Blocks deferred for DA capacity are retried on the next wake-up, i.e. the next L1 block
+            if verifier.has_submission_ready() {
+                verifier.verify_proofs();
+            }
+            if closed {
+                break;
+            }
+        })
+    }
+}
@@ -1,52 +1,320 @@
 use std::sync::{Arc, Mutex};
-use crate::block::{L2Block, BlockStatus};
+use crate::block::{L2Block, BlockStatus, InvalidTransition};
 use crate::sequencer::Sequencer;
+use crate::stark::{self, PublicInput, StarkProof};
+use crate::state::StateDiff;
+use crate::utils::U256;
 use sha2::{Sha256, Digest};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-/// Represents the Prover responsible for generating and verifying STARK proofs
+/// Proof of the state transition of a range of consecutive blocks, with the public
+/// outputs L1 checks it against. A single-block proof has `first_block_number == block_number`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockProof {
+    pub first_block_number: u64,
+    pub block_number: u64, // last block of the range
+    pub block_hash: String, // hash of the last block
+    pub block_hashes: Vec<String>, // every block in the range, in order
+    pub prev_state_root: String, // before the first block
+    pub new_state_root: String, // after the last block
+    pub state_diff_hash: String, // hash of the squashed diff of the range
+    pub l1_to_l2_messages: Vec<String>, // hashes of the L1→L2 messages the blocks consumed
+    pub l2_to_l1_messages: Vec<String>, // hashes of the L2→L1 messages the blocks sent
+    pub proof: String, // digest over the public outputs
+    pub stark: StarkProof, // proves the balance updates of the squashed state diff
+}
+
+impl BlockProof {
+    fn digest(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(format!(
+            "{}-{}-{}-{}-{}-{}-{}-{}-{}-{}-{}",
+            self.first_block_number,
+            self.block_number,
+            self.block_hash,
+            self.block_hashes.join(","),
+            self.prev_state_root,
+            self.new_state_root,
+            self.state_diff_hash,
+            self.l1_to_l2_messages.join(","),
+            self.l2_to_l1_messages.join(","),
+            self.stark.trace_root,
+            self.stark.output
+        ));
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Checks the proof matches its public outputs
+    pub fn is_valid(&self) -> bool {
+        self.proof == self.digest()
+    }
+
+    /// Number of blocks the proof covers
+    pub fn block_count(&self) -> u64 {
+        self.block_number - self.first_block_number + 1
+    }
+}
+
+/// One SHARP-style proving job and what it cost
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofJob {
+    pub first_block_number: u64,
+    pub last_block_number: u64,
+    pub transaction_count: usize,
+    pub steps: u64,
+    pub proving_cost: U256,
+}
+
+impl ProofJob {
+    /// Proving cost amortized over every transaction in the job
+    pub fn cost_per_transaction(&self) -> U256 {
+        if self.transaction_count == 0 {
+            return self.proving_cost;
+        }
+        self.proving_cost.div_rem_u64(self.transaction_count as u64).0
+    }
+}
+
+/// Represents the Prover responsible for generating and verifying STARK proofs.
+///
+/// Owns the proving stage of the block pipeline: blocks go from `AcceptedOnL2` to `Proven`.
+/// Works as a proof job scheduler: unproven blocks are collected until
+/// `max_blocks_per_job` are waiting, or until the oldest one has waited `max_wait`,
+/// and each job yields one proof over its whole range.
 pub struct Prover {
-    pub sequencer: Arc<Mutex<Sequencer>>,
+    pub blocks: Arc<Mutex<Vec<L2Block>>>, // the sequencer's chain; the sequencer itself is never locked
+    pub proving_delay: Duration, // simulated proving time per job
+    pub max_blocks_per_job: usize,
+    pub max_wait: Duration, // a partial job is started once its oldest block has waited this long
+    pub fixed_cost_per_job: U256, // paid once per proof, mostly verifying it on L1
+    pub cost_per_1k_steps: U256,
+    pub jobs: Mutex<Vec<ProofJob>>, // every job run so far
 }
 
 impl Prover {
     /// Creates a new Prover instance
     pub fn new(sequencer: Arc<Mutex<Sequencer>>) -> Self {
-        Prover { sequencer }
+        let blocks = sequencer.lock().unwrap().blocks.clone();
+        Prover {
+            blocks,
+            proving_delay: Duration::from_secs(2),
+            max_blocks_per_job: 1,
+            max_wait: Duration::ZERO,
+            fixed_cost_per_job: U256::from(10_000u64),
+            cost_per_1k_steps: U256::ONE,
+            jobs: Mutex::new(vec![]),
+        }
     }
 
     /// Generates a proof for a block (simulated)
-    pub fn generate_proof(&self, block_hash: &str) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(block_hash);
-        format!("{:x}", hasher.finalize())
+    pub fn generate_proof(&self, block: &L2Block) -> BlockProof {
+        self.generate_aggregated_proof(std::slice::from_ref(block))
     }
 
-    /// Verifies the proof and updates block status to `AcceptedOnL1`
-    pub fn verify_proof(&self) {
-        let sequencer = self.sequencer.lock().unwrap();
-        let mut blocks = sequencer.blocks.lock().unwrap();
+    /// Generates one proof over consecutive blocks: a toy STARK over the balance updates
+    /// of their squashed state diff, bound to the blocks' public outputs
+    pub fn generate_aggregated_proof(&self, blocks: &[L2Block]) -> BlockProof {
+        let first = blocks.first().expect("a proof covers at least one block");
+        let last = blocks.last().expect("a proof covers at least one block");
+        let state_diff = StateDiff::squash(blocks.iter().map(|b| &b.state_diff));
+        let public_input = PublicInput::for_state_transition(&first.header.parent_state_root, &state_diff);
+
+        let mut proof = BlockProof {
+            first_block_number: first.header.block_number,
+            block_number: last.header.block_number,
+            block_hash: last.get_block_hash(),
+            block_hashes: blocks.iter().map(|b| b.get_block_hash()).collect(),
+            prev_state_root: first.header.parent_state_root.clone(),
+            new_state_root: last.header.state_root.clone(),
+            state_diff_hash: state_diff.hash(),
+            l1_to_l2_messages: blocks.iter().flat_map(|b| b.l1_to_l2_messages.iter().cloned()).collect(),
+            l2_to_l1_messages: blocks
+                .iter()
+                .flat_map(|b| b.l2_to_l1_messages())
+                .map(|m| m.hash())
+                .collect(),
+            proof: String::new(),
+            stark: stark::prove(&public_input),
+        };
+        proof.proof = proof.digest();
+        proof
+    }
 
-        let mut verified_any = false;
+    /// Runs every proof job that is ready, moving its blocks from `AcceptedOnL2` to `Proven`.
+    /// The blocks are marked `ProofRequested` and copied out of the chain, then proven without
+    /// holding any lock, so the sequencer keeps producing blocks while a job runs.
+    /// Fails if a block of the job was moved on by someone else in the meantime; the rest of
+    /// that job is then put back in the queue unproven.
+    pub fn verify_proof(&self) -> Result<(), InvalidTransition> {
+        let mut proved_any = false;
 
-        for block in blocks.iter_mut().filter(|b| b.header.block_status == BlockStatus::AcceptedOnL2) {
-            println!("[Prover] 🔍 Verifying proof for Block #{}...", block.header.block_number);
-            thread::sleep(Duration::from_secs(2)); // Simulating verification delay
+        while let Some(batch) = self.next_job()? {
+            let first_block_number = batch[0].header.block_number;
+            let last_block_number = batch[batch.len() - 1].header.block_number;
+            println!(
+                "[Prover] 🔍 Proving Blocks #{}..#{} in one job...",
+                first_block_number, last_block_number
+            );
+            thread::sleep(self.proving_delay); // Simulating proving time
 
-            // Simulate proof generation
-            let proof = self.generate_proof(&block.get_block_hash());
-            block.header.block_status = BlockStatus::AcceptedOnL1;
+            let proof = self.generate_aggregated_proof(&batch);
+            let job = self.job_cost(&batch);
 
-            println!("[Prover] ✅ Block #{} verified and proof generated!", block.header.block_number);
-            println!("🌍 Block #{} Proof Generated ✅", block.header.block_number);
-            println!("🔗 Proof: {}\n", proof);
+            {
+                let mut blocks = self.blocks.lock().unwrap();
+                let mut job_blocks: Vec<&mut L2Block> = blocks
+                    .iter_mut()
+                    .filter(|b| batch.iter().any(|proven| proven.header.block_number == b.header.block_number))
+                    .collect();
+                // The job is applied to every block or to none: if one of them was moved on,
+                // the others go back to the queue instead of waiting for a proof forever
+                if let Some(moved) = job_blocks.iter().find(|b| !b.header.block_status.can_transition_to(&BlockStatus::Proven)) {
+                    let err = InvalidTransition {
+                        block_number: moved.header.block_number,
+                        from: moved.header.block_status.clone(),
+                        to: BlockStatus::Proven,
+                    };
+                    for block in job_blocks.iter_mut().filter(|b| b.header.block_status == BlockStatus::ProofRequested) {
+                        block.transition(BlockStatus::AcceptedOnL2)?;
+                    }
+                    return Err(err);
+                }
+                for block in job_blocks {
+                    block.transition(BlockStatus::Proven)?;
+                    block.proof = Some(proof.clone());
+                }
+            }
 
-            verified_any = true;
+            println!(
+                "[Prover] ✅ Blocks #{}..#{} proven together: {} transaction(s), {} steps",
+                first_block_number, last_block_number, job.transaction_count, job.steps
+            );
+            println!(
+                "💸 Proving cost: {} for the job, {} per transaction",
+                job.proving_cost,
+                job.cost_per_transaction()
+            );
+            println!(
+                "🧮 STARK: {} trace rows, {} FRI layers, {} queries, ~{} bytes",
+                proof.stark.trace_length,
+                proof.stark.fri_roots.len(),
+                proof.stark.queries.len(),
+                proof.stark.size_in_bytes()
+            );
+            println!("🔗 Proof: {}\n", proof.proof);
+
+            self.jobs.lock().unwrap().push(job);
+            proved_any = true;
         }
 
-        if !verified_any {
+        if !proved_any {
             println!("[Prover] ⚠️ No new L2 blocks available for proving.");
         }
+        Ok(())
+    }
+
+    /// Puts blocks whose proof was rejected back in the proving queue, dropping the old proof.
+    /// Returns how many blocks were re-queued.
+    pub fn reprove_rejected(&self) -> usize {
+        let mut blocks = self.blocks.lock().unwrap();
+        let mut requeued = 0;
+        for block in blocks.iter_mut() {
+            if let BlockStatus::Rejected { reason } = &block.header.block_status {
+                println!(
+                    "[Prover] 🔁 Re-proving Block #{} (rejected: {})",
+                    block.header.block_number, reason
+                );
+                block.transition(BlockStatus::AcceptedOnL2).expect("rejected blocks can be re-queued");
+                block.proof = None;
+                requeued += 1;
+            }
+        }
+        requeued
+    }
+
+    /// Whether a proof job could start now
+    pub fn has_job_ready(&self) -> bool {
+        let blocks = self.blocks.lock().unwrap();
+        let waiting = &blocks[Self::waiting_run(&blocks)];
+        waiting.first().is_some_and(|oldest| self.job_size(waiting.len(), oldest.header.block_timestamp).is_ok())
     }
-}
\ No newline at end of file
+
+    /// Indexes of the blocks a job may cover: the run of consecutive `AcceptedOnL2` blocks
+    /// starting at the lowest one. An aggregated proof only covers consecutive blocks, so a
+    /// block still being proven or settled ends the run.
+    fn waiting_run(blocks: &[L2Block]) -> std::ops::Range<usize> {
+        let is_waiting = |b: &L2Block| b.header.block_status == BlockStatus::AcceptedOnL2;
+        let Some(start) = blocks.iter().position(is_waiting) else {
+            return 0..0;
+        };
+        let len = blocks[start..]
+            .iter()
+            .zip(blocks[start].header.block_number..)
+            .take_while(|(b, block_number)| is_waiting(b) && b.header.block_number == *block_number)
+            .count();
+        start..start + len
+    }
+
+    /// Picks the next batch of blocks waiting for a proof, if one is ready, and marks them `ProofRequested`
+    fn next_job(&self) -> Result<Option<Vec<L2Block>>, InvalidTransition> {
+        let mut blocks = self.blocks.lock().unwrap();
+        let run = Self::waiting_run(&blocks);
+        let waiting = &mut blocks[run];
+        let Some(oldest) = waiting.first() else {
+            return Ok(None);
+        };
+
+        let job_size = match self.job_size(waiting.len(), oldest.header.block_timestamp) {
+            Ok(job_size) => job_size,
+            Err(waited) => {
+                println!(
+                    "[Prover] ⏳ {} block(s) waiting for a job of {} (oldest waited {}s of {}s).",
+                    waiting.len(),
+                    self.max_blocks_per_job.max(1),
+                    waited.as_secs(),
+                    self.max_wait.as_secs()
+                );
+                return Ok(None);
+            }
+        };
+
+        let mut batch = vec![];
+        for block in waiting.iter_mut().take(job_size) {
+            block.transition(BlockStatus::ProofRequested)?;
+            batch.push(block.clone());
+        }
+        Ok(Some(batch))
+    }
+
+    /// Size of the job to start with `waiting` blocks queued, or how long the oldest has waited
+    /// if the job should wait for more blocks
+    fn job_size(&self, waiting: usize, oldest_timestamp: u64) -> Result<usize, Duration> {
+        let job_size = self.max_blocks_per_job.max(1);
+        if waiting < job_size {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+            let waited = Duration::from_secs(now.saturating_sub(oldest_timestamp));
+            if waited < self.max_wait {
+                return Err(waited);
+            }
+        }
+        Ok(job_size)
+    }
+
+    /// Cost of proving a batch: a fixed cost per job plus a cost per Cairo step
+    fn job_cost(&self, batch: &[L2Block]) -> ProofJob {
+        let receipts = || batch.iter().flat_map(|b| b.receipts.iter());
+        let steps: u64 = receipts().map(|r| r.resources.steps).sum();
+        let step_cost = U256::from(steps.div_ceil(1_000))
+            .checked_mul(self.cost_per_1k_steps)
+            .unwrap_or(U256::MAX);
+
+        ProofJob {
+            first_block_number: batch[0].header.block_number,
+            last_block_number: batch[batch.len() - 1].header.block_number,
+            transaction_count: receipts().count(),
+            steps,
+            proving_cost: self.fixed_cost_per_job.checked_add(step_cost).unwrap_or(U256::MAX),
+        }
+    }
+}
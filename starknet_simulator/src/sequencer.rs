@@ -1,236 +1,951 @@
 use crate::mempool::Mempool;
-use crate::transaction::{Transaction, TransactionStatus, TransactionType};
-use crate::block::{L2Block, BlockStatus};
+use crate::account::{self, Validation};
+use crate::block_stm::{self, StateKey, StateValue, TransferOutcome};
+use crate::contract::{self, Contract, EntryPointType, ExecutionContext, TxInfo};
+use crate::execution::{self, BaseState};
+use crate::gas::{self, FeeMarket, GasPrices, ResourceMeter};
+use std::collections::{BTreeMap, HashMap};
+use crate::state::{self, StateDiff, StateOverlay, StateSnapshot, FEE_TOKEN_ADDRESS};
+use crate::storage::{NodeStore, Storage, StorageError};
+use crate::transaction::{PhaseOutcome, Transaction, TransactionPhases, TransactionReceipt, TransactionStatus, TransactionType};
+use crate::block::{L2Block, PendingBlock};
+use crate::event::{self, EventFilter, EventQueryError, EventsChunk};
+use crate::l1::{ConstantGasPrice, L1GasPriceSource, L1ToL2Message, L2ToL1Message, StarknetCoreContract};
+use crate::utils::U256;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// When the sequencer closes a block. A block is closed once it holds `max_transactions`,
+/// once it has used `max_l2_gas` (the transaction that crosses the limit is the last one in),
+/// or, for the block producer, once `block_time` has passed since the previous block.
+/// Whatever does not fit stays in the mempool for the next block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockPolicy {
+    pub max_transactions: usize,
+    pub max_l2_gas: u64,
+    pub block_time: Duration,
+}
+
+impl Default for BlockPolicy {
+    fn default() -> Self {
+        BlockPolicy {
+            max_transactions: 50,
+            max_l2_gas: FeeMarket::default().max_l2_gas,
+            block_time: Duration::from_secs(10),
+        }
+    }
+}
+
+/// How the sequencer runs the transactions of a block
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExecutionMode {
+    /// One transaction at a time, in block order
+    #[default]
+    Sequential,
+    /// Runs of plain transfers execute optimistically on `workers` threads under Block-STM,
+    /// with the same result as running them in order. Other transactions still run one at a time.
+    Parallel { workers: usize },
+}
 
 pub struct Sequencer {
     pub mempool: Arc<Mempool>,
     pub executed_txs: Arc<Mutex<Vec<Transaction>>>,
     pub blocks: Arc<Mutex<Vec<L2Block>>>,
+    pub pending_block: Arc<Mutex<Option<PendingBlock>>>, // the block being built, readable while it fills up
     pub block_number: u64,
     pub parent_block_hash: String,
+    pub state_root: String, // state root after the latest block (genesis root before any block)
+    pub committed_state: StateSnapshot, // state after the latest block; the next block's diff is taken against it
     pub sequencer_address: String,
+    pub fee_market: FeeMarket,
+    pub l1_gas_oracle: Box<dyn L1GasPriceSource>, // where L1 gas prices come from, read once per block
+    pub core_contract: Arc<Mutex<StarknetCoreContract>>, // Starknet core contract on the simulated L1
+    pub contracts: HashMap<String, Arc<dyn Contract>>, // deployed contracts by address
+    pub processing_delay: Duration, // pause between txs so the CLI output is readable
+    pub block_policy: BlockPolicy,
+    pub execution_mode: ExecutionMode,
+    pub last_block_at: Instant, // when the previous block was sealed (or the sequencer started)
 }
 
 impl Sequencer {
     pub fn new(mempool: Arc<Mempool>) -> Self {
+        let genesis_state = mempool.snapshot();
+        let genesis_state_root = genesis_state.state_root();
         Sequencer {
             mempool,
             executed_txs: Arc::new(Mutex::new(vec![])),
             blocks: Arc::new(Mutex::new(vec![])),
+            pending_block: Arc::new(Mutex::new(None)),
             block_number: 1,
             parent_block_hash: "genesis_hash".to_string(),
+            state_root: genesis_state_root.clone(),
+            committed_state: genesis_state.clone(),
             sequencer_address: "sequencer_0x123".to_string(),
+            fee_market: FeeMarket::default(),
+            l1_gas_oracle: Box::new(ConstantGasPrice::default()),
+            core_contract: Arc::new(Mutex::new(StarknetCoreContract::new(genesis_state.clone()))),
+            contracts: HashMap::new(),
+            processing_delay: Duration::from_secs(1),
+            block_policy: BlockPolicy::default(),
+            execution_mode: ExecutionMode::Sequential,
+            last_block_at: Instant::now(),
         }
     }
 
+    /// Opens a node that saves its blocks, state and mempool to `storage`. If `storage` already
+    /// holds a chain, the node resumes after its latest block, with the state, gas prices and
+    /// mempool saved with it; otherwise it starts from the state of `mempool`, as `new` does.
+    ///
+    /// Contract code is not saved, so contracts, accounts and paymasters have to be deployed
+    /// again. The simulated L1 is not part of the node either: it starts over at the saved
+    /// genesis state, and the restored blocks are proven and settled again.
+    pub fn open(mempool: Arc<Mempool>, storage: impl Storage + 'static) -> Result<Self, StorageError> {
+        let mut store = NodeStore::new(Box::new(storage))?;
+        let saved = store.load()?;
+        if let Some(state) = saved.state {
+            mempool.restore(state);
+        }
+        if let Some(gas_prices) = saved.gas_prices {
+            *mempool.gas_prices.lock().unwrap() = gas_prices;
+        }
+        let last_id = saved
+            .blocks
+            .iter()
+            .flat_map(|block| block.transactions.iter())
+            .chain(&saved.queued)
+            .map(|tx| tx.id)
+            .max();
+        if let Some(last_id) = last_id {
+            Transaction::reserve_ids_up_to(last_id);
+        }
+        {
+            let mut txs = mempool.transactions.lock().unwrap();
+            for tx in saved.queued.into_iter().rev() {
+                txs.push_front(tx);
+            }
+            mempool.rejected_transactions.lock().unwrap().extend(saved.rejected);
+        }
+
+        let mut sequencer = Sequencer::new(mempool);
+        if let (Some(genesis), Some(latest)) = (saved.genesis, saved.blocks.last()) {
+            sequencer.block_number = latest.header.block_number + 1;
+            sequencer.parent_block_hash = latest.get_block_hash();
+            sequencer.core_contract = Arc::new(Mutex::new(StarknetCoreContract::new(genesis)));
+            println!(
+                "[Sequencer] 💾 Resumed after Block #{} ({} block(s), {} transaction(s) in the mempool).",
+                latest.header.block_number,
+                saved.blocks.len(),
+                sequencer.mempool.transactions.lock().unwrap().len()
+            );
+        }
+        if saved.blocks.is_empty() {
+            store.save_genesis(&sequencer.committed_state)?;
+        }
+        *sequencer.blocks.lock().unwrap() = saved.blocks;
+        *sequencer.mempool.store.lock().unwrap() = Some(store);
+        Ok(sequencer)
+    }
+
+    /// Deploys a contract at `address` so transactions and L1 messages can call it.
+    /// The deployment is published in the next block's state diff.
+    pub fn deploy_contract(&mut self, address: &str, contract: Arc<dyn Contract>) {
+        println!("[Sequencer] 📦 Deployed contract {} at {}", contract.class_hash(), address);
+        self.mempool
+            .contract_classes
+            .lock()
+            .unwrap()
+            .insert(address.to_string(), contract.class_hash());
+        self.contracts.insert(address.to_string(), contract);
+    }
+
+    /// Upgrades a deployed contract to a new class (`replace_class`), keeping its storage
+    pub fn replace_class(&mut self, address: &str, contract: Arc<dyn Contract>) -> Result<(), String> {
+        if !self.contracts.contains_key(address) {
+            return Err(format!("no contract deployed at {}", address));
+        }
+        println!("[Sequencer] ♻️ Replaced the class of {} with {}", address, contract.class_hash());
+        self.mempool
+            .contract_classes
+            .lock()
+            .unwrap()
+            .insert(address.to_string(), contract.class_hash());
+        self.contracts.insert(address.to_string(), contract);
+        Ok(())
+    }
+
     /// **Processes transactions one-by-one and creates an L2 block**
+    ///
+    /// Messages sent from L1 are handled first, as L1 handler transactions, then
+    /// the transactions waiting in the mempool, until the block is full under the
+    /// block policy. Transactions that do not fit carry over to the next block.
+    /// Every executed transaction is visible in the pending block until the block is sealed.
     pub fn process_transactions(&mut self) {
-        let mut transactions = vec![];
-
-        let tx_count = self.mempool.transactions.lock().unwrap().len();
+        let messages = self
+            .core_contract
+            .lock()
+            .unwrap()
+            .take_pending_messages(self.block_policy.max_transactions);
+        let tx_count = self.mempool.transactions.lock().unwrap().len() + messages.len();
         if tx_count == 0 {
             println!("[Sequencer] ⚠️ No transactions to process. Returning to menu.");
             return;
         }
 
-    println!("[Sequencer] Processing {} transaction(s)...", tx_count);
+        // 🌍 Read the L1 prices for this block; all prices stay fixed until it is sealed
+        let l1_quote = self.l1_gas_oracle.next_quote();
+        let gas_prices = {
+            let mut gas_prices = self.mempool.gas_prices.lock().unwrap();
+            gas_prices.l1_gas_price = l1_quote.l1_gas_price;
+            gas_prices.l1_data_gas_price = l1_quote.l1_data_gas_price;
+            *gas_prices
+        };
+
+        println!("[Sequencer] Processing {} transaction(s)...", tx_count);
+        *self.pending_block.lock().unwrap() = Some(PendingBlock::new(
+            self.block_number,
+            self.parent_block_hash.clone(),
+            self.sequencer_address.clone(),
+            gas_prices,
+            self.committed_state.clone(),
+        ));
+
+        // 📨 Deliver L1→L2 messages. A message whose handler fails, or that does not fit in
+        // the block, is left unconsumed on L1 and queued again: it is retried with the next
+        // block until it goes through or its sender cancels it.
+        let mut unconsumed = vec![];
+        for message in messages {
+            if self.pending_block_is_full() {
+                unconsumed.push(message);
+                continue;
+            }
+            let mut tx = Transaction::l1_handler(&message);
+            println!(
+                "[Sequencer] 📨 Handling L1 message #{} from {} to {} ({})...",
+                message.nonce, message.from_address, message.to_address, message.selector
+            );
+            match self.execute_l1_handler(&mut tx, &message, &gas_prices) {
+                Ok((receipt, state_diff)) => {
+                    println!("[Sequencer] ✅ L1 handler transaction {} EXECUTED!", tx.id);
+                    self.executed_txs.lock().unwrap().push(tx.clone());
+                    self.add_to_pending_block(tx, receipt, state_diff, Some(message.hash()));
+                }
+                Err(reason) => {
+                    println!(
+                        "[Sequencer] ❌ L1 handler transaction {} REJECTED! {}",
+                        tx.id, reason
+                    );
+                    tx.update_status(TransactionStatus::Rejected);
+                    unconsumed.push(message);
+                }
+            }
+        }
+        if !unconsumed.is_empty() {
+            self.core_contract.lock().unwrap().requeue_pending_messages(unconsumed);
+        }
+
+        while !self.pending_block_is_full() {
+            // Otherwise the next transaction is not a plain transfer; it runs on its own
+            if let ExecutionMode::Parallel { workers } = self.execution_mode {
+                if self.process_parallel_batch(workers) {
+                    continue;
+                }
+            }
+            if !self.process_next_transaction() {
+                break;
+            }
+        }
+
+        // The mempool is only left with transactions once the block is full
+        let carried_over = self.mempool.transactions.lock().unwrap().len();
+        if carried_over > 0 {
+            let (transaction_count, l2_gas_used) = self.pending_block_fill();
+            println!(
+                "[Sequencer] 📦 Block is full ({} txs, {} L2 gas). {} transaction(s) carried over to the next block.",
+                transaction_count,
+                l2_gas_used,
+                carried_over
+            );
+        }
+
+        self.create_l2_block();
+    }
 
-        while let Some(mut tx) = self.mempool.validate_transaction() {
-            println!("[Sequencer] Processing transaction ID: {}...", tx.id);
+    /// Validates and executes the next transaction in the mempool. A transaction that fails
+    /// validation leaves the mempool and the block goes on with the next one. Returns false
+    /// once the mempool is empty.
+    fn process_next_transaction(&self) -> bool {
+        let Some(mut tx) = self.mempool.validate_transaction(self.pending_block_timestamp()) else {
+            return !self.mempool.transactions.lock().unwrap().is_empty();
+        };
+        println!("[Sequencer] Processing transaction ID: {}...", tx.id);
 
-            // Ensure strict transaction ordering (FIFO)
-            if !self.validate_transaction_again(&tx) {
+        let (receipt, state_diff) = match self.execute_transaction(&mut tx) {
+            Ok(executed) => executed,
+            Err(err) => {
                 println!(
-                    "[Sequencer] ❌ Transaction {} REJECTED! Failed validation.",
-                    tx.id
+                    "[Sequencer] ❌ Transaction {} REJECTED! {}",
+                    tx.id, err
                 );
                 tx.update_status(TransactionStatus::Rejected);
-                continue;
+                return true;
             }
+        };
+        println!(
+            "[Sequencer] ⛽ Transaction {} used {} L1 gas + {} L1 data gas + {} L2 gas ({} steps). Actual fee: {} tokens",
+            tx.id,
+            receipt.gas_consumed.l1_gas,
+            receipt.gas_consumed.l1_data_gas,
+            receipt.gas_consumed.l2_gas,
+            receipt.resources.steps,
+            receipt.actual_fee
+        );
+        self.executed_txs.lock().unwrap().push(tx.clone());
+        self.add_to_pending_block(tx.clone(), receipt, state_diff, None);
 
-            self.execute_transaction(&mut tx);
-            transactions.push(tx.clone());
+        // Ensure sequential processing by adding a slight delay
+        thread::sleep(self.processing_delay);
 
-            let mut executed = self.executed_txs.lock().unwrap();
-            executed.push(tx.clone());
+        let (sender_balance, receiver_balance) = {
+            let balances = self.mempool.balances.lock().unwrap();
+            let receiver_balance = tx
+                .receiver
+                .as_ref()
+                .map(|r| state::get_balance(&balances, r))
+                .unwrap_or_default();
+            (state::get_balance(&balances, &tx.sender), receiver_balance)
+        };
+        println!("💰 {}'s remaining balance: {} tokens", tx.sender, sender_balance);
+        if let Some(receiver) = &tx.receiver {
+            println!("💰 {}'s new balance: {} tokens", receiver, receiver_balance);
+        }
+        true
+    }
 
-            // Ensure sequential processing by adding a slight delay
-            thread::sleep(Duration::from_secs(1));
+    /// Runs the next plain transfers in the mempool under Block-STM, then commits their results
+    /// in block order for as long as the block takes more, exactly as if they had run one at a
+    /// time. Whatever is not committed goes back to the mempool untouched.
+    ///
+    /// A transaction that fails mempool validation leaves the mempool, as in sequential mode.
+    /// Returns false if there was nothing to run in parallel.
+    fn process_parallel_batch(&self, workers: usize) -> bool {
+        let (transaction_count, mut l2_gas_used) = self.pending_block_fill();
+        let capacity = self.block_policy.max_transactions.saturating_sub(transaction_count);
+        // The sequencer's own balance only changes at commit, when tips are paid in order.
+        // Account contracts run their own `__validate__`, so their transactions run one at a time
+        let batch = self.mempool.take_transactions(capacity, |tx| {
+            block_stm::is_plain_transfer(tx)
+                && self.mempool.account(&tx.sender).is_none()
+                && tx.sender != self.sequencer_address
+                && tx.receiver.as_deref() != Some(self.sequencer_address.as_str())
+        });
+        if batch.is_empty() {
+            return false;
+        }
+        let gas_prices = *self.mempool.gas_prices.lock().unwrap();
 
-            let sender_balance = {
-                let balances = self.mempool.balances.lock().unwrap();
-                *balances.get(&tx.sender).unwrap_or(&0)
+        let mut balances = self.mempool.balances.lock().unwrap();
+        let mut nonces = self.mempool.nonces.lock().unwrap();
+        let output = block_stm::execute_parallel(
+            &batch,
+            BaseState { balances: &balances, nonces: &nonces },
+            workers,
+            |tx, view| block_stm::execute_transfer(tx, view, &gas_prices),
+        );
+        println!(
+            "[Sequencer] ⚡ Block-STM ran {} transaction(s) on {} worker(s): {} execution(s), {} re-run after a conflict, in {} wave(s).",
+            batch.len(),
+            workers,
+            output.executions,
+            output.re_executions(),
+            output.waves
+        );
+
+        let mut executed = vec![];
+        let mut processed = 0;
+        for (tx, result) in batch.iter().zip(output.results) {
+            if self.block_is_full(transaction_count + executed.len(), l2_gas_used) {
+                break;
+            }
+            let mut tx = tx.clone();
+            let tip = match &result.output {
+                TransferOutcome::Executed { tip, .. } => Some(*tip),
+                _ => None,
             };
-            let receiver_balance = {
-                let balances = self.mempool.balances.lock().unwrap();
-                tx.receiver
-                    .as_ref()
-                    .map(|r| balances.get(r).unwrap_or(&0))
-                    .unwrap_or(&0)
-                    .to_owned()
+            if state::get_balance(&balances, &self.sequencer_address).checked_add(tip.unwrap_or_default()).is_none() {
+                break; // runs on its own, where paying the tip fails as it would in block order
+            }
+            processed += 1;
+
+            let receipt = match result.output {
+                TransferOutcome::Rejected { reason, remember } => {
+                    println!("[Mempool] ❌ Transaction {} is rejected! {}", tx.id, reason);
+                    if remember {
+                        self.mempool.rejected_transactions.lock().unwrap().insert(tx.get_hash());
+                    }
+                    break;
+                }
+                TransferOutcome::Executed { status, revert_reason, resources, actual_fee, tip, phases } => {
+                    match &revert_reason {
+                        None => println!("[Sequencer] ✅ Transaction {} EXECUTED!", tx.id),
+                        Some(reason) => println!("[Sequencer] ⚠️ Transaction {} REVERTED! {}", tx.id, reason),
+                    }
+                    println!(
+                        "[Sequencer] 💰 Charged {} a fee of {} (max {}, tip {}).",
+                        tx.sender, actual_fee, tx.fee, tip
+                    );
+                    tx.update_status(status);
+                    let gas_consumed = resources.to_gas();
+                    l2_gas_used += gas_consumed.l2_gas;
+                    TransactionReceipt {
+                        transaction_hash: tx.get_hash(),
+                        tx_id: tx.id,
+                        status: tx.status.clone(),
+                        resources,
+                        gas_consumed,
+                        actual_fee,
+                        tip,
+                        messages_sent: vec![],
+                        events: vec![],
+                        phases,
+                        revert_reason,
+                        fee_payer: tx.sender.clone(),
+                    }
+                }
             };
-            println!("💰 {}'s remaining balance: {} tokens", tx.sender, sender_balance);
-            if let Some(receiver) = &tx.receiver {
-                println!("💰 {}'s new balance: {} tokens", receiver, receiver_balance);
+
+            // Balances are published as storage of the fee token
+            let mut state_diff = StateDiff::default();
+            let mut balance_updates = BTreeMap::new();
+            for (key, value) in result.writes {
+                match (key, value) {
+                    (StateKey::Balance(account), StateValue::Balance(balance)) => {
+                        balance_updates.insert(account.clone(), balance);
+                        balances.insert(account, balance);
+                    }
+                    (StateKey::Nonce(account), StateValue::Nonce(nonce)) => {
+                        state_diff.nonce_updates.insert(account.clone(), nonce);
+                        nonces.insert(account, nonce);
+                    }
+                    _ => unreachable!("balances hold balances and nonces hold nonces"),
+                }
             }
+            state::credit(&mut balances, &self.sequencer_address, receipt.tip)
+                .expect("checked before the transaction was committed");
+            balance_updates.insert(self.sequencer_address.clone(), state::get_balance(&balances, &self.sequencer_address));
+            state_diff.storage_updates.insert(FEE_TOKEN_ADDRESS.to_string(), balance_updates);
+            executed.push((tx, receipt, state_diff));
         }
+        drop((balances, nonces));
 
-        if !transactions.is_empty() {
-            self.create_l2_block(transactions);
+        // A rejected transaction leaves the mempool; everything after it goes back
+        self.mempool.return_transactions(batch[processed..].to_vec());
+        if !executed.is_empty() {
+            self.executed_txs.lock().unwrap().extend(executed.iter().map(|(tx, _, _)| tx.clone()));
+            let mut pending_block = self.pending_block.lock().unwrap();
+            let pending_block = pending_block.as_mut().expect("transactions execute into an open pending block");
+            for (tx, receipt, state_diff) in executed {
+                pending_block.push(tx, receipt, state_diff);
+            }
+            thread::sleep(self.processing_delay);
         }
+
+        processed > 0
     }
 
-    /// **Repeats the validation process before execution**
-    fn validate_transaction_again(&self, tx: &Transaction) -> bool {
-        let balances = self.mempool.balances.lock().unwrap();
-        let nonces = self.mempool.nonces.lock().unwrap();
+    /// Appends an executed transaction to the pending block, with what it changed
+    fn add_to_pending_block(&self, tx: Transaction, receipt: TransactionReceipt, state_diff: StateDiff, l1_to_l2_message: Option<String>) {
+        let mut pending_block = self.pending_block.lock().unwrap();
+        let pending_block = pending_block.as_mut().expect("transactions execute into an open pending block");
+        pending_block.push(tx, receipt, state_diff);
+        pending_block.l1_to_l2_messages.extend(l1_to_l2_message);
+    }
 
-        // ✅ Ensure transactions are processed in strict nonce order
-        let expected_nonce = nonces.get(&tx.sender).unwrap_or(&0);
-        if tx.nonce != *expected_nonce {
-            println!(
-                "[Sequencer] ❌ Transaction {} REJECTED! Nonce mismatch. Expected: {}",
-                tx.id, expected_nonce
-            );
+    /// Transactions and L2 gas in the pending block so far
+    fn pending_block_fill(&self) -> (usize, u64) {
+        self.pending_block
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|block| (block.transactions.len(), block.l2_gas_used()))
+            .unwrap_or_default()
+    }
+
+    /// Timestamp of the block being built: the mempool's dry run and the transactions in the
+    /// block all see this one
+    fn pending_block_timestamp(&self) -> u64 {
+        self.pending_block
+            .lock()
+            .unwrap()
+            .as_ref()
+            .expect("transactions execute into an open pending block")
+            .block_timestamp
+    }
+
+    fn pending_block_is_full(&self) -> bool {
+        let (transaction_count, l2_gas_used) = self.pending_block_fill();
+        self.block_is_full(transaction_count, l2_gas_used)
+    }
+
+    /// Copy of the block being built, if the sequencer is filling one
+    pub fn pending_block(&self) -> Option<PendingBlock> {
+        self.pending_block.lock().unwrap().clone()
+    }
+
+    /// Whether a block with this many transactions and this much L2 gas used takes no more
+    fn block_is_full(&self, transaction_count: usize, l2_gas_used: u64) -> bool {
+        transaction_count >= self.block_policy.max_transactions || l2_gas_used >= self.block_policy.max_l2_gas
+    }
+
+    /// Whether the block policy calls for a block now: the waiting transactions would fill one,
+    /// or some are waiting and `block_time` has passed since the previous block
+    pub fn block_due(&self) -> bool {
+        let (waiting, estimated_l2_gas) = {
+            let txs = self.mempool.transactions.lock().unwrap();
+            let gas = txs
+                .iter()
+                .map(|tx| Transaction::estimate_resources(&tx.tx_type).to_gas().l2_gas)
+                .sum::<u64>();
+            (txs.len(), gas)
+        };
+        let messages = self.core_contract.lock().unwrap().has_pending_messages();
+        if waiting == 0 && !messages {
             return false;
         }
+        self.block_is_full(waiting, estimated_l2_gas) || self.last_block_at.elapsed() >= self.block_policy.block_time
+    }
 
-        // ✅ Ensure sender has enough funds
-        if let Some(amount) = tx.amount {
-            let sender_balance = balances.get(&tx.sender).unwrap_or(&0);
-            if *sender_balance < amount {
-                println!(
-                    "[Sequencer] ❌ Transaction {} REJECTED! Insufficient balance.",
-                    tx.id
-                );
-                return false;
-            }
+    /// Block producer step: seals a block if one is due. Returns whether a block was sealed.
+    pub fn produce_block_if_due(&mut self) -> bool {
+        if !self.block_due() {
+            return false;
         }
-
-        true
+        let block_number = self.block_number;
+        self.process_transactions();
+        self.block_number > block_number
     }
 
     /// **Executes a transaction and handles `REVERTED` cases**
-    fn execute_transaction(&self, tx: &mut Transaction) {
+    ///
+    /// The mempool already checked the nonce, the fee bounds and that the fee payer can pay
+    /// the max fee. What is left runs in three phases:
+    /// 1. `__validate__`: the account contract's own validation, or the signature check of a
+    ///    plain account, then the paymaster's `__validate_paymaster__` if the transaction names
+    ///    one. A transaction either of them refuses is not included, and its nonce stays free.
+    ///    Once both pass, the nonce is consumed, even if the execution reverts.
+    /// 2. `__execute__`: the calls run on a state overlay, which is dropped if they revert.
+    ///    What the validation wrote is kept either way.
+    /// 3. Fee transfer: the actual fee, never more than the bounds, is charged once to the
+    ///    paymaster or the sender. If it cannot be paid out of what `__execute__` left, the
+    ///    execution reverts and the fee is charged on the state from before it, capped at what
+    ///    the payer has left there.
+    ///
+    /// Returns the receipt together with what the transaction changed.
+    fn execute_transaction(&self, tx: &mut Transaction) -> Result<(TransactionReceipt, StateDiff), String> {
+        let block_timestamp = self.pending_block_timestamp();
+        let gas_prices = *self.mempool.gas_prices.lock().unwrap();
         let mut balances = self.mempool.balances.lock().unwrap();
-        let mut nonces = self.mempool.nonces.lock().unwrap();
+        let mut meter = ResourceMeter::new();
 
-        let sender_nonce = nonces.entry(tx.sender.clone()).or_insert(0);
+        // 🔢 The nonce is checked again: it is consumed here, not in the mempool
+        let mut nonces = self.mempool.nonces.lock().unwrap();
+        let nonce = execution::check_nonce(tx, &mut BaseState { balances: &balances, nonces: &nonces })
+            .map_err(|rejection| rejection.reason)?;
 
-        // ✅ Check if transaction nonce matches expected nonce
-        if tx.nonce != *sender_nonce {
-            println!(
-                "[Sequencer] ❌ Transaction {} REJECTED! Nonce mismatch. Expected: {}, Got: {}",
-                tx.id, *sender_nonce, tx.nonce
-            );
-            tx.update_status(TransactionStatus::Rejected);
-            return;
+        // 🔐 __validate__ and __validate_paymaster__
+        let mut overlay = StateOverlay::default();
+        {
+            let storage = self.mempool.storage.lock().unwrap();
+            let validation = Validation {
+                committed_balances: &balances,
+                committed_storage: &storage,
+                block_timestamp,
+                step_limit: self.mempool.validation_step_limit,
+            };
+            match self.mempool.account(&tx.sender) {
+                Some(account) => {
+                    account::validate(account.as_ref(), tx, &mut overlay, &mut meter, &validation)
+                        .map_err(|reason| format!("__validate__ failed: {}", reason))?;
+                }
+                None => gas::meter_validate(&mut meter, &tx.sender),
+            }
+            if let Some(address) = &tx.paymaster {
+                let paymaster = self.mempool.paymaster(address).ok_or_else(|| format!("no paymaster deployed at {}", address))?;
+                account::validate_paymaster(paymaster.as_ref(), tx, &mut overlay, &mut meter, &validation)
+                    .map_err(|reason| format!("__validate_paymaster__ failed: {}", reason))?;
+            }
         }
+        nonces.insert(tx.sender.clone(), nonce + 1);
+        drop(nonces);
+        let validated = overlay.clone();
 
-        match tx.tx_type {
-            TransactionType::Invoke => {
-                if let Some(amount) = tx.amount {
-                    let sender_balance = balances.get(&tx.sender).unwrap();
-                    if *sender_balance < amount {
-                        println!(
-                            "[Sequencer] ⚠️ Transaction {} REVERTED! Insufficient funds.",
-                            tx.id
-                        );
-
-                        // 🛑 Deduct the fee even if transaction fails
-                        if *sender_balance >= tx.fee {
-                            *balances.get_mut(&tx.sender).unwrap() -= tx.fee;
-                            println!(
-                                "[Sequencer] 💰 Fee of {} deducted from {} for reverted transaction {}.",
-                                tx.fee, tx.sender, tx.id
-                            );
-                        } else {
-                            println!(
-                                "[Sequencer] 🚨 Warning! {} does not have enough funds for the full fee of {}. Deducting available amount.",
-                                tx.sender, tx.fee
-                            );
-                            *balances.get_mut(&tx.sender).unwrap() = 0;
-                        }
-
-                        tx.update_status(TransactionStatus::Reverted);
-                    } else {
-                        // ✅ Deduct funds sequentially
-                        *balances.get_mut(&tx.sender).unwrap() -= amount;
-
-                        // ✅ Update receiver balance
-                        let receiver_balance = balances.get(&tx.receiver.clone().unwrap()).cloned().unwrap_or(0);
-                        balances.insert(tx.receiver.clone().unwrap(), receiver_balance + amount);
-
-                        println!("[Sequencer] ✅ Transaction {} EXECUTED!", tx.id);
-
-                        tx.update_status(TransactionStatus::Succeeded);
-                    }
+        // ⚙️ __execute__
+        let (executed, (mut messages, mut events)) = match self.run_transaction(tx, &balances, &mut overlay, &mut meter, &gas_prices) {
+            Ok(sent) => (Ok(()), sent),
+            Err(reason) => (Err(reason), Default::default()),
+        };
+
+        // 💰 Fee transfer. The base fee is burned, the tip goes to the sequencer.
+        let resources = meter.finish();
+        let gas_consumed = resources.to_gas();
+        let charge = tx.chargeable_fee(&gas_consumed, &gas_prices);
+        let mut validated = validated;
+        let payer = tx.fee_payer();
+        let fee = execution::transfer_fee(
+            executed,
+            overlay.balances_for(&balances, &[payer]),
+            validated.balances_for(&balances, &[payer]),
+            payer,
+            charge,
+        );
+        let mut charge = fee.charge;
+        let (revert_reason, mut state_diff) = match fee.revert_reason {
+            None => {
+                let state_diff = self.commit_overlay(overlay, &mut balances);
+                if tx.tx_type == TransactionType::Invoke {
+                    println!("[Sequencer] ✅ Transaction {} EXECUTED!", tx.id);
+                } else {
+                    println!(
+                        "[Sequencer] ✅ Transaction {} EXECUTED! (Contract Deployment)",
+                        tx.id
+                    );
                 }
+                tx.update_status(TransactionStatus::Succeeded);
+                (None, state_diff)
             }
-            TransactionType::Declare | TransactionType::DeployAccount => {
+            Some(reason) => {
                 println!(
-                    "[Sequencer] ✅ Transaction {} EXECUTED! (Contract Deployment)",
-                    tx.id
+                    "[Sequencer] ⚠️ Transaction {} REVERTED! {}",
+                    tx.id, reason
                 );
+                tx.update_status(TransactionStatus::Reverted);
+                messages.clear();
+                events.clear();
+                (Some(reason), self.commit_overlay(validated, &mut balances))
+            }
+        };
+        // A tip the sequencer cannot hold is burned with the base fee
+        if state::credit(&mut balances, &self.sequencer_address, charge.tip).is_err() {
+            charge.base_fee = charge.total();
+            charge.tip = U256::ZERO;
+        } else {
+            state_diff
+                .storage_updates
+                .entry(FEE_TOKEN_ADDRESS.to_string())
+                .or_default()
+                .insert(self.sequencer_address.clone(), state::get_balance(&balances, &self.sequencer_address));
+        }
+        state_diff.nonce_updates.insert(tx.sender.clone(), nonce + 1);
+        let actual_fee = charge.total();
+        println!(
+            "[Sequencer] 💰 Charged {} a fee of {} (max {}, tip {}).",
+            tx.fee_payer(), actual_fee, tx.fee, charge.tip
+        );
 
-                tx.update_status(TransactionStatus::Succeeded);
+        let receipt = TransactionReceipt {
+            transaction_hash: tx.get_hash(),
+            tx_id: tx.id,
+            status: tx.status.clone(),
+            resources,
+            gas_consumed,
+            actual_fee,
+            tip: charge.tip,
+            messages_sent: messages,
+            events,
+            phases: fee.phases,
+            revert_reason,
+            fee_payer: tx.fee_payer().to_string(),
+        };
+        Ok((receipt, state_diff))
+    }
+
+    /// Runs the transaction body on the overlay, on top of `committed_balances`. Each operation
+    /// is metered and checked against the transaction's gas limits, and any error reverts the
+    /// whole transaction. Returns the messages it sent to L1 and the events it emitted.
+    fn run_transaction(
+        &self,
+        tx: &Transaction,
+        committed_balances: &HashMap<String, U256>,
+        state: &mut StateOverlay,
+        meter: &mut ResourceMeter,
+        gas_prices: &GasPrices,
+    ) -> Result<(Vec<L2ToL1Message>, Vec<event::Event>), String> {
+        let (mut messages, mut events) = (vec![], vec![]);
+        tx.check_gas_limit(&meter.resources().to_gas(), gas_prices)?;
+
+        match tx.tx_type {
+            TransactionType::Invoke => {
+                if tx.entry_point.is_some() {
+                    self.call_contract(tx, committed_balances, state, meter, &mut messages, &mut events)?;
+                    tx.check_gas_limit(&meter.resources().to_gas(), gas_prices)?;
+                } else if let Some(receiver) = &tx.receiver {
+                    let balances = state.balances_for(committed_balances, &[&tx.sender, receiver]);
+                    execution::run_transfer(tx, balances, meter, gas_prices)?;
+                }
+            }
+            TransactionType::Declare => {
+                let class_hash = tx.contract_address.as_deref().ok_or("declare has no class hash")?;
+                if self.mempool.declared_classes.lock().unwrap().contains_key(class_hash)
+                    || state.declared_classes.contains_key(class_hash)
+                {
+                    return Err(format!("class {} is already declared", class_hash));
+                }
+                gas::meter_declare(meter);
+                tx.check_gas_limit(&meter.resources().to_gas(), gas_prices)?;
+                state.declared_classes.insert(class_hash.to_string(), contract::compiled_class_hash(class_hash));
+            }
+            TransactionType::DeployAccount => {
+                if self.mempool.contract_classes.lock().unwrap().contains_key(&tx.sender)
+                    || state.contract_classes.contains_key(&tx.sender)
+                {
+                    return Err(format!("account {} is already deployed", tx.sender));
+                }
+                gas::meter_deploy_account(meter, &tx.sender);
+                tx.check_gas_limit(&meter.resources().to_gas(), gas_prices)?;
+                state.contract_classes.insert(tx.sender.clone(), contract::ACCOUNT_CLASS_HASH.to_string());
+            }
+            TransactionType::L1Handler => {
+                return Err("L1 handler transactions can only be sent by the sequencer".to_string());
             }
         }
-        *sender_nonce += 1;
+        Ok((messages, events))
     }
 
-    /// **Creates an L2 block containing all processed transactions**
-    fn create_l2_block(&mut self, transactions: Vec<Transaction>) {
-        let mut block_status = BlockStatus::Pending; // Initially, block is pending
-    
-        // Determine if any transactions were reverted
-        if transactions.iter().all(|tx| tx.status == TransactionStatus::Reverted) {
-            println!("[Sequencer] ❌ All transactions in this block failed. Marking block as REJECTED.");
-            block_status = BlockStatus::Rejected;
-        } else {
-            println!("[Sequencer] ✅ Block successfully created on L2.");
-            block_status = BlockStatus::AcceptedOnL2;
+    /// Runs the entry point named by `tx.entry_point`: the L1 handler of an L1 handler
+    /// transaction, an external entry point otherwise. Its writes go to the overlay, and
+    /// the messages it sends and events it emits are appended to `messages` and `events`.
+    fn call_contract(
+        &self,
+        tx: &Transaction,
+        committed_balances: &HashMap<String, U256>,
+        state: &mut StateOverlay,
+        meter: &mut ResourceMeter,
+        messages: &mut Vec<L2ToL1Message>,
+        events: &mut Vec<event::Event>,
+    ) -> Result<Vec<String>, String> {
+        let contract_address = tx.contract_address.as_deref().ok_or("call has no contract address")?;
+        let selector = tx.entry_point.as_deref().ok_or("call has no entry point")?;
+        let entry_point_type = match tx.tx_type {
+            TransactionType::L1Handler => EntryPointType::L1Handler,
+            _ => EntryPointType::External,
+        };
+        let contract = self
+            .contracts
+            .get(contract_address)
+            .cloned()
+            .ok_or_else(|| format!("no contract deployed at {}", contract_address))?;
+        if contract.entry_point_type(selector) != Some(entry_point_type) {
+            return Err(format!(
+                "{} has no {:?} entry point named {}",
+                contract_address, entry_point_type, selector
+            ));
         }
-    
-        let new_block = L2Block::new(
-            self.block_number,
-            self.parent_block_hash.clone(),
-            self.sequencer_address.clone(),
-            transactions.clone(),
+
+        let tx_info = TxInfo::new(tx, self.pending_block_timestamp());
+        let committed_storage = self.mempool.storage.lock().unwrap();
+        let mut ctx = ExecutionContext {
+            contract_address: contract_address.to_string(),
+            caller_address: tx.sender.clone(),
+            state,
+            committed_balances,
+            committed_storage: &committed_storage,
+            contracts: &self.contracts,
+            meter,
+            messages,
+            events,
+            tx_info: &tx_info,
+            step_limit: None,
+        };
+        contract.execute(selector, &tx.calldata, &mut ctx)
+    }
+
+    /// Writes a successful transaction's overlay into the committed state, and returns what it wrote
+    fn commit_overlay(&self, overlay: StateOverlay, balances: &mut HashMap<String, U256>) -> StateDiff {
+        let state_diff = overlay.state_diff();
+        overlay.commit(
+            balances,
+            &mut self.mempool.storage.lock().unwrap(),
+            &mut self.mempool.contract_classes.lock().unwrap(),
+            &mut self.mempool.declared_classes.lock().unwrap(),
         );
-    
-        // Apply final block status
-        let mut final_block = new_block.clone();
-        final_block.header.block_status = block_status;
-    
-        self.parent_block_hash = new_block.get_block_hash();
+        state_diff
+    }
+
+    /// Runs an L1 handler transaction and commits it only if the handler succeeds and
+    /// the fee paid on L1 covers the gas it used. There is no validation phase and
+    /// nothing is charged on L2.
+    fn execute_l1_handler(&self, tx: &mut Transaction, message: &L1ToL2Message, gas_prices: &GasPrices) -> Result<(TransactionReceipt, StateDiff), String> {
+        let mut meter = ResourceMeter::new();
+        gas::meter_l1_handler(&mut meter, message.payload.len() as u64);
+
+        let mut balances = self.mempool.balances.lock().unwrap();
+        let mut overlay = StateOverlay::default();
+        let mut messages = vec![];
+        let mut events = vec![];
+        self.call_contract(tx, &balances, &mut overlay, &mut meter, &mut messages, &mut events)?;
+
+        let resources = meter.finish();
+        let gas_consumed = resources.to_gas();
+        let actual_fee = gas_prices.fee_for(&gas_consumed).unwrap_or(U256::MAX);
+        if actual_fee > message.fee {
+            return Err(format!(
+                "the fee of {} paid on L1 does not cover the actual fee {}",
+                message.fee, actual_fee
+            ));
+        }
+
+        let state_diff = self.commit_overlay(overlay, &mut balances);
+        tx.update_status(TransactionStatus::Succeeded);
+
+        let receipt = TransactionReceipt {
+            transaction_hash: tx.get_hash(),
+            tx_id: tx.id,
+            status: tx.status.clone(),
+            resources,
+            gas_consumed,
+            actual_fee,
+            tip: U256::ZERO,
+            messages_sent: messages,
+            events,
+            revert_reason: None,
+            fee_payer: tx.sender.clone(), // the L1 sender
+            // Paid on L1, with no account to validate
+            phases: TransactionPhases { execute: PhaseOutcome::Succeeded, ..Default::default() },
+        };
+        Ok((receipt, state_diff))
+    }
+
+    /// **Seals the pending block into an L2 block**
+    ///
+    /// The block leaves the pending slot and joins the chain under one lock, so a reader
+    /// sees its transactions either pending or sealed, never in neither.
+    /// Reverted transactions still charge fees and consume nonces, so a block is
+    /// accepted even when all of its transactions reverted: its state changes must
+    /// reach L1 for the state roots to keep chaining.
+    fn create_l2_block(&mut self) {
+        let mut pending_block = self.pending_block.lock().unwrap();
+        let Some(block) = pending_block.take() else {
+            return;
+        };
+        if block.is_empty() {
+            return;
+        }
+        if block.transactions.iter().all(|tx| tx.status == TransactionStatus::Reverted) {
+            println!("[Sequencer] ⚠️ All transactions in this block reverted. Their fees and nonces are still applied.");
+        }
+        println!("[Sequencer] ✅ Block successfully created on L2.");
+
+        // The only full copy of the state taken for this block
+        let state = self.mempool.snapshot();
+        self.committed_state = state.clone();
+        let gas_prices = block.gas_prices;
+        let final_block = block.seal(state);
+
+        self.parent_block_hash = final_block.get_block_hash();
+        self.state_root = final_block.header.state_root.clone();
         self.block_number += 1;
-    
-        let mut blocks = self.blocks.lock().unwrap();
-        blocks.push(final_block.clone());
-    
+        self.last_block_at = Instant::now();
+        self.blocks.lock().unwrap().push(final_block.clone());
+        drop(pending_block);
+
+        // ⛽ Adjust the L2 base fee from how full this block was
+        let next_l2_gas_price = self
+            .fee_market
+            .next_l2_gas_price(gas_prices.l2_gas_price, final_block.header.l2_gas_used);
+        self.mempool.gas_prices.lock().unwrap().l2_gas_price = next_l2_gas_price;
+        self.save_block(&final_block);
+
         println!(
-            "[Sequencer] 🏗️ New L2 Block Created: #{} with {} transactions (Status: {:?})",
+            "[Sequencer] 🏗️ New L2 Block Created: #{} with {} transactions (Status: {})",
             final_block.header.block_number, final_block.header.transaction_count, final_block.header.block_status
         );
         println!("\n🌟 L2 Block Created 🌟");
         println!("🔢 Block Number: {}", final_block.header.block_number);
         println!("🔗 Previous Hash: {}", final_block.header.parent_block_hash);
         println!("🔗 Current Hash: {}", final_block.get_block_hash());
+        println!("🌳 State Root: {}", final_block.header.state_root);
         println!("⏳ Timestamp: {}", final_block.header.block_timestamp);
         println!("💰 Transactions in Block: {}", final_block.header.transaction_count);
-        println!("🚀 Block Status: {:?}", final_block.header.block_status);
+        println!("📨 L1 Messages Consumed: {}", final_block.l1_to_l2_messages.len());
+        println!(
+            "🧾 State Diff: {} deployed, {} storage contract(s), {} nonce(s), {} declared, {} replaced | DA size {} felts ({} bytes)",
+            final_block.state_diff.deployed_contracts.len(),
+            final_block.state_diff.storage_updates.len(),
+            final_block.state_diff.nonce_updates.len(),
+            final_block.state_diff.declared_classes.len(),
+            final_block.state_diff.replaced_classes.len(),
+            final_block.header.state_diff_length,
+            final_block.da_size_bytes()
+        );
+        println!(
+            "📤 L2→L1 Messages Sent: {} (commitment {})",
+            final_block.l2_to_l1_messages().len(),
+            final_block.header.message_commitment
+        );
+        println!(
+            "📣 Events Emitted: {} (commitment {})",
+            final_block.header.event_count,
+            final_block.header.event_commitment
+        );
+        println!(
+            "⛽ Gas Prices: L1 {} | L1 data {} | L2 {} (L2 gas used {}/{} target, next L2 price {})",
+            final_block.header.l1_gas_price,
+            final_block.header.l1_data_gas_price,
+            final_block.header.l2_gas_price,
+            final_block.header.l2_gas_used,
+            self.fee_market.target_l2_gas,
+            next_l2_gas_price
+        );
+        println!("🚀 Block Status: {}", final_block.header.block_status);
         println!("\n📜 Transactions in Block #{}:", final_block.header.block_number);
     
         println!("[Sequencer] Transactions in Block #{}:", final_block.header.block_number);
-        for tx in &transactions {
+        for tx in &final_block.transactions {
             println!(
                 "    - ID: {} | Nonce: {} | Sender: {} | Receiver: {:?} | Status: {:?}",
                 tx.id,tx.nonce, tx.sender,  tx.receiver, tx.status
             );
         }
     }
-}
\ No newline at end of file
+
+    /// Saves a block that was just sealed, if the node has storage. The mempool is saved as it
+    /// is now, under its lock, so a transaction that arrives meanwhile is not lost.
+    fn save_block(&self, block: &L2Block) {
+        // Copied first: `submit_transaction` takes the rejected hashes before the queue
+        let rejected_txs = self.mempool.rejected_transactions.lock().unwrap().clone();
+        let txs = self.mempool.transactions.lock().unwrap();
+        let gas_prices = *self.mempool.gas_prices.lock().unwrap();
+        let mut store = self.mempool.store.lock().unwrap();
+        let Some(store) = store.as_mut() else {
+            return;
+        };
+        match store.save_block(block, &self.committed_state, &gas_prices, &txs, &rejected_txs) {
+            Ok(()) => println!("[Sequencer] 💾 Block #{} saved.", block.header.block_number),
+            Err(err) => println!(
+                "[Sequencer] ❌ Block #{} was not saved, retrying with the next block: {}",
+                block.header.block_number, err
+            ),
+        }
+    }
+
+    /// Looks up the receipt of a transaction included in a block, or executed in the pending block
+    pub fn get_receipt(&self, transaction_hash: &str) -> Option<TransactionReceipt> {
+        // The pending block is locked before the chain, as when it is sealed
+        let pending_block = self.pending_block.lock().unwrap();
+        if let Some(receipt) = pending_block.as_ref().and_then(|b| b.get_receipt(transaction_hash)) {
+            return Some(receipt.clone());
+        }
+        let blocks = self.blocks.lock().unwrap();
+        blocks
+            .iter()
+            .flat_map(|block| block.receipts.iter())
+            .find(|receipt| receipt.transaction_hash == transaction_hash)
+            .cloned()
+    }
+
+    /// Returns the events of sealed blocks that pass `filter`, one page at a time,
+    /// like `starknet_getEvents`
+    pub fn get_events(&self, filter: &EventFilter) -> Result<EventsChunk, EventQueryError> {
+        event::get_events(&self.blocks.lock().unwrap(), filter)
+    }
+}
@@ -0,0 +1,617 @@
+//toy STARK
+//an educational STARK over a 32-bit prime field: the balance updates of a state transition are
+//arithmetized as an execution trace, committed with Merkle trees and checked for low degree with FRI
+
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+use sha2::{Sha256, Digest};
+use crate::state::{StateDiff, FEE_TOKEN_ADDRESS};
+
+/// The field modulus, 3·2^30 + 1. Its multiplicative group has subgroups of every power-of-two order up to 2^30.
+pub const MODULUS: u64 = 3 * (1 << 30) + 1;
+/// Generator of the multiplicative group; also the offset of the evaluation coset
+pub const GENERATOR: u64 = 5;
+/// Evaluation domain size over trace length
+pub const BLOWUP_FACTOR: usize = 8;
+/// Number of FRI queries the verifier checks
+pub const NUM_QUERIES: usize = 16;
+/// Shortest trace the prover commits to
+pub const MIN_TRACE_LENGTH: usize = 8;
+
+/// An element of the prime field
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Felt(u64);
+
+impl Felt {
+    pub const ZERO: Felt = Felt(0);
+    pub const ONE: Felt = Felt(1);
+
+    pub fn new(value: u64) -> Self {
+        Felt(value % MODULUS)
+    }
+
+    pub fn value(self) -> u64 {
+        self.0
+    }
+
+    pub fn pow(self, mut exponent: u64) -> Self {
+        let (mut base, mut result) = (self, Felt::ONE);
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            exponent >>= 1;
+        }
+        result
+    }
+
+    /// Multiplicative inverse, by Fermat's little theorem. Zero has none.
+    pub fn inverse(self) -> Option<Self> {
+        (self != Felt::ZERO).then(|| self.pow(MODULUS - 2))
+    }
+
+    /// Generator of the subgroup of order `order`, which must be a power of two
+    pub fn root_of_unity(order: usize) -> Self {
+        assert!(order.is_power_of_two() && (MODULUS - 1).is_multiple_of(order as u64), "no subgroup of order {}", order);
+        Felt(GENERATOR).pow((MODULUS - 1) / order as u64)
+    }
+
+    /// Maps arbitrary data into the field
+    pub fn from_hash(data: &str) -> Self {
+        let digest = Sha256::digest(data.as_bytes());
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        Felt::new(u64::from_be_bytes(bytes))
+    }
+
+    fn div(self, other: Felt) -> Felt {
+        self * other.inverse().expect("division by zero in the field")
+    }
+}
+
+impl Add for Felt {
+    type Output = Felt;
+    fn add(self, other: Felt) -> Felt {
+        Felt((self.0 + other.0) % MODULUS)
+    }
+}
+
+impl Sub for Felt {
+    type Output = Felt;
+    fn sub(self, other: Felt) -> Felt {
+        Felt((self.0 + MODULUS - other.0) % MODULUS)
+    }
+}
+
+impl Mul for Felt {
+    type Output = Felt;
+    fn mul(self, other: Felt) -> Felt {
+        Felt(self.0 * other.0 % MODULUS) // both operands are below 2^32
+    }
+}
+
+impl Neg for Felt {
+    type Output = Felt;
+    fn neg(self) -> Felt {
+        Felt::ZERO - self
+    }
+}
+
+impl fmt::Display for Felt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Evaluates the polynomial with coefficients `values` on the powers of `root`, in place
+fn ntt(values: &mut [Felt], root: Felt) {
+    let n = values.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            values.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let step = root.pow((n / len) as u64);
+        for chunk in values.chunks_mut(len) {
+            let mut w = Felt::ONE;
+            for k in 0..len / 2 {
+                let (even, odd) = (chunk[k], chunk[k + len / 2] * w);
+                chunk[k] = even + odd;
+                chunk[k + len / 2] = even - odd;
+                w = w * step;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Interpolates evaluations on the powers of `root` back to coefficients, in place
+fn inverse_ntt(values: &mut [Felt], root: Felt) {
+    ntt(values, root.inverse().expect("roots of unity are non-zero"));
+    let n_inverse = Felt::new(values.len() as u64).inverse().expect("domain sizes are non-zero");
+    for value in values.iter_mut() {
+        *value = *value * n_inverse;
+    }
+}
+
+/// Low-degree extension: interpolates `evaluations` over the subgroup of their size and
+/// evaluates the polynomial on the coset `offset·⟨ω⟩` of size `evaluations.len() · blowup`
+fn extend(evaluations: &[Felt], offset: Felt, blowup: usize) -> Vec<Felt> {
+    let n = evaluations.len();
+    let mut coefficients = evaluations.to_vec();
+    inverse_ntt(&mut coefficients, Felt::root_of_unity(n));
+
+    let mut shift = Felt::ONE;
+    for coefficient in coefficients.iter_mut() {
+        *coefficient = *coefficient * shift;
+        shift = shift * offset;
+    }
+    coefficients.resize(n * blowup, Felt::ZERO);
+    ntt(&mut coefficients, Felt::root_of_unity(n * blowup));
+    coefficients
+}
+
+/// Evaluates at `x` the polynomial taking `values[i]` at `root^i`, with the barycentric formula
+fn evaluate_interpolant(values: &[Felt], root: Felt, x: Felt) -> Felt {
+    let n = values.len() as u64;
+    let mut sum = Felt::ZERO;
+    let mut point = Felt::ONE;
+    for value in values {
+        sum = sum + (*value * point).div(x - point);
+        point = point * root;
+    }
+    (x.pow(n) - Felt::ONE).div(Felt::new(n)) * sum
+}
+
+/// Merkle tree over field elements
+pub struct MerkleTree {
+    layers: Vec<Vec<String>>, // leaves first, root last
+}
+
+impl MerkleTree {
+    pub fn new(values: &[Felt]) -> Self {
+        assert!(values.len().is_power_of_two(), "Merkle trees are built over a power-of-two number of leaves");
+        let mut layers = vec![values.iter().map(|value| Self::hash_leaf(*value)).collect::<Vec<_>>()];
+        while layers[layers.len() - 1].len() > 1 {
+            let parents = layers[layers.len() - 1]
+                .chunks(2)
+                .map(|pair| Self::hash_node(&pair[0], &pair[1]))
+                .collect();
+            layers.push(parents);
+        }
+        MerkleTree { layers }
+    }
+
+    pub fn root(&self) -> String {
+        self.layers[self.layers.len() - 1][0].clone()
+    }
+
+    /// Sibling hashes from the leaf at `index` up to the root
+    pub fn open(&self, mut index: usize) -> Vec<String> {
+        let mut path = vec![];
+        for layer in &self.layers[..self.layers.len() - 1] {
+            path.push(layer[index ^ 1].clone());
+            index >>= 1;
+        }
+        path
+    }
+
+    /// Checks that `value` is the leaf at `index` of the tree with the given root
+    pub fn verify(root: &str, mut index: usize, value: Felt, path: &[String]) -> bool {
+        let mut hash = Self::hash_leaf(value);
+        for sibling in path {
+            hash = if index & 1 == 0 {
+                Self::hash_node(&hash, sibling)
+            } else {
+                Self::hash_node(sibling, &hash)
+            };
+            index >>= 1;
+        }
+        hash == root
+    }
+
+    fn hash_leaf(value: Felt) -> String {
+        format!("{:x}", Sha256::digest(format!("leaf-{}", value).as_bytes()))
+    }
+
+    fn hash_node(left: &str, right: &str) -> String {
+        format!("{:x}", Sha256::digest(format!("node-{}-{}", left, right).as_bytes()))
+    }
+}
+
+/// Fiat-Shamir transcript: the verifier's random challenges are hashes of everything sent so far
+struct Transcript {
+    state: String,
+}
+
+impl Transcript {
+    fn new(public_input: &PublicInput) -> Self {
+        let mut transcript = Transcript { state: String::new() };
+        transcript.absorb(&format!("seed-{}", public_input.seed));
+        for update in &public_input.updates {
+            transcript.absorb(&format!("update-{}", update));
+        }
+        transcript
+    }
+
+    fn absorb(&mut self, data: &str) {
+        self.state = format!("{:x}", Sha256::digest(format!("{}-{}", self.state, data).as_bytes()));
+    }
+
+    fn challenge(&mut self) -> Felt {
+        self.absorb("challenge");
+        Felt::from_hash(&self.state)
+    }
+
+    fn challenge_index(&mut self, bound: usize) -> usize {
+        self.absorb("index");
+        (Felt::from_hash(&self.state).value() % bound as u64) as usize
+    }
+}
+
+/// The statement the STARK proves: folding every balance update into the seed, one per step
+/// (`a[i+1] = a[i]² + u[i]`), ends at the claimed output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicInput {
+    pub seed: Felt,
+    pub updates: Vec<Felt>,
+}
+
+impl PublicInput {
+    /// The balance updates of a state transition, starting from the state root it applies to
+    pub fn for_state_transition(prev_state_root: &str, state_diff: &StateDiff) -> Self {
+        let updates = state_diff
+            .storage_updates
+            .get(FEE_TOKEN_ADDRESS)
+            .map(|balances| {
+                balances
+                    .iter()
+                    .map(|(account, balance)| Felt::from_hash(&format!("{}-{}", account, balance)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        PublicInput { seed: Felt::from_hash(prev_state_root), updates }
+    }
+
+    /// Rows in the trace: a power of two with room for one transition per update
+    pub fn trace_length(&self) -> usize {
+        (self.updates.len() + 1).next_power_of_two().max(MIN_TRACE_LENGTH)
+    }
+
+    /// Update applied at every step, zero-padded to the trace length
+    fn update_column(&self) -> Vec<Felt> {
+        let mut column = self.updates.clone();
+        column.resize(self.trace_length(), Felt::ZERO);
+        column
+    }
+
+    /// The honest execution trace
+    pub fn trace(&self) -> Vec<Felt> {
+        let mut trace = vec![self.seed];
+        for update in &self.update_column()[..self.trace_length() - 1] {
+            let last = trace[trace.len() - 1];
+            trace.push(last * last + *update);
+        }
+        trace
+    }
+}
+
+/// A Merkle-authenticated value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Opening {
+    pub value: Felt,
+    pub path: Vec<String>,
+}
+
+/// Everything opened for one query: the trace at `x` and `ω·x`, and each FRI layer at `±x`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub index: usize,
+    pub trace: Opening,
+    pub next_trace: Opening,
+    pub fri_layers: Vec<(Opening, Opening)>,
+}
+
+/// A STARK proof
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StarkProof {
+    pub trace_length: usize,
+    pub output: Felt, // claimed last row of the trace
+    pub trace_root: String,
+    pub fri_roots: Vec<String>, // the composition polynomial first, then each folded layer
+    pub fri_final: Felt, // the constant the last fold reaches
+    pub queries: Vec<Query>,
+}
+
+impl StarkProof {
+    /// Approximate serialized size: 8 bytes per felt, 32 per hash
+    pub fn size_in_bytes(&self) -> usize {
+        let opening = |o: &Opening| 8 + 32 * o.path.len();
+        let queries: usize = self
+            .queries
+            .iter()
+            .map(|q| {
+                8 + opening(&q.trace)
+                    + opening(&q.next_trace)
+                    + q.fri_layers.iter().map(|(a, b)| opening(a) + opening(b)).sum::<usize>()
+            })
+            .sum();
+        16 + 32 * (1 + self.fri_roots.len()) + 8 + queries
+    }
+}
+
+/// Reasons a STARK proof is rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StarkError {
+    TraceLengthMismatch { expected: usize, got: usize },
+    MalformedProof,
+    InvalidTraceOpening { index: usize },
+    InvalidFriOpening { layer: usize, index: usize },
+    ConstraintMismatch { index: usize },
+    FriFoldMismatch { layer: usize, index: usize },
+}
+
+impl fmt::Display for StarkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StarkError::TraceLengthMismatch { expected, got } => {
+                write!(f, "trace of {} rows expected, proof has {}", expected, got)
+            }
+            StarkError::MalformedProof => write!(f, "proof does not have the expected shape"),
+            StarkError::InvalidTraceOpening { index } => {
+                write!(f, "trace opening at {} is not in the committed trace", index)
+            }
+            StarkError::InvalidFriOpening { layer, index } => {
+                write!(f, "FRI layer {} opening at {} is not in the committed layer", layer, index)
+            }
+            StarkError::ConstraintMismatch { index } => {
+                write!(f, "composition polynomial does not match the trace at {}", index)
+            }
+            StarkError::FriFoldMismatch { layer, index } => {
+                write!(f, "FRI layer {} is not the fold of the previous one at {}", layer, index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StarkError {}
+
+/// Boundary values and the random coefficients combining the constraints
+struct Composition {
+    seed: Felt,
+    output: Felt,
+    alphas: [Felt; 3],
+}
+
+/// Evaluation domain shared by the prover and the verifier
+struct Domain {
+    trace_length: usize,
+    size: usize,
+    trace_root: Felt,      // ω, generates the trace domain
+    evaluation_root: Felt, // generates the evaluation domain, before the coset shift
+}
+
+impl Domain {
+    fn new(trace_length: usize) -> Self {
+        Domain {
+            trace_length,
+            size: trace_length * BLOWUP_FACTOR,
+            trace_root: Felt::root_of_unity(trace_length),
+            evaluation_root: Felt::root_of_unity(trace_length * BLOWUP_FACTOR),
+        }
+    }
+
+    /// Point `index` of the evaluation coset
+    fn point(&self, index: usize) -> Felt {
+        Felt(GENERATOR) * self.evaluation_root.pow(index as u64)
+    }
+
+    /// The composition polynomial at `x`, from the trace at `x` and `ω·x` and the update column at `x`.
+    /// Each constraint is divided by the polynomial vanishing where it must hold, so the sum has
+    /// degree below the trace length exactly when every constraint holds.
+    fn composition(&self, coefficients: &Composition, x: Felt, trace: Felt, next_trace: Felt, update: Felt) -> Felt {
+        let last_row = self.trace_root.pow(self.trace_length as u64 - 1);
+        // a(ωx) = a(x)² + u(x) on every row but the last
+        let transition = (next_trace - trace * trace - update) * (x - last_row)
+            .div(x.pow(self.trace_length as u64) - Felt::ONE);
+        let first = (trace - coefficients.seed).div(x - Felt::ONE);
+        let last = (trace - coefficients.output).div(x - last_row);
+        let alphas = &coefficients.alphas;
+        alphas[0] * transition + alphas[1] * first + alphas[2] * last
+    }
+}
+
+/// Proves the honest execution of `public_input`
+pub fn prove(public_input: &PublicInput) -> StarkProof {
+    prove_trace(public_input, public_input.trace())
+}
+
+/// Commits to `trace` and proves it satisfies the constraints of `public_input`. Exposed
+/// so that a dishonest trace can be shown to fail verification.
+pub fn prove_trace(public_input: &PublicInput, trace: Vec<Felt>) -> StarkProof {
+    let domain = Domain::new(public_input.trace_length());
+    assert_eq!(trace.len(), domain.trace_length, "trace does not match the public input");
+    let output = trace[trace.len() - 1];
+    let mut transcript = Transcript::new(public_input);
+
+    // Trace commitment
+    let trace_evaluations = extend(&trace, Felt(GENERATOR), BLOWUP_FACTOR);
+    let update_evaluations = extend(&public_input.update_column(), Felt(GENERATOR), BLOWUP_FACTOR);
+    let trace_tree = MerkleTree::new(&trace_evaluations);
+    transcript.absorb(&format!("trace-{}-output-{}", trace_tree.root(), output));
+    let coefficients = Composition {
+        seed: public_input.seed,
+        output,
+        alphas: [transcript.challenge(), transcript.challenge(), transcript.challenge()],
+    };
+
+    // Composition polynomial, the first FRI layer
+    let mut layer: Vec<Felt> = (0..domain.size)
+        .map(|i| {
+            domain.composition(
+                &coefficients,
+                domain.point(i),
+                trace_evaluations[i],
+                trace_evaluations[(i + BLOWUP_FACTOR) % domain.size],
+                update_evaluations[i],
+            )
+        })
+        .collect();
+
+    // FRI: fold f(x) = g(x²) + x·h(x²) into g + β·h until the degree bound reaches one
+    let mut offset = Felt(GENERATOR);
+    let mut root = domain.evaluation_root;
+    let mut layers = vec![];
+    while layer.len() > BLOWUP_FACTOR {
+        let tree = MerkleTree::new(&layer);
+        transcript.absorb(&format!("fri-{}", tree.root()));
+        let beta = transcript.challenge();
+
+        let half = layer.len() / 2;
+        let two_inverse = Felt::new(2).inverse().expect("2 is invertible");
+        let mut x = offset;
+        let folded = (0..half)
+            .map(|i| {
+                let (plus, minus) = (layer[i], layer[i + half]);
+                let value = (plus + minus) * two_inverse + beta * (plus - minus) * two_inverse.div(x);
+                x = x * root;
+                value
+            })
+            .collect();
+        layers.push((layer, tree));
+        layer = folded;
+        offset = offset * offset;
+        root = root * root;
+    }
+    let fri_final = layer[0];
+    transcript.absorb(&format!("final-{}", fri_final));
+
+    let queries = (0..NUM_QUERIES)
+        .map(|_| {
+            let index = transcript.challenge_index(domain.size);
+            let next_index = (index + BLOWUP_FACTOR) % domain.size;
+            let fri_layers = layers
+                .iter()
+                .map(|(values, tree)| {
+                    let i = index % values.len();
+                    let sibling = (i + values.len() / 2) % values.len();
+                    (
+                        Opening { value: values[i], path: tree.open(i) },
+                        Opening { value: values[sibling], path: tree.open(sibling) },
+                    )
+                })
+                .collect();
+            Query {
+                index,
+                trace: Opening { value: trace_evaluations[index], path: trace_tree.open(index) },
+                next_trace: Opening { value: trace_evaluations[next_index], path: trace_tree.open(next_index) },
+                fri_layers,
+            }
+        })
+        .collect();
+
+    StarkProof {
+        trace_length: domain.trace_length,
+        output,
+        trace_root: trace_tree.root(),
+        fri_roots: layers.iter().map(|(_, tree)| tree.root()).collect(),
+        fri_final,
+        queries,
+    }
+}
+
+/// Checks a STARK proof against its public input
+pub fn verify(public_input: &PublicInput, proof: &StarkProof) -> Result<(), StarkError> {
+    let expected_length = public_input.trace_length();
+    if proof.trace_length != expected_length {
+        return Err(StarkError::TraceLengthMismatch { expected: expected_length, got: proof.trace_length });
+    }
+    let domain = Domain::new(proof.trace_length);
+    let fri_layer_count = proof.trace_length.trailing_zeros() as usize;
+    if proof.fri_roots.len() != fri_layer_count || proof.queries.len() != NUM_QUERIES {
+        return Err(StarkError::MalformedProof);
+    }
+
+    // Replay the transcript to recover the challenges
+    let mut transcript = Transcript::new(public_input);
+    transcript.absorb(&format!("trace-{}-output-{}", proof.trace_root, proof.output));
+    let coefficients = Composition {
+        seed: public_input.seed,
+        output: proof.output,
+        alphas: [transcript.challenge(), transcript.challenge(), transcript.challenge()],
+    };
+    let betas: Vec<Felt> = proof
+        .fri_roots
+        .iter()
+        .map(|root| {
+            transcript.absorb(&format!("fri-{}", root));
+            transcript.challenge()
+        })
+        .collect();
+    transcript.absorb(&format!("final-{}", proof.fri_final));
+
+    let update_column = public_input.update_column();
+    let two_inverse = Felt::new(2).inverse().expect("2 is invertible");
+    for query in &proof.queries {
+        let index = transcript.challenge_index(domain.size);
+        if query.index != index || query.fri_layers.len() != fri_layer_count {
+            return Err(StarkError::MalformedProof);
+        }
+        let next_index = (index + BLOWUP_FACTOR) % domain.size;
+        if !MerkleTree::verify(&proof.trace_root, index, query.trace.value, &query.trace.path)
+            || !MerkleTree::verify(&proof.trace_root, next_index, query.next_trace.value, &query.next_trace.path)
+        {
+            return Err(StarkError::InvalidTraceOpening { index });
+        }
+
+        // The composition polynomial must follow from the trace at this point
+        let x = domain.point(index);
+        let update = evaluate_interpolant(&update_column, domain.trace_root, x);
+        let composition = domain.composition(&coefficients, x, query.trace.value, query.next_trace.value, update);
+
+        // Then every FRI layer must be the fold of the one before
+        let mut expected = composition;
+        let (mut size, mut offset, mut root) = (domain.size, Felt(GENERATOR), domain.evaluation_root);
+        for (layer, (opening, sibling)) in query.fri_layers.iter().enumerate() {
+            let i = index % size;
+            let sibling_index = (i + size / 2) % size;
+            if !MerkleTree::verify(&proof.fri_roots[layer], i, opening.value, &opening.path)
+                || !MerkleTree::verify(&proof.fri_roots[layer], sibling_index, sibling.value, &sibling.path)
+            {
+                return Err(StarkError::InvalidFriOpening { layer, index: i });
+            }
+            if opening.value != expected {
+                return Err(if layer == 0 {
+                    StarkError::ConstraintMismatch { index }
+                } else {
+                    StarkError::FriFoldMismatch { layer, index: i }
+                });
+            }
+
+            let (plus, minus, x) = if i < size / 2 {
+                (opening.value, sibling.value, offset * root.pow(i as u64))
+            } else {
+                (sibling.value, opening.value, offset * root.pow(sibling_index as u64))
+            };
+            expected = (plus + minus) * two_inverse + betas[layer] * (plus - minus) * two_inverse.div(x);
+            size /= 2;
+            offset = offset * offset;
+            root = root * root;
+        }
+        if expected != proof.fri_final {
+            return Err(StarkError::FriFoldMismatch { layer: fri_layer_count, index: index % size });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,346 @@
+//account state helpers
+//every balance mutation in the mempool and the sequencer goes through these checked helpers
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use sha2::{Sha256, Digest};
+use crate::utils::U256;
+
+/// Address of the fee token contract; account balances live in its storage
+pub const FEE_TOKEN_ADDRESS: &str = "fee_token";
+
+/// Errors raised when a balance update would underflow or overflow
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    InsufficientBalance {
+        account: String,
+        balance: U256,
+        required: U256,
+    },
+    BalanceOverflow {
+        account: String,
+    },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::InsufficientBalance { account, balance, required } => write!(
+                f,
+                "insufficient balance for {}: has {}, needs {}",
+                account, balance, required
+            ),
+            StateError::BalanceOverflow { account } => {
+                write!(f, "balance of {} would overflow u256", account)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Returns the balance of an account, treating unknown accounts as empty
+pub fn get_balance(balances: &HashMap<String, U256>, account: &str) -> U256 {
+    balances.get(account).copied().unwrap_or(U256::ZERO)
+}
+
+/// Adds `amount` to an account balance
+pub fn credit(balances: &mut HashMap<String, U256>, account: &str, amount: U256) -> Result<(), StateError> {
+    let new_balance = get_balance(balances, account)
+        .checked_add(amount)
+        .ok_or_else(|| StateError::BalanceOverflow { account: account.to_string() })?;
+    balances.insert(account.to_string(), new_balance);
+    Ok(())
+}
+
+/// Subtracts `amount` from an account balance
+pub fn debit(balances: &mut HashMap<String, U256>, account: &str, amount: U256) -> Result<(), StateError> {
+    let balance = get_balance(balances, account);
+    let new_balance = balance.checked_sub(amount).ok_or_else(|| StateError::InsufficientBalance {
+        account: account.to_string(),
+        balance,
+        required: amount,
+    })?;
+    balances.insert(account.to_string(), new_balance);
+    Ok(())
+}
+
+/// Checks that `amount` can move between two accounts without writing anything
+pub fn check_transfer(balances: &HashMap<String, U256>, from: &str, to: &str, amount: U256) -> Result<(), StateError> {
+    let from_balance = get_balance(balances, from);
+    if from_balance < amount {
+        return Err(StateError::InsufficientBalance {
+            account: from.to_string(),
+            balance: from_balance,
+            required: amount,
+        });
+    }
+    if from != to && get_balance(balances, to).checked_add(amount).is_none() {
+        return Err(StateError::BalanceOverflow { account: to.to_string() });
+    }
+    Ok(())
+}
+
+/// Moves `amount` between two accounts. Both sides are checked before
+/// anything is written, so a failed transfer leaves balances untouched.
+pub fn transfer(balances: &mut HashMap<String, U256>, from: &str, to: &str, amount: U256) -> Result<(), StateError> {
+    check_transfer(balances, from, to, amount)?;
+    if from == to {
+        return Ok(());
+    }
+
+    debit(balances, from, amount)?;
+    credit(balances, to, amount)
+}
+
+/// Copy of the whole L2 state at one point in time
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateSnapshot {
+    pub balances: HashMap<String, U256>,
+    pub nonces: HashMap<String, u64>,
+    pub storage: HashMap<String, HashMap<String, U256>>, // contract -> storage key -> value
+    pub contract_classes: HashMap<String, String>, // contract address -> class hash
+    pub declared_classes: HashMap<String, String>, // class hash -> compiled class hash
+}
+
+impl StateSnapshot {
+    /// Commitment to the whole state: a hash over every balance, nonce and storage slot in key order
+    pub fn state_root(&self) -> String {
+        let mut hasher = Sha256::new();
+        for (account, balance) in self.balances.iter().collect::<BTreeMap<_, _>>() {
+            hasher.update(format!("balance-{}-{}", account, balance));
+        }
+        for (account, nonce) in self.nonces.iter().collect::<BTreeMap<_, _>>() {
+            hasher.update(format!("nonce-{}-{}", account, nonce));
+        }
+        for (contract, slots) in self.storage.iter().collect::<BTreeMap<_, _>>() {
+            for (key, value) in slots.iter().collect::<BTreeMap<_, _>>() {
+                hasher.update(format!("storage-{}-{}-{}", contract, key, value));
+            }
+        }
+        for (contract, class_hash) in self.contract_classes.iter().collect::<BTreeMap<_, _>>() {
+            hasher.update(format!("class-{}-{}", contract, class_hash));
+        }
+        for (class_hash, compiled_class_hash) in self.declared_classes.iter().collect::<BTreeMap<_, _>>() {
+            hasher.update(format!("declared-{}-{}", class_hash, compiled_class_hash));
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Applies a diff as published on L1: storage of the fee token goes back into the balances
+    pub fn apply(&mut self, diff: &StateDiff) {
+        for (contract, updates) in &diff.storage_updates {
+            let updates = updates.iter().map(|(key, value)| (key.clone(), *value));
+            match contract.as_str() {
+                FEE_TOKEN_ADDRESS => self.balances.extend(updates),
+                _ => self.storage.entry(contract.clone()).or_default().extend(updates),
+            }
+        }
+        self.nonces.extend(diff.nonce_updates.iter().map(|(account, nonce)| (account.clone(), *nonce)));
+        self.contract_classes.extend(diff.deployed_contracts.clone());
+        self.contract_classes.extend(diff.replaced_classes.clone());
+        self.declared_classes.extend(diff.declared_classes.clone());
+    }
+}
+
+/// Writes of one transaction, kept apart from the committed state while it runs.
+/// Only what the transaction wrote is held here; reads fall through to the committed state.
+/// If the transaction succeeds the overlay is committed; if it reverts the overlay is dropped
+/// and nothing it wrote survives.
+#[derive(Debug, Clone, Default)]
+pub struct StateOverlay {
+    pub balances: HashMap<String, U256>, // balances this transaction touched
+    pub storage: HashMap<String, HashMap<String, U256>>, // contract -> storage slots written so far
+    pub contract_classes: HashMap<String, String>, // contracts deployed by this transaction
+    pub declared_classes: HashMap<String, String>, // classes declared by this transaction
+}
+
+impl StateOverlay {
+    /// The balance of an account as this transaction sees it
+    pub fn balance(&self, committed: &HashMap<String, U256>, account: &str) -> U256 {
+        self.balances.get(account).copied().unwrap_or_else(|| get_balance(committed, account))
+    }
+
+    /// The overlay's balances, with `accounts` read in from `committed` on first touch, so the
+    /// balance helpers can update them in place
+    pub fn balances_for(&mut self, committed: &HashMap<String, U256>, accounts: &[&str]) -> &mut HashMap<String, U256> {
+        for account in accounts {
+            if !self.balances.contains_key(*account) {
+                if let Some(balance) = committed.get(*account) {
+                    self.balances.insert(account.to_string(), *balance);
+                }
+            }
+        }
+        &mut self.balances
+    }
+
+    /// A storage slot as this transaction sees it
+    pub fn storage_read(&self, committed: &HashMap<String, HashMap<String, U256>>, contract: &str, key: &str) -> U256 {
+        self.storage
+            .get(contract)
+            .and_then(|slots| slots.get(key))
+            .or_else(|| committed.get(contract).and_then(|slots| slots.get(key)))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn storage_write(&mut self, contract: &str, key: &str, value: U256) {
+        self.storage.entry(contract.to_string()).or_default().insert(key.to_string(), value);
+    }
+
+    /// What the transaction wrote, as a state diff. Balances are reported as storage of
+    /// the fee token, and contracts it deployed as deployments.
+    pub fn state_diff(&self) -> StateDiff {
+        let mut diff = StateDiff::default();
+        if !self.balances.is_empty() {
+            diff.storage_updates.insert(
+                FEE_TOKEN_ADDRESS.to_string(),
+                self.balances.iter().map(|(account, balance)| (account.clone(), *balance)).collect(),
+            );
+        }
+        for (contract, slots) in &self.storage {
+            diff.storage_updates
+                .entry(contract.clone())
+                .or_default()
+                .extend(slots.iter().map(|(key, value)| (key.clone(), *value)));
+        }
+        diff.deployed_contracts.extend(self.contract_classes.clone());
+        diff.declared_classes.extend(self.declared_classes.clone());
+        diff
+    }
+
+    /// Writes everything the transaction changed into the committed state
+    pub fn commit(
+        self,
+        balances: &mut HashMap<String, U256>,
+        storage: &mut HashMap<String, HashMap<String, U256>>,
+        contract_classes: &mut HashMap<String, String>,
+        declared_classes: &mut HashMap<String, String>,
+    ) {
+        balances.extend(self.balances);
+        for (contract, slots) in self.storage {
+            storage.entry(contract).or_default().extend(slots);
+        }
+        contract_classes.extend(self.contract_classes);
+        declared_classes.extend(self.declared_classes);
+    }
+}
+
+/// State changes made by one block, as published to L1
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub deployed_contracts: BTreeMap<String, String>, // new contract address -> class hash
+    pub storage_updates: BTreeMap<String, BTreeMap<String, U256>>, // contract -> storage key -> new value
+    pub nonce_updates: BTreeMap<String, u64>,
+    pub declared_classes: BTreeMap<String, String>, // class hash -> compiled class hash
+    pub replaced_classes: BTreeMap<String, String>, // existing contract address -> new class hash
+}
+
+impl StateDiff {
+    /// Diffs two snapshots of the state. Only final values of changed entries are kept,
+    /// and balances are reported as storage of the fee token contract.
+    pub fn from_changes(before: &StateSnapshot, after: &StateSnapshot) -> Self {
+        let mut diff = StateDiff::default();
+
+        for (account, balance) in &after.balances {
+            if before.balances.get(account) != Some(balance) {
+                diff.storage_updates
+                    .entry(FEE_TOKEN_ADDRESS.to_string())
+                    .or_default()
+                    .insert(account.clone(), *balance);
+            }
+        }
+        for (contract, slots) in &after.storage {
+            for (key, value) in slots {
+                let old = before.storage.get(contract).and_then(|slots| slots.get(key));
+                if old != Some(value) {
+                    diff.storage_updates
+                        .entry(contract.clone())
+                        .or_default()
+                        .insert(key.clone(), *value);
+                }
+            }
+        }
+        for (account, nonce) in &after.nonces {
+            if before.nonces.get(account) != Some(nonce) {
+                diff.nonce_updates.insert(account.clone(), *nonce);
+            }
+        }
+        for (contract, class_hash) in &after.contract_classes {
+            match before.contract_classes.get(contract) {
+                None => {
+                    diff.deployed_contracts.insert(contract.clone(), class_hash.clone());
+                }
+                Some(old) if old != class_hash => {
+                    diff.replaced_classes.insert(contract.clone(), class_hash.clone());
+                }
+                Some(_) => {}
+            }
+        }
+        for (class_hash, compiled_class_hash) in &after.declared_classes {
+            if !before.declared_classes.contains_key(class_hash) {
+                diff.declared_classes.insert(class_hash.clone(), compiled_class_hash.clone());
+            }
+        }
+        diff
+    }
+
+    /// Folds the diffs of consecutive blocks into one, as published for an aggregated proof.
+    /// Later values win, and a class replaced after its deployment in the same range is
+    /// reported as deployed with the new class.
+    pub fn squash<'a>(diffs: impl IntoIterator<Item = &'a StateDiff>) -> Self {
+        let mut squashed = StateDiff::default();
+        for diff in diffs {
+            for (contract, updates) in &diff.storage_updates {
+                squashed
+                    .storage_updates
+                    .entry(contract.clone())
+                    .or_default()
+                    .extend(updates.iter().map(|(key, value)| (key.clone(), *value)));
+            }
+            squashed.nonce_updates.extend(diff.nonce_updates.iter().map(|(a, n)| (a.clone(), *n)));
+            squashed.deployed_contracts.extend(diff.deployed_contracts.clone());
+            squashed.declared_classes.extend(diff.declared_classes.clone());
+            for (contract, class_hash) in &diff.replaced_classes {
+                match squashed.deployed_contracts.get_mut(contract) {
+                    Some(deployed_class) => *deployed_class = class_hash.clone(),
+                    None => {
+                        squashed.replaced_classes.insert(contract.clone(), class_hash.clone());
+                    }
+                }
+            }
+        }
+        squashed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deployed_contracts.is_empty()
+            && self.storage_updates.is_empty()
+            && self.nonce_updates.is_empty()
+            && self.declared_classes.is_empty()
+            && self.replaced_classes.is_empty()
+    }
+
+    /// Hash of the diff, used to bind a proof to the data published on L1
+    pub fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for (contract, updates) in &self.storage_updates {
+            for (key, value) in updates {
+                hasher.update(format!("storage-{}-{}-{}", contract, key, value));
+            }
+        }
+        for (account, nonce) in &self.nonce_updates {
+            hasher.update(format!("nonce-{}-{}", account, nonce));
+        }
+        for (contract, class_hash) in &self.deployed_contracts {
+            hasher.update(format!("deployed-{}-{}", contract, class_hash));
+        }
+        for (contract, class_hash) in &self.replaced_classes {
+            hasher.update(format!("replaced-{}-{}", contract, class_hash));
+        }
+        for (class_hash, compiled_class_hash) in &self.declared_classes {
+            hasher.update(format!("declared-{}-{}", class_hash, compiled_class_hash));
+        }
+        format!("{:x}", hasher.finalize())
+    }
+}
@@ -0,0 +1,437 @@
+//storage
+//key-value storage for blocks, state and the mempool: in memory, or in a file so the node survives a restart
+
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use sha2::{Sha256, Digest};
+use crate::block::L2Block;
+use crate::codec::{self, Codec};
+use crate::gas::GasPrices;
+use crate::state::StateSnapshot;
+use crate::transaction::Transaction;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageError {
+    Io { path: String, reason: String },
+    Corrupt { key: String, reason: String }, // a stored value that cannot be read back
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io { path, reason } => write!(f, "cannot access {}: {}", path, reason),
+            StorageError::Corrupt { key, reason } => write!(f, "stored {} is corrupt: {}", key, reason),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+/// Writes applied together: a storage applies all of them or none
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteBatch {
+    pub writes: Vec<(String, Option<Vec<u8>>)>, // key -> new value, or None to delete it
+}
+
+impl WriteBatch {
+    pub fn put(&mut self, key: impl Into<String>, value: Vec<u8>) {
+        self.writes.push((key.into(), Some(value)));
+    }
+
+    pub fn delete(&mut self, key: impl Into<String>) {
+        self.writes.push((key.into(), None));
+    }
+}
+
+/// Key-value store the node keeps its data in. Keys are ordered.
+pub trait Storage: Send {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+
+    /// Every key that starts with `prefix`, in order
+    fn keys(&self, prefix: &str) -> Result<Vec<String>, StorageError>;
+
+    /// Applies the whole batch, or nothing if it fails
+    fn write(&mut self, batch: WriteBatch) -> Result<(), StorageError>;
+
+    fn put(&mut self, key: &str, value: Vec<u8>) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        batch.put(key, value);
+        self.write(batch)
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        batch.delete(key);
+        self.write(batch)
+    }
+}
+
+fn apply(entries: &mut BTreeMap<String, Vec<u8>>, batch: WriteBatch) {
+    for (key, value) in batch.writes {
+        match value {
+            Some(value) => entries.insert(key, value),
+            None => entries.remove(&key),
+        };
+    }
+}
+
+fn keys_with_prefix(entries: &BTreeMap<String, Vec<u8>>, prefix: &str) -> Vec<String> {
+    entries
+        .range(prefix.to_string()..)
+        .map(|(key, _)| key)
+        .take_while(|key| key.starts_with(prefix))
+        .cloned()
+        .collect()
+}
+
+/// Storage that lives as long as the process. Clones share their entries, so a node
+/// reopened on a clone finds what the previous one saved.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    entries: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self.entries.lock().unwrap().get(key).cloned())
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+        Ok(keys_with_prefix(&self.entries.lock().unwrap(), prefix))
+    }
+
+    fn write(&mut self, batch: WriteBatch) -> Result<(), StorageError> {
+        apply(&mut self.entries.lock().unwrap(), batch);
+        Ok(())
+    }
+}
+
+/// Storage in a single append-only file. Every batch is appended as one record,
+/// `<length> <sha256>\n<writes>`, and synced before `write` returns. Opening the file
+/// replays the records; a last record cut short by a crash is dropped, so a batch is
+/// either fully there after a restart or not at all. A bad record anywhere else means
+/// the file was damaged, and opening it fails rather than lose the records after it.
+pub struct FileStorage {
+    path: PathBuf,
+    entries: BTreeMap<String, Vec<u8>>,
+    log: File,
+    len: u64, // end of the last complete record; a failed write is cut back to it
+    broken: bool, // a failed write could not be cut back, so nothing may be appended after it
+}
+
+impl FileStorage {
+    /// Opens the storage at `path`, creating the file if it does not exist yet
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let path = path.as_ref().to_path_buf();
+        let io_error = |err: std::io::Error| StorageError::Io { path: path.display().to_string(), reason: err.to_string() };
+
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(io_error(err)),
+        };
+        let mut entries = BTreeMap::new();
+        let mut offset = 0;
+        let mut replayed_writes = 0;
+        while offset < data.len() {
+            match read_record(&data[offset..]) {
+                Ok(Some((batch, len))) => {
+                    replayed_writes += batch.writes.len();
+                    apply(&mut entries, batch);
+                    offset += len;
+                }
+                Ok(None) => break,
+                Err(reason) => {
+                    return Err(StorageError::Corrupt {
+                        key: path.display().to_string(),
+                        reason: format!("record at byte {}: {}", offset, reason),
+                    })
+                }
+            }
+        }
+
+        let log = OpenOptions::new().create(true).append(true).open(&path).map_err(io_error)?;
+        if offset < data.len() {
+            println!(
+                "[Storage] ⚠️ Dropping an unfinished write ({} bytes) at the end of {}",
+                data.len() - offset,
+                path.display()
+            );
+            log.set_len(offset as u64).map_err(io_error)?;
+        }
+
+        let mut storage = FileStorage { path, entries, log, len: offset as u64, broken: false };
+        // Rewrite the file once most of it is overwritten or deleted values
+        if replayed_writes > 2 * storage.entries.len() + 64 {
+            storage.compact()?;
+        }
+        Ok(storage)
+    }
+
+    /// Rewrites the file with only the current entries, as a single record
+    pub fn compact(&mut self) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        for (key, value) in &self.entries {
+            batch.put(key.clone(), value.clone());
+        }
+        let compacted = self.path.with_extension("compacting");
+        let io_error = |err: std::io::Error| StorageError::Io { path: compacted.display().to_string(), reason: err.to_string() };
+        let record = encode_record(&batch);
+        let mut file = File::create(&compacted).map_err(io_error)?;
+        file.write_all(&record).map_err(io_error)?;
+        file.sync_all().map_err(io_error)?;
+        fs::rename(&compacted, &self.path).map_err(io_error)?;
+        self.log = OpenOptions::new().append(true).open(&self.path).map_err(|err| self.io_error(err))?;
+        self.len = record.len() as u64;
+        self.broken = false;
+        Ok(())
+    }
+
+    fn io_error(&self, err: std::io::Error) -> StorageError {
+        StorageError::Io { path: self.path.display().to_string(), reason: err.to_string() }
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self.entries.get(key).cloned())
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+        Ok(keys_with_prefix(&self.entries, prefix))
+    }
+
+    fn write(&mut self, batch: WriteBatch) -> Result<(), StorageError> {
+        if self.broken {
+            return Err(StorageError::Io {
+                path: self.path.display().to_string(),
+                reason: "an earlier failed write could not be undone".to_string(),
+            });
+        }
+        let record = encode_record(&batch);
+        if let Err(err) = self.log.write_all(&record).and_then(|_| self.log.sync_data()) {
+            // Part of the record may be in the file: cut it off so the next record does not
+            // follow a broken one
+            if self.log.set_len(self.len).is_err() {
+                self.broken = true;
+            }
+            return Err(self.io_error(err));
+        }
+        self.len += record.len() as u64;
+        apply(&mut self.entries, batch);
+        Ok(())
+    }
+}
+
+fn checksum(payload: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(payload);
+    format!("{:x}", hasher.finalize())
+}
+
+/// A batch as one log record. Each write is `P<key length>:<key><value length>:<value>`
+/// or `D<key length>:<key>`.
+fn encode_record(batch: &WriteBatch) -> Vec<u8> {
+    let mut payload = vec![];
+    for (key, value) in &batch.writes {
+        payload.push(if value.is_some() { b'P' } else { b'D' });
+        payload.extend_from_slice(format!("{}:", key.len()).as_bytes());
+        payload.extend_from_slice(key.as_bytes());
+        if let Some(value) = value {
+            payload.extend_from_slice(format!("{}:", value.len()).as_bytes());
+            payload.extend_from_slice(value);
+        }
+    }
+    let mut record = format!("{} {}\n", payload.len(), checksum(&payload)).into_bytes();
+    record.extend(payload);
+    record
+}
+
+/// Reads the record at the start of `data` and its length, or None if it runs past the
+/// end of `data`, as the last record does when a crash cuts it short
+fn read_record(data: &[u8]) -> Result<Option<(WriteBatch, usize)>, String> {
+    let Some(newline) = data.iter().position(|&b| b == b'\n') else {
+        return Ok(None);
+    };
+    let invalid_header = || "invalid record header".to_string();
+    let header = std::str::from_utf8(&data[..newline]).map_err(|_| invalid_header())?;
+    let (len, expected_checksum) = header.split_once(' ').ok_or_else(invalid_header)?;
+    let len: usize = len.parse().map_err(|_| invalid_header())?;
+    let Some(payload) = data.get(newline + 1..newline + 1 + len) else {
+        return Ok(None);
+    };
+    if checksum(payload) != expected_checksum {
+        return Err("checksum mismatch".to_string());
+    }
+
+    let invalid_write = || "invalid write in record".to_string();
+    let mut batch = WriteBatch::default();
+    let mut rest = payload;
+    while let Some((&op, tail)) = rest.split_first() {
+        rest = tail;
+        let key = take_prefixed(&mut rest).and_then(|key| String::from_utf8(key.to_vec()).ok()).ok_or_else(invalid_write)?;
+        match op {
+            b'P' => batch.put(key, take_prefixed(&mut rest).ok_or_else(invalid_write)?.to_vec()),
+            b'D' => batch.delete(key),
+            _ => return Err(invalid_write()),
+        }
+    }
+    Ok(Some((batch, newline + 1 + len)))
+}
+
+fn take_prefixed<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let colon = data.iter().position(|&b| b == b':')?;
+    let len: usize = std::str::from_utf8(&data[..colon]).ok()?.parse().ok()?;
+    let value = data.get(colon + 1..colon + 1 + len)?;
+    *data = &data[colon + 1 + len..];
+    Some(value)
+}
+
+const BLOCK_PREFIX: &str = "block/";
+const STATE_KEY: &str = "state";
+const GENESIS_KEY: &str = "genesis";
+const GAS_PRICES_KEY: &str = "gas_prices";
+const MEMPOOL_PREFIX: &str = "mempool/";
+const REJECTED_KEY: &str = "rejected";
+
+fn block_key(block_number: u64) -> String {
+    format!("{}{:020}", BLOCK_PREFIX, block_number)
+}
+
+fn queued_key(position: u64) -> String {
+    format!("{}{:020}", MEMPOOL_PREFIX, position)
+}
+
+fn decode<T: Codec>(key: &str, bytes: &[u8]) -> Result<T, StorageError> {
+    codec::from_bytes(bytes).map_err(|reason| StorageError::Corrupt { key: key.to_string(), reason })
+}
+
+/// Everything a node saved, as `NodeStore::load` finds it
+#[derive(Debug, Clone, Default)]
+pub struct SavedNode {
+    pub blocks: Vec<L2Block>, // in order; proofs are not kept
+    pub genesis: Option<StateSnapshot>, // before the first block
+    pub state: Option<StateSnapshot>, // after the latest block
+    pub gas_prices: Option<GasPrices>, // for the block after the latest one
+    pub queued: Vec<Transaction>, // in the order they entered the mempool
+    pub rejected: Vec<String>, // hashes the mempool refuses to take again
+}
+
+/// The node's data on top of a `Storage`. A sealed block is saved at once with the
+/// state it left, the gas prices of the next block and the mempool. Transactions that
+/// enter the mempool between blocks are saved as they arrive.
+pub struct NodeStore {
+    storage: Box<dyn Storage>,
+    next_queued: u64, // position of the next transaction saved into the mempool
+    unsaved: Vec<L2Block>, // sealed blocks whose save failed; they go out with the next block
+}
+
+impl NodeStore {
+    pub fn new(storage: Box<dyn Storage>) -> Result<Self, StorageError> {
+        let next_queued = match storage.keys(MEMPOOL_PREFIX)?.last() {
+            Some(key) => {
+                let position: u64 = key[MEMPOOL_PREFIX.len()..].parse().map_err(|_| StorageError::Corrupt {
+                    key: key.clone(),
+                    reason: "invalid mempool position".to_string(),
+                })?;
+                position + 1
+            }
+            None => 0,
+        };
+        Ok(NodeStore { storage, next_queued, unsaved: vec![] })
+    }
+
+    /// Saves a transaction that just entered the mempool
+    pub fn queue_transaction(&mut self, tx: &Transaction) -> Result<(), StorageError> {
+        self.storage.put(&queued_key(self.next_queued), codec::to_bytes(tx))?;
+        self.next_queued += 1;
+        Ok(())
+    }
+
+    /// Saves the state the first block will start from
+    pub fn save_genesis(&mut self, state: &StateSnapshot) -> Result<(), StorageError> {
+        self.storage.put(GENESIS_KEY, codec::to_bytes(state))
+    }
+
+    /// Saves a sealed block with the state it left and the gas prices of the next block,
+    /// and replaces the saved mempool with `queued` and `rejected`, all in one batch.
+    /// If the write fails, the block is kept and saved with the next one, so the saved
+    /// chain never skips a block.
+    pub fn save_block(
+        &mut self,
+        block: &L2Block,
+        state: &StateSnapshot,
+        gas_prices: &GasPrices,
+        queued: &VecDeque<Transaction>,
+        rejected: &HashSet<String>,
+    ) -> Result<(), StorageError> {
+        self.unsaved.push(block.clone());
+        let mut batch = WriteBatch::default();
+        for block in &self.unsaved {
+            batch.put(block_key(block.header.block_number), codec::to_bytes(block));
+        }
+        batch.put(STATE_KEY, codec::to_bytes(state));
+        batch.put(GAS_PRICES_KEY, codec::to_bytes(gas_prices));
+        for key in self.storage.keys(MEMPOOL_PREFIX)? {
+            batch.delete(key);
+        }
+        for (position, tx) in queued.iter().enumerate() {
+            batch.put(queued_key(position as u64), codec::to_bytes(tx));
+        }
+        let mut rejected: Vec<String> = rejected.iter().cloned().collect();
+        rejected.sort();
+        batch.put(REJECTED_KEY, codec::to_bytes(&rejected));
+
+        self.storage.write(batch)?;
+        self.unsaved.clear();
+        self.next_queued = queued.len() as u64;
+        Ok(())
+    }
+
+    /// Reads back what was saved. The blocks must still match their headers, chain from
+    /// one another, and the first one must start from the saved genesis state.
+    pub fn load(&self) -> Result<SavedNode, StorageError> {
+        let mut saved = SavedNode { genesis: self.read(GENESIS_KEY)?, ..SavedNode::default() };
+        for key in self.storage.keys(BLOCK_PREFIX)? {
+            let block: L2Block = self.read(&key)?.expect("listed keys exist");
+            let corrupt = |reason: String| StorageError::Corrupt { key: key.clone(), reason };
+            block.verify_integrity().map_err(corrupt)?;
+            if let Some(parent) = saved.blocks.last() {
+                if block.header.parent_block_hash != parent.get_block_hash() {
+                    return Err(corrupt(format!("block #{} does not chain from its parent", block.header.block_number)));
+                }
+            }
+            saved.blocks.push(block);
+        }
+        if let Some(first) = saved.blocks.first() {
+            let genesis_state_root = saved.genesis.as_ref().map(StateSnapshot::state_root);
+            if genesis_state_root.as_ref() != Some(&first.header.parent_state_root) {
+                return Err(StorageError::Corrupt {
+                    key: GENESIS_KEY.to_string(),
+                    reason: format!("block #{} does not start from the saved genesis state", first.header.block_number),
+                });
+            }
+        }
+        saved.state = self.read(STATE_KEY)?;
+        saved.gas_prices = self.read(GAS_PRICES_KEY)?;
+        for key in self.storage.keys(MEMPOOL_PREFIX)? {
+            saved.queued.push(self.read(&key)?.expect("listed keys exist"));
+        }
+        saved.rejected = self.read(REJECTED_KEY)?.unwrap_or_default();
+        Ok(saved)
+    }
+
+    fn read<T: Codec>(&self, key: &str) -> Result<Option<T>, StorageError> {
+        self.storage.get(key)?.map(|bytes| decode(key, &bytes)).transpose()
+    }
+}
@@ -1,11 +1,17 @@
 use std::sync::atomic::{AtomicUsize, Ordering};
 use sha2::{Sha256, Digest};
+use crate::account;
+use crate::event::Event;
+use crate::gas::{self, ExecutionResources, FeeCharge, GasPrices, GasVector, ResourceBounds, ResourceBoundsMapping, ResourceMeter};
+use crate::l1::{L1ToL2Message, L2ToL1Message};
+use crate::utils::U256;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TransactionType {
     Declare,
     Invoke,
-    DeployAccount
+    DeployAccount,
+    L1Handler, //sent by the sequencer to deliver an L1→L2 message, never submitted to the mempool
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -27,21 +33,87 @@ pub struct Transaction {
     pub sender: String,
     pub receiver: Option<String>,  //to be used in invoke txs
     pub contract_address: Option<String>, //to be used in declare txs
-    pub amount: Option<u64>, //invoke txs -> token transfers
-    pub fee: u64, //gas fees
+    pub amount: Option<U256>, //invoke txs -> token transfers
+    pub entry_point: Option<String>, //selector of the contract entry point to call
+    pub calldata: Vec<String>, //felts passed to the entry point
+    pub signature: Vec<String>, //checked by the sender account's `__validate__`, not part of the hash
+    pub fee: U256, //max fee the sender agrees to pay, checked against its balance at validation (v1 `max_fee` or the v3 bounds total)
+    pub resource_bounds: Option<ResourceBoundsMapping>, //v3 txs: per-resource gas limits
+    pub tip: U256, //priority tip per unit of L2 gas, paid to the sequencer
+    pub paymaster: Option<String>, //contract that pays the fee instead of the sender
     pub nonce: u64,
     pub tx_type: TransactionType,
     pub status: TransactionStatus,
 }
 
+/// Outcome of executing a transaction, including the fee it actually cost
+#[derive(Debug, Clone)]
+pub struct TransactionReceipt {
+    pub transaction_hash: String,
+    pub tx_id: usize,
+    pub status: TransactionStatus,
+    pub resources: ExecutionResources,
+    pub gas_consumed: GasVector,
+    pub actual_fee: U256, //base fee + tip
+    pub tip: U256, //part of the actual fee paid to the sequencer
+    pub messages_sent: Vec<L2ToL1Message>, //L2→L1 messages, only kept when the tx succeeded
+    pub events: Vec<Event>, //events emitted, only kept when the tx succeeded
+    pub revert_reason: Option<String>, //why execution reverted, if it did
+    pub fee_payer: String, //the sender, or the paymaster that sponsored the tx
+    pub phases: TransactionPhases,
+}
+
+/// Outcome of one phase of a transaction
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum PhaseOutcome {
+    #[default]
+    NotRun,
+    Succeeded,
+    Reverted(String),
+}
+
+/// What each phase of a transaction did: the account's `__validate__`, the `__execute__`
+/// of its calls, and the transfer of the actual fee. A transaction that fails `__validate__`
+/// never reaches a block, and a reverted `__execute__` still pays its fee.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransactionPhases {
+    pub validate: PhaseOutcome,
+    pub execute: PhaseOutcome,
+    pub fee_transfer: PhaseOutcome,
+}
+
+impl TransactionPhases {
+    /// Phases of an account transaction that passed `__validate__` and paid its fee
+    pub fn charged(revert_reason: Option<&str>) -> Self {
+        TransactionPhases {
+            validate: PhaseOutcome::Succeeded,
+            execute: match revert_reason {
+                None => PhaseOutcome::Succeeded,
+                Some(reason) => PhaseOutcome::Reverted(reason.to_string()),
+            },
+            fee_transfer: PhaseOutcome::Succeeded,
+        }
+    }
+}
+
 static TX_COUNTER: AtomicUsize = AtomicUsize::new(1);
 
 impl Transaction {
     // Calculate hash of the transaction
     pub fn get_hash(&self) -> String {
         let input = format!(
-            "{}-{}-{:?}-{}-{:?}",
-            self.id, self.sender, self.tx_type, self.nonce, self.amount
+            "{}-{}-{:?}-{}-{:?}-{}-{:?}-{}-{:?}-{:?}-{:?}",
+            self.id,
+            self.sender,
+            self.tx_type,
+            self.nonce,
+            self.amount,
+            self.fee,
+            self.resource_bounds,
+            self.tip,
+            self.entry_point,
+            self.calldata,
+            self.paymaster
         );
         let mut hasher = Sha256::new();
         hasher.update(input);
@@ -49,16 +121,175 @@ impl Transaction {
         format!("{:x}", result)
     }
 
-    // Calculate fee based on tx type
-    pub fn calculate_fee(tx_type: &TransactionType) -> u64 {
+    /// Resources a successful transaction of this type consumes
+    pub fn estimate_resources(tx_type: &TransactionType) -> ExecutionResources {
+        let mut meter = ResourceMeter::new();
+        if *tx_type != TransactionType::L1Handler {
+            gas::meter_validate(&mut meter, "sender");
+        }
         match tx_type {
-            TransactionType::Invoke => 5,  // Fixed fee for invoking transactions
-            TransactionType::Declare => 20, // Declaring contracts costs more
-            TransactionType::DeployAccount => 10, // Deploying accounts costs more
+            TransactionType::Invoke => {
+                gas::meter_transfer_checks(&mut meter);
+                gas::meter_transfer_writes(&mut meter, "sender", "receiver");
+            }
+            TransactionType::Declare => gas::meter_declare(&mut meter),
+            TransactionType::DeployAccount => gas::meter_deploy_account(&mut meter, "sender"),
+            TransactionType::L1Handler => gas::meter_l1_handler(&mut meter, 0),
+        }
+        meter.finish()
+    }
+
+    // Calculate the expected fee from the resources the tx type consumes
+    pub fn calculate_fee(tx_type: &TransactionType) -> U256 {
+        Self::estimate_fee(tx_type, &GasPrices::default())
+    }
+
+    /// Expected fee of a successful transaction of this type at the given prices
+    pub fn estimate_fee(tx_type: &TransactionType, gas_prices: &GasPrices) -> U256 {
+        let gas = Self::estimate_resources(tx_type).to_gas();
+        gas_prices.fee_for(&gas).unwrap_or(U256::MAX)
+    }
+
+    /// Transaction version: 3 when resource bounds are set, 1 for a plain `max_fee`
+    pub fn version(&self) -> u8 {
+        if self.resource_bounds.is_some() { 3 } else { 1 }
+    }
+
+    /// Sets a v1 `max_fee`
+    pub fn with_max_fee(mut self, max_fee: U256) -> Self {
+        self.fee = max_fee;
+        self.resource_bounds = None;
+        self
+    }
+
+    /// Turns the transaction into a v3 transaction with per-resource bounds
+    pub fn with_resource_bounds(mut self, bounds: ResourceBoundsMapping) -> Self {
+        self.fee = bounds.max_fee().unwrap_or(U256::MAX);
+        self.resource_bounds = Some(bounds);
+        self
+    }
+
+    /// Makes an invoke transaction call an external entry point of a deployed contract
+    pub fn with_call(mut self, contract_address: &str, entry_point: &str, calldata: Vec<String>) -> Self {
+        self.contract_address = Some(contract_address.to_string());
+        self.entry_point = Some(entry_point.to_string());
+        self.calldata = calldata;
+        self
+    }
+
+    /// Signs the transaction hash with each of `signers`, as `[signer, signature, ...]`.
+    /// The hash covers every other field, so this comes last.
+    pub fn signed_by(mut self, signers: &[&str]) -> Self {
+        let transaction_hash = self.get_hash();
+        self.signature = signers
+            .iter()
+            .flat_map(|signer| [signer.to_string(), account::sign(signer, &transaction_hash)])
+            .collect();
+        self
+    }
+
+    /// Has the paymaster at `paymaster` pay the fee, if its `__validate_paymaster__` agrees
+    pub fn with_paymaster(mut self, paymaster: &str) -> Self {
+        self.paymaster = Some(paymaster.to_string());
+        self
+    }
+
+    /// Who pays the fee: the paymaster if there is one, otherwise the sender
+    pub fn fee_payer(&self) -> &str {
+        self.paymaster.as_deref().unwrap_or(&self.sender)
+    }
+
+    /// Sets the priority tip paid per unit of L2 gas
+    pub fn with_tip(mut self, tip: U256) -> Self {
+        self.tip = tip;
+        self
+    }
+
+    /// Checks the fee limits against the current gas prices before the tx is accepted
+    pub fn check_fee_bounds(&self, gas_prices: &GasPrices) -> Result<(), String> {
+        match &self.resource_bounds {
+            Some(bounds) => {
+                let estimate = Self::estimate_resources(&self.tx_type).to_gas();
+                check_resource_bound("L1 gas", &bounds.l1_gas, estimate.l1_gas, gas_prices.l1_gas_price)?;
+                check_resource_bound("L1 data gas", &bounds.l1_data_gas, estimate.l1_data_gas, gas_prices.l1_data_gas_price)?;
+                check_resource_bound("L2 gas", &bounds.l2_gas, estimate.l2_gas, gas_prices.l2_gas_price)
+            }
+            None => {
+                let estimate = Self::estimate_fee(&self.tx_type, gas_prices);
+                if self.fee < estimate {
+                    return Err(format!(
+                        "max fee {} is below the estimated fee {}",
+                        self.fee, estimate
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks that the gas consumed so far still fits in the transaction's limits
+    pub fn check_gas_limit(&self, gas: &GasVector, gas_prices: &GasPrices) -> Result<(), String> {
+        match &self.resource_bounds {
+            Some(bounds) => {
+                if gas.l1_gas > bounds.l1_gas.max_amount {
+                    return Err(format!(
+                        "out of L1 gas: used {}, max {}",
+                        gas.l1_gas, bounds.l1_gas.max_amount
+                    ));
+                }
+                if gas.l1_data_gas > bounds.l1_data_gas.max_amount {
+                    return Err(format!(
+                        "out of L1 data gas: used {}, max {}",
+                        gas.l1_data_gas, bounds.l1_data_gas.max_amount
+                    ));
+                }
+                if gas.l2_gas > bounds.l2_gas.max_amount {
+                    return Err(format!(
+                        "out of L2 gas: used {}, max {}",
+                        gas.l2_gas, bounds.l2_gas.max_amount
+                    ));
+                }
+                Ok(())
+            }
+            None => match gas_prices.fee_for(gas) {
+                Some(fee) if fee <= self.fee => Ok(()),
+                _ => Err(format!("actual fee exceeded max fee {}", self.fee)),
+            },
         }
     }
 
-    pub fn new(sender: String, tx_type: TransactionType, receiver: Option<String>, contract_address: Option<String>, amount: Option<u64>, nonce: u64) -> Self {
+    /// Fee actually charged for the consumed gas. Gas beyond a v3 bound is not
+    /// billed, the tip is limited by the v3 max L2 price, and the total never
+    /// exceeds the amount the sender agreed to. The base fee is charged first.
+    pub fn chargeable_fee(&self, gas: &GasVector, gas_prices: &GasPrices) -> FeeCharge {
+        let (billable, tip_per_gas) = match &self.resource_bounds {
+            Some(bounds) => {
+                let billable = GasVector {
+                    l1_gas: gas.l1_gas.min(bounds.l1_gas.max_amount),
+                    l1_data_gas: gas.l1_data_gas.min(bounds.l1_data_gas.max_amount),
+                    l2_gas: gas.l2_gas.min(bounds.l2_gas.max_amount),
+                };
+                let tip_room = bounds.l2_gas.max_price_per_unit.saturating_sub(gas_prices.l2_gas_price);
+                (billable, self.tip.min(tip_room))
+            }
+            None => (*gas, self.tip),
+        };
+
+        let base_fee = gas_prices.fee_for(&billable).unwrap_or(U256::MAX).min(self.fee);
+        let tip = U256::from(billable.l2_gas)
+            .checked_mul(tip_per_gas)
+            .unwrap_or(U256::MAX)
+            .min(self.fee.saturating_sub(base_fee));
+        FeeCharge { base_fee, tip }
+    }
+
+    /// Makes new transactions take ids above `id`, so they do not collide with
+    /// transactions restored from storage
+    pub fn reserve_ids_up_to(id: usize) {
+        TX_COUNTER.fetch_max(id + 1, Ordering::Relaxed);
+    }
+
+    pub fn new(sender: String, tx_type: TransactionType, receiver: Option<String>, contract_address: Option<String>, amount: Option<U256>, nonce: u64) -> Self {
         let id = TX_COUNTER.fetch_add(1, Ordering::Relaxed);
         let fee = Self::calculate_fee(&tx_type); // Automatically calculate fee
 
@@ -68,14 +299,65 @@ impl Transaction {
             receiver,
             contract_address,
             amount,
+            entry_point: None,
+            calldata: vec![],
+            signature: vec![],
             fee,
+            resource_bounds: None,
+            tip: U256::ZERO,
+            paymaster: None,
             tx_type,
             status: TransactionStatus::Received,
             nonce,
         }
     }
+    /// L1 handler transaction delivering a message from L1. Its nonce is the message nonce,
+    /// and its fee was already paid on L1 when the message was sent.
+    pub fn l1_handler(message: &L1ToL2Message) -> Self {
+        let id = TX_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let mut calldata = vec![message.from_address.clone()];
+        calldata.extend(message.payload.iter().cloned());
+
+        Transaction {
+            id,
+            sender: message.from_address.clone(),
+            receiver: None,
+            contract_address: Some(message.to_address.clone()),
+            amount: None,
+            entry_point: Some(message.selector.clone()),
+            calldata,
+            signature: vec![],
+            fee: message.fee,
+            resource_bounds: None,
+            tip: U256::ZERO,
+            paymaster: None,
+            tx_type: TransactionType::L1Handler,
+            status: TransactionStatus::Received,
+            nonce: message.nonce,
+        }
+    }
+
     //Update tx status
     pub fn update_status(&mut self, new_status: TransactionStatus) {
         self.status = new_status;
     }
-}
\ No newline at end of file
+}
+/// Checks one v3 resource bound: the max price must cover the current price and
+/// the bound as a whole must cover the estimated fee for that resource
+fn check_resource_bound(resource: &str, bound: &ResourceBounds, estimated_amount: u64, price: U256) -> Result<(), String> {
+    if bound.max_price_per_unit < price {
+        return Err(format!(
+            "max {} price {} is below the current price {}",
+            resource, bound.max_price_per_unit, price
+        ));
+    }
+    let max_fee = U256::from(bound.max_amount).checked_mul(bound.max_price_per_unit).unwrap_or(U256::MAX);
+    let estimate = U256::from(estimated_amount).checked_mul(price).unwrap_or(U256::MAX);
+    if max_fee < estimate {
+        return Err(format!(
+            "max {} fee {} is below the estimated fee {}",
+            resource, max_fee, estimate
+        ));
+    }
+    Ok(())
+}
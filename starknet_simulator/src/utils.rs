@@ -0,0 +1,178 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// Unsigned 256-bit integer used for token amounts, fees and balances.
+///
+/// Stored as two 128-bit halves, matching the `Uint256 { low, high }`
+/// encoding that ERC-20 contracts use on Starknet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct U256 {
+    pub low: u128,
+    pub high: u128,
+}
+
+impl U256 {
+    pub const ZERO: U256 = U256 { low: 0, high: 0 };
+    pub const ONE: U256 = U256 { low: 1, high: 0 };
+    pub const MAX: U256 = U256 { low: u128::MAX, high: u128::MAX };
+
+    /// Builds a value from its ERC-20 `low`/`high` felts
+    pub const fn from_low_high(low: u128, high: u128) -> Self {
+        U256 { low, high }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.low == 0 && self.high == 0
+    }
+
+    /// Returns the value as `u128` if it fits in the low half
+    pub fn to_u128(&self) -> Option<u128> {
+        if self.high == 0 {
+            Some(self.low)
+        } else {
+            None
+        }
+    }
+
+    pub fn checked_add(self, rhs: U256) -> Option<U256> {
+        let (low, carry) = self.low.overflowing_add(rhs.low);
+        let high = self.high.checked_add(rhs.high)?.checked_add(carry as u128)?;
+        Some(U256 { low, high })
+    }
+
+    pub fn checked_sub(self, rhs: U256) -> Option<U256> {
+        let (low, borrow) = self.low.overflowing_sub(rhs.low);
+        let high = self.high.checked_sub(rhs.high)?.checked_sub(borrow as u128)?;
+        Some(U256 { low, high })
+    }
+
+    pub fn saturating_sub(self, rhs: U256) -> U256 {
+        self.checked_sub(rhs).unwrap_or(U256::ZERO)
+    }
+
+    pub fn checked_mul(self, rhs: U256) -> Option<U256> {
+        let a = self.limbs();
+        let b = rhs.limbs();
+        let mut result = [0u64; 8];
+
+        // Schoolbook multiplication over 64-bit limbs
+        for i in 0..4 {
+            let mut carry: u128 = 0;
+            for j in 0..4 {
+                let cur = result[i + j] as u128 + (a[i] as u128) * (b[j] as u128) + carry;
+                result[i + j] = cur as u64;
+                carry = cur >> 64;
+            }
+            result[i + 4] = carry as u64;
+        }
+
+        if result[4..].iter().any(|&limb| limb != 0) {
+            return None;
+        }
+        Some(Self::from_limbs([result[0], result[1], result[2], result[3]]))
+    }
+
+    /// Divides by a small divisor, returning the quotient and remainder
+    pub fn div_rem_u64(self, divisor: u64) -> (U256, u64) {
+        assert!(divisor != 0, "division by zero");
+        let mut limbs = self.limbs();
+        let mut rem: u128 = 0;
+        for limb in limbs.iter_mut().rev() {
+            let cur = (rem << 64) | *limb as u128;
+            *limb = (cur / divisor as u128) as u64;
+            rem = cur % divisor as u128;
+        }
+        (Self::from_limbs(limbs), rem as u64)
+    }
+
+    /// Little-endian 64-bit limbs
+    fn limbs(&self) -> [u64; 4] {
+        [
+            self.low as u64,
+            (self.low >> 64) as u64,
+            self.high as u64,
+            (self.high >> 64) as u64,
+        ]
+    }
+
+    fn from_limbs(limbs: [u64; 4]) -> Self {
+        U256 {
+            low: limbs[0] as u128 | (limbs[1] as u128) << 64,
+            high: limbs[2] as u128 | (limbs[3] as u128) << 64,
+        }
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.high.cmp(&other.high).then(self.low.cmp(&other.low))
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> Self {
+        U256 { low: value as u128, high: 0 }
+    }
+}
+
+impl From<u128> for U256 {
+    fn from(value: u128) -> Self {
+        U256 { low: value, high: 0 }
+    }
+}
+
+impl fmt::Display for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.high == 0 {
+            return fmt::Display::fmt(&self.low, f);
+        }
+
+        // Peel off 19 decimal digits at a time (largest power of ten in a u64)
+        const CHUNK: u64 = 10_000_000_000_000_000_000;
+        let mut chunks = vec![];
+        let mut value = *self;
+        while !value.is_zero() {
+            let (quotient, rem) = value.div_rem_u64(CHUNK);
+            chunks.push(rem);
+            value = quotient;
+        }
+
+        let mut out = chunks.pop().unwrap().to_string();
+        for chunk in chunks.iter().rev() {
+            out.push_str(&format!("{:019}", chunk));
+        }
+        f.pad(&out)
+    }
+}
+
+impl FromStr for U256 {
+    type Err = String;
+
+    /// Parses a decimal string, rejecting values above `U256::MAX`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("empty amount".to_string());
+        }
+
+        let ten = U256::from(10u64);
+        let mut value = U256::ZERO;
+        for c in s.chars() {
+            let digit = c
+                .to_digit(10)
+                .ok_or_else(|| format!("invalid digit '{}' in amount", c))?;
+            value = value
+                .checked_mul(ten)
+                .and_then(|v| v.checked_add(U256::from(digit as u64)))
+                .ok_or_else(|| "amount does not fit in u256".to_string())?;
+        }
+        Ok(value)
+    }
+}
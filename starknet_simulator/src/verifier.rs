@@ -1,42 +1,112 @@
 use std::sync::{Arc, Mutex};
 use crate::sequencer::Sequencer;
-use crate::block::{L2Block, BlockStatus};
+use crate::block::{BlockStatus, L2Block};
+use crate::da::{self, DaCost};
+use crate::prover::BlockProof;
+use crate::stark::{self, PublicInput};
+use crate::state::StateDiff;
+use crate::l1::StarknetCoreContract;
 use std::thread;
 use std::time::Duration;
-use rand::random;
 
 /// The Verifier is responsible for verifying cryptographic proofs and finalizing blocks on L1.
 pub struct Verifier {
-    pub sequencer: Arc<Mutex<Sequencer>>, // Reference to the sequencer
+    pub blocks: Arc<Mutex<Vec<L2Block>>>, // the sequencer's chain; the sequencer itself is never locked
+    pub core_contract: Arc<Mutex<StarknetCoreContract>>, // Starknet core contract on the simulated L1
+    pub verification_delay: Duration, // simulated L1 confirmation time per block
+    pub da_costs: Mutex<Vec<(u64, DaCost)>>, // what publishing each settled block's state diff cost
 }
 
 impl Verifier {
     /// Creates a new Verifier instance
-    pub fn new(sequencer: Arc<Mutex<Sequencer>>) -> Self {
-        Verifier { sequencer }
+    pub fn new(sequencer: Arc<Mutex<Sequencer>>, core_contract: Arc<Mutex<StarknetCoreContract>>) -> Self {
+        let blocks = sequencer.lock().unwrap().blocks.clone();
+        Verifier {
+            blocks,
+            core_contract,
+            verification_delay: Duration::from_secs(2),
+            da_costs: Mutex::new(vec![]),
+        }
     }
 
-    /// Simulates proof verification for all blocks that have `AcceptedOnL1` status
+    /// Submits proven blocks, in order, to the core contract with `updateState(proof, state_diff)`,
+    /// moving them from `Proven` to `AcceptedOnL1` through `SubmittedToL1`.
+    /// A block is final once L1 has accepted the update that chains from the previous state root.
+    /// Every proof is first checked against the block data it claims to cover; a proof that does
+    /// not match, or that L1 refuses, leaves its blocks `Rejected` until they are re-proven.
+    /// An aggregated proof settles its whole range of blocks with a single update carrying
+    /// their squashed state diff.
+    ///
+    /// Each call is one L1 block: once its DA capacity (calldata bytes or blobs) is used up,
+    /// the remaining blocks wait for the next call. A single update too large for one L1
+    /// block is still published, spread over several.
     pub fn verify_proofs(&self) {
-        let sequencer = self.sequencer.lock().unwrap();
-        let mut blocks = sequencer.blocks.lock().unwrap();
-
         let mut verified_any = false;
+        let da_mode = self.core_contract.lock().unwrap().da_mode;
+        let mut da_capacity_left = da_mode.units_per_l1_block();
 
-        for block in blocks.iter_mut().filter(|b| b.header.block_status == BlockStatus::AcceptedOnL1) {
-            println!("[Verifier] 🔍 Verifying proof on Ethereum for Block #{}...", block.header.block_number);
-            thread::sleep(Duration::from_secs(2)); // Simulating verification delay
+        while let Some((range, proof)) = self.next_submission() {
+            let (first_block_number, last_block_number) = (proof.first_block_number, proof.block_number);
+
+            let state_diff = StateDiff::squash(range.iter().map(|b| &b.state_diff));
+            let da_bytes = da::encode_state_diff(&state_diff).len() * da::BYTES_PER_FELT;
+            let da_cost = da_mode.publication_cost(da_bytes, &range[range.len() - 1].gas_prices());
+            if let Some(capacity_left) = da_capacity_left {
+                if verified_any && da_cost.units > capacity_left {
+                    println!(
+                        "[Verifier] 📦 {} DA capacity of this L1 block is used up. Block #{} waits for the next one.",
+                        da_mode, first_block_number
+                    );
+                    break;
+                }
+                da_capacity_left = Some(capacity_left.saturating_sub(da_cost.units));
+            }
 
-            // Simulate a verification process with a 95% success rate
-            let verification_success = random::<f32>() > 0.05;
+            println!(
+                "[Verifier] 🔍 Verifying proof on Ethereum for Blocks #{}..#{}...",
+                first_block_number, last_block_number
+            );
+            // Nothing is sent to L1 unless the proof matches the blocks
+            if let Err(reason) = Self::check_proof(&range, &proof, &state_diff) {
+                self.reject(first_block_number, last_block_number, &reason);
+                break;
+            }
+            self.advance(first_block_number, last_block_number, BlockStatus::SubmittedToL1);
+            thread::sleep(self.verification_delay); // Simulating L1 confirmation time, with no lock held
 
-            if verification_success {
-                block.header.block_status = BlockStatus::AcceptedOnL1;
-                println!("[Verifier] ✅ Proof for Block #{} is valid!", block.header.block_number);
-                println!("🌍 Block #{} is now **Finalized on Ethereum L1** ✅\n", block.header.block_number);
-                verified_any = true;
-            } else {
-                println!("[Verifier] ❌ Proof for Block #{} **failed verification**! Retrying required.", block.header.block_number);
+            let mut core_contract = self.core_contract.lock().unwrap();
+            match core_contract.update_state(&proof, &state_diff) {
+                Ok(()) => {
+                    self.advance(first_block_number, last_block_number, BlockStatus::AcceptedOnL1);
+                    println!(
+                        "[Verifier] ✅ Proof for Blocks #{}..#{} is valid! One L1 update settles {} block(s).",
+                        first_block_number,
+                        last_block_number,
+                        proof.block_count()
+                    );
+                    println!(
+                        "🧮 STARK checked: {} trace rows, {} FRI layers, {} queries",
+                        proof.stark.trace_length,
+                        proof.stark.fri_roots.len(),
+                        proof.stark.queries.len()
+                    );
+                    println!(
+                        "📦 State diff published via {}: {} bytes, {} unit(s), cost {}",
+                        da_mode, da_cost.bytes, da_cost.units, da_cost.cost
+                    );
+                    self.da_costs.lock().unwrap().push((last_block_number, da_cost));
+                    println!("🌳 L1 state root is now {}", core_contract.state_root);
+                    println!(
+                        "🌍 Blocks #{}..#{} are now **Finalized on Ethereum L1** ✅\n",
+                        first_block_number, last_block_number
+                    );
+                    verified_any = true;
+                }
+                Err(err) => {
+                    self.reject(first_block_number, last_block_number, &err.to_string());
+                    // Later blocks cannot chain from a state root L1 never accepted
+                    break;
+                }
             }
         }
 
@@ -44,4 +114,87 @@ impl Verifier {
             println!("[Verifier] ⚠️ No new proofs available for verification.");
         }
     }
-}
\ No newline at end of file
+
+    /// Whether the blocks right after the last one L1 accepted are proven and can be sent
+    pub fn has_submission_ready(&self) -> bool {
+        self.next_submission().is_some()
+    }
+
+    /// Copies out the next range of proven blocks, the one starting right after the last block L1 accepted
+    fn next_submission(&self) -> Option<(Vec<L2Block>, BlockProof)> {
+        let next_block_number = self.core_contract.lock().unwrap().block_number + 1;
+        let blocks = self.blocks.lock().unwrap();
+        let start = blocks.iter().position(|b| b.header.block_number == next_block_number)?;
+        if blocks[start].header.block_status != BlockStatus::Proven {
+            return None;
+        }
+        let proof = blocks[start].proof.clone()?;
+        let end = start + (proof.block_count() as usize).saturating_sub(1);
+        if end >= blocks.len() || blocks[start..=end].iter().any(|b| b.header.block_status != BlockStatus::Proven) {
+            return None;
+        }
+        Some((blocks[start..=end].to_vec(), proof))
+    }
+
+    /// Moves every block of a range to its next status
+    fn advance(&self, first_block_number: u64, last_block_number: u64, status: BlockStatus) {
+        let mut blocks = self.blocks.lock().unwrap();
+        for block in blocks
+            .iter_mut()
+            .filter(|b| (first_block_number..=last_block_number).contains(&b.header.block_number))
+        {
+            block
+                .transition(status.clone())
+                .expect("only the verifier moves blocks on from Proven");
+        }
+    }
+
+    fn reject(&self, first_block_number: u64, last_block_number: u64, reason: &str) {
+        println!(
+            "[Verifier] ❌ Proof for Blocks #{}..#{} rejected: {}. Re-proving required.",
+            first_block_number, last_block_number, reason
+        );
+        self.advance(first_block_number, last_block_number, BlockStatus::Rejected { reason: reason.to_string() });
+    }
+
+    /// Checks a proof against the blocks it claims to cover: their bodies must match their
+    /// headers, their hashes and state roots must be the proven ones, and the STARK over
+    /// their state diff must verify
+    pub fn check_proof(blocks: &[L2Block], proof: &BlockProof, state_diff: &StateDiff) -> Result<(), String> {
+        if !proof.is_valid() {
+            return Err("proof does not match its public outputs".to_string());
+        }
+        if proof.block_hashes.len() != blocks.len() {
+            return Err(format!("proof covers {} block(s), not {}", proof.block_hashes.len(), blocks.len()));
+        }
+        for (block, proven_hash) in blocks.iter().zip(&proof.block_hashes) {
+            block.verify_integrity()?;
+            if block.get_block_hash() != *proven_hash {
+                return Err(format!("block #{} changed after it was proven", block.header.block_number));
+            }
+        }
+        for pair in blocks.windows(2) {
+            if pair[1].header.parent_block_hash != pair[0].get_block_hash()
+                || pair[1].header.parent_state_root != pair[0].header.state_root
+            {
+                return Err(format!("block #{} does not chain from its parent", pair[1].header.block_number));
+            }
+        }
+
+        let (first, last) = (&blocks[0], &blocks[blocks.len() - 1]);
+        if first.header.parent_state_root != proof.prev_state_root || last.header.state_root != proof.new_state_root {
+            return Err("state roots of the blocks do not match the proof".to_string());
+        }
+        if state_diff.hash() != proof.state_diff_hash {
+            return Err("state diff of the blocks does not match the proof".to_string());
+        }
+        let l1_to_l2_messages: Vec<String> = blocks.iter().flat_map(|b| b.l1_to_l2_messages.iter().cloned()).collect();
+        let l2_to_l1_messages: Vec<String> = blocks.iter().flat_map(|b| b.l2_to_l1_messages()).map(|m| m.hash()).collect();
+        if l1_to_l2_messages != proof.l1_to_l2_messages || l2_to_l1_messages != proof.l2_to_l1_messages {
+            return Err("messages of the blocks do not match the proof".to_string());
+        }
+
+        let public_input = PublicInput::for_state_transition(&proof.prev_state_root, state_diff);
+        stark::verify(&public_input, &proof.stark).map_err(|err| format!("STARK does not verify: {}", err))
+    }
+}
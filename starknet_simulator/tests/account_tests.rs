@@ -0,0 +1,410 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use starknet_simulator::account::{MultisigAccount, OnboardingPaymaster, SessionKeyAccount, SpendingLimitAccount, TimeLockAccount};
+    use starknet_simulator::contract::{parse_felt, Contract, EntryPointType, ExecutionContext};
+    use starknet_simulator::mempool::Mempool;
+    use starknet_simulator::sequencer::{ExecutionMode, Sequencer};
+    use starknet_simulator::transaction::{PhaseOutcome, Transaction, TransactionStatus, TransactionType};
+    use starknet_simulator::utils::U256;
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    fn mempool_with_account(account: Arc<dyn starknet_simulator::contract::Contract>) -> Arc<Mempool> {
+        let mempool = Mempool::new();
+        mempool.deploy_account("vault", account).unwrap();
+        mempool.balances.lock().unwrap().insert("vault".to_string(), U256::from(1_000u64));
+        Arc::new(mempool)
+    }
+
+    fn transfer(amount: u64, nonce: u64) -> Transaction {
+        Transaction::new("vault".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(amount)), nonce)
+            .with_max_fee(U256::from(20u64))
+    }
+
+    fn multisig() -> Arc<MultisigAccount> {
+        Arc::new(MultisigAccount { owners: vec!["ann".to_string(), "ben".to_string(), "cat".to_string()], threshold: 2 })
+    }
+
+    #[test]
+    fn test_multisig_needs_enough_owner_signatures() {
+        let mempool = mempool_with_account(multisig());
+
+        // One owner plus a stranger is not enough, and the nonce stays unused
+        mempool.submit_transaction(transfer(10, 0).signed_by(&["ann", "eve"]));
+        assert!(mempool.validate_transaction(now()).is_none());
+        assert_eq!(mempool.nonces.lock().unwrap().get("vault"), None);
+
+        // The same transaction can be resent with a second owner's signature
+        mempool.submit_transaction(transfer(10, 0).signed_by(&["ann", "cat"]));
+        assert!(mempool.validate_transaction(now()).is_some());
+    }
+
+    #[test]
+    fn test_signed_transactions_keep_their_nonces_in_one_block() {
+        let mempool = mempool_with_account(multisig());
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+
+        let txs = [transfer(10, 0).signed_by(&["ann", "ben"]), transfer(20, 1).signed_by(&["ben", "cat"])];
+        let hashes: Vec<String> = txs.iter().map(Transaction::get_hash).collect();
+        for tx in txs {
+            mempool.submit_transaction(tx);
+        }
+        sequencer.process_transactions();
+
+        for hash in &hashes {
+            assert_eq!(sequencer.get_receipt(hash).unwrap().status, TransactionStatus::Succeeded);
+        }
+        assert_eq!(mempool.nonces.lock().unwrap()["vault"], 2);
+    }
+
+    #[test]
+    fn test_forged_signature_is_rejected() {
+        let mempool = mempool_with_account(multisig());
+        let mut tx = transfer(10, 0).signed_by(&["ann", "ben"]);
+        tx.signature[3] = "forged".to_string();
+
+        mempool.submit_transaction(tx);
+        assert!(mempool.validate_transaction(now()).is_none());
+    }
+
+    #[test]
+    fn test_session_key_is_limited_to_its_contract_and_lifetime() {
+        let session = |expires_at| {
+            mempool_with_account(Arc::new(SessionKeyAccount {
+                owner: "ann".to_string(),
+                session_key: "game_key".to_string(),
+                allowed_contract: "game".to_string(),
+                expires_at,
+            }))
+        };
+        let play = || {
+            Transaction::new("vault".to_string(), TransactionType::Invoke, None, None, None, 0)
+                .with_call("game", "play", vec![])
+                .with_max_fee(U256::from(20u64))
+        };
+
+        let mempool = session(now() + 3600);
+        mempool.submit_transaction(transfer(10, 0).signed_by(&["game_key"]));
+        assert!(mempool.validate_transaction(now()).is_none());
+        mempool.submit_transaction(play().signed_by(&["game_key"]));
+        assert!(mempool.validate_transaction(now()).is_some());
+        // The owner can do anything. Nothing was executed, so nonce 0 is still free
+        mempool.submit_transaction(transfer(10, 0).signed_by(&["ann"]));
+        assert!(mempool.validate_transaction(now()).is_some());
+
+        let expired = session(now() - 1);
+        expired.submit_transaction(play().signed_by(&["game_key"]));
+        assert!(expired.validate_transaction(now()).is_none());
+    }
+
+    #[test]
+    fn test_time_lock_refuses_transactions_until_it_opens() {
+        let unlock_at = 1_700_000_000;
+        let mempool = mempool_with_account(Arc::new(TimeLockAccount { owner: "ann".to_string(), unlock_at }));
+        mempool.submit_transaction(transfer(10, 0).signed_by(&["ann"]));
+        assert!(mempool.validate_transaction(unlock_at - 1).is_none());
+
+        mempool.submit_transaction(transfer(10, 0).signed_by(&["ann"]));
+        assert!(mempool.validate_transaction(unlock_at).is_some());
+    }
+
+    /// Records the block timestamp every `__validate__` run sees
+    #[derive(Default)]
+    struct ClockAccount {
+        seen: Mutex<Vec<u64>>,
+    }
+
+    impl Contract for ClockAccount {
+        fn class_hash(&self) -> String {
+            "clock_account".to_string()
+        }
+
+        fn entry_point_type(&self, selector: &str) -> Option<EntryPointType> {
+            (selector == "__validate__").then_some(EntryPointType::External)
+        }
+
+        fn execute(&self, _selector: &str, _calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+            self.seen.lock().unwrap().push(ctx.tx_info.block_timestamp);
+            Ok(vec![])
+        }
+    }
+
+    #[test]
+    fn test_dry_run_and_sequencer_validate_see_the_block_timestamp() {
+        let account = Arc::new(ClockAccount::default());
+        let mempool = mempool_with_account(account.clone());
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+
+        mempool.submit_transaction(transfer(10, 0));
+        sequencer.process_transactions();
+
+        let block_timestamp = sequencer.blocks.lock().unwrap()[0].header.block_timestamp;
+        assert_eq!(*account.seen.lock().unwrap(), vec![block_timestamp, block_timestamp]);
+    }
+
+    #[test]
+    fn test_spending_limit_is_tracked_in_account_storage() {
+        let mempool = mempool_with_account(Arc::new(SpendingLimitAccount { owner: "ann".to_string(), limit: U256::from(100u64) }));
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+
+        let first = transfer(60, 0).signed_by(&["ann"]);
+        let first_hash = first.get_hash();
+        mempool.submit_transaction(first);
+        sequencer.process_transactions();
+        let receipt = sequencer.get_receipt(&first_hash).unwrap();
+        assert_eq!(receipt.status, TransactionStatus::Succeeded);
+        assert_eq!(receipt.phases.validate, PhaseOutcome::Succeeded);
+        assert_eq!(mempool.storage.lock().unwrap()["vault"]["spent"], U256::from(60u64));
+        assert_eq!(mempool.balances.lock().unwrap()["Bob"], U256::from(560u64));
+
+        // 60 more would pass the limit
+        mempool.submit_transaction(transfer(60, 1).signed_by(&["ann"]));
+        assert!(mempool.validate_transaction(now()).is_none());
+        mempool.submit_transaction(transfer(40, 1).signed_by(&["ann"]));
+        assert!(mempool.validate_transaction(now()).is_some());
+    }
+
+    /// A token with its own `transfer(recipient, amount)`, unrelated to the fee token
+    struct Points;
+
+    impl Contract for Points {
+        fn class_hash(&self) -> String {
+            "points_class".to_string()
+        }
+
+        fn entry_point_type(&self, selector: &str) -> Option<EntryPointType> {
+            (selector == "transfer").then_some(EntryPointType::External)
+        }
+
+        fn execute(&self, _selector: &str, calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+            let amount = parse_felt(calldata, 1)?;
+            let total = ctx.storage_read(&calldata[0]).checked_add(amount).ok_or("points overflow")?;
+            ctx.storage_write(&calldata[0], total);
+            Ok(vec![])
+        }
+    }
+
+    #[test]
+    fn test_spending_limit_ignores_transfers_of_other_contracts() {
+        let mempool = mempool_with_account(Arc::new(SpendingLimitAccount { owner: "ann".to_string(), limit: U256::from(100u64) }));
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+        sequencer.deploy_contract("points", Arc::new(Points));
+
+        // 500 points are not fee tokens, and neither the recipient nor the amount is read as one
+        let tx = Transaction::new("vault".to_string(), TransactionType::Invoke, None, None, None, 0)
+            .with_call("points", "transfer", vec!["Bob".to_string(), "500".to_string()])
+            .with_max_fee(U256::from(50u64))
+            .signed_by(&["ann"]);
+        let tx_hash = tx.get_hash();
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+        assert_eq!(sequencer.get_receipt(&tx_hash).unwrap().status, TransactionStatus::Succeeded);
+        let storage = mempool.storage.lock().unwrap();
+        assert_eq!(storage["points"]["Bob"], U256::from(500u64));
+        assert!(storage.get("vault").is_none_or(|slots| !slots.contains_key("spent")));
+        drop(storage);
+
+        // Fee-token transfers still count against the limit
+        mempool.submit_transaction(transfer(60, 1).signed_by(&["ann"]));
+        sequencer.process_transactions();
+        assert_eq!(mempool.storage.lock().unwrap()["vault"]["spent"], U256::from(60u64));
+    }
+
+    #[test]
+    fn test_validation_is_bounded_by_the_step_budget() {
+        let owners: Vec<String> = (0..200).map(|i| format!("owner{}", i)).collect();
+        let signers: Vec<&str> = owners.iter().map(String::as_str).collect();
+        let account = Arc::new(MultisigAccount { owners: owners.clone(), threshold: owners.len() });
+
+        let mempool = mempool_with_account(account.clone());
+        mempool.submit_transaction(transfer(10, 0).signed_by(&signers));
+        assert!(mempool.validate_transaction(now()).is_none());
+
+        let mut generous = Mempool::new();
+        generous.validation_step_limit = 100_000;
+        generous.deploy_account("vault", account).unwrap();
+        generous.balances.lock().unwrap().insert("vault".to_string(), U256::from(1_000u64));
+        generous.submit_transaction(transfer(10, 0).signed_by(&signers));
+        assert!(generous.validate_transaction(now()).is_some());
+    }
+
+    #[test]
+    fn test_account_transactions_run_sequentially_in_parallel_mode() {
+        let mempool = mempool_with_account(multisig());
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+        sequencer.execution_mode = ExecutionMode::Parallel { workers: 4 };
+
+        let tx = transfer(10, 0).signed_by(&["ben", "cat"]);
+        let tx_hash = tx.get_hash();
+        mempool.submit_transaction(Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::ONE), 0));
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        assert_eq!(sequencer.get_receipt(&tx_hash).unwrap().status, TransactionStatus::Succeeded);
+        assert_eq!(mempool.balances.lock().unwrap()["Bob"], U256::from(511u64));
+        assert_eq!(sequencer.blocks.lock().unwrap()[0].transactions.len(), 2);
+    }
+
+    /// `__validate__` burns `burn` of the account's own tokens, and if `refuse_after_first`
+    /// is set, accepts only the first transaction it sees
+    struct BurningAccount {
+        burn: U256,
+        refuse_after_first: bool,
+        seen: AtomicBool,
+    }
+
+    impl Contract for BurningAccount {
+        fn class_hash(&self) -> String {
+            "burning_account".to_string()
+        }
+
+        fn entry_point_type(&self, selector: &str) -> Option<EntryPointType> {
+            (selector == "__validate__").then_some(EntryPointType::External)
+        }
+
+        fn execute(&self, _selector: &str, _calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+            if self.seen.swap(true, Ordering::SeqCst) && self.refuse_after_first {
+                return Err("already used".to_string());
+            }
+            let account = ctx.contract_address.clone();
+            ctx.burn(&account, self.burn)?;
+            Ok(vec![])
+        }
+    }
+
+    #[test]
+    fn test_fee_is_capped_at_what_validation_left() {
+        let account = BurningAccount { burn: U256::from(999u64), refuse_after_first: false, seen: AtomicBool::new(false) };
+        let mempool = mempool_with_account(Arc::new(account));
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+
+        // __validate__ leaves 1 token: the transfer reverts, and the fee takes what is left
+        let tx = transfer(10, 0);
+        let tx_hash = tx.get_hash();
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let receipt = sequencer.get_receipt(&tx_hash).unwrap();
+        assert_eq!(receipt.status, TransactionStatus::Reverted);
+        assert_eq!(receipt.actual_fee, U256::ONE);
+        assert!(matches!(receipt.phases.fee_transfer, PhaseOutcome::Reverted(_)));
+        assert_eq!(mempool.balances.lock().unwrap()["vault"], U256::ZERO);
+        assert_eq!(mempool.nonces.lock().unwrap()["vault"], 1);
+    }
+
+    #[test]
+    fn test_nonce_is_kept_when_the_sequencer_validation_fails() {
+        // The mempool's dry run passes, the sequencer's own __validate__ does not
+        let account = BurningAccount { burn: U256::ZERO, refuse_after_first: true, seen: AtomicBool::new(false) };
+        let mempool = mempool_with_account(Arc::new(account));
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+
+        mempool.submit_transaction(transfer(10, 0));
+        sequencer.process_transactions();
+
+        assert!(sequencer.blocks.lock().unwrap().is_empty());
+        assert_eq!(mempool.nonces.lock().unwrap().get("vault"), None);
+        assert_eq!(mempool.balances.lock().unwrap()["vault"], U256::from(1_000u64));
+    }
+
+    fn sponsored_sequencer(sponsor_balance: u64) -> (Sequencer, Arc<Mempool>) {
+        let mempool = Mempool::new();
+        let paymaster = OnboardingPaymaster { max_fee: U256::from(50u64), max_transactions_per_account: 2 };
+        mempool.deploy_paymaster("sponsor", Arc::new(paymaster)).unwrap();
+        mempool.balances.lock().unwrap().insert("sponsor".to_string(), U256::from(sponsor_balance));
+        let mempool = Arc::new(mempool);
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+        (sequencer, mempool)
+    }
+
+    fn sponsored_transfer(sender: &str, amount: u64, nonce: u64) -> Transaction {
+        Transaction::new(sender.to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(amount)), nonce)
+            .with_max_fee(U256::from(20u64))
+            .with_paymaster("sponsor")
+    }
+
+    #[test]
+    fn test_paymaster_pays_for_an_account_without_funds() {
+        let (mut sequencer, mempool) = sponsored_sequencer(1_000);
+
+        // Mark has nothing, so he cannot pay for his own deployment
+        let unsponsored = Transaction::new("Mark".to_string(), TransactionType::DeployAccount, None, None, None, 0);
+        mempool.submit_transaction(unsponsored.clone());
+        assert!(mempool.validate_transaction(now()).is_none());
+
+        // The paymaster's own validation is paid for too, so the default max fee is not enough
+        let tx = unsponsored.with_max_fee(U256::from(40u64)).with_paymaster("sponsor");
+        let tx_hash = tx.get_hash();
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let receipt = sequencer.get_receipt(&tx_hash).unwrap();
+        assert_eq!(receipt.status, TransactionStatus::Succeeded);
+        assert_eq!(receipt.fee_payer, "sponsor");
+        let balances = mempool.balances.lock().unwrap();
+        assert_eq!(balances["Mark"], U256::ZERO);
+        assert_eq!(balances["sponsor"], U256::from(1_000u64).checked_sub(receipt.actual_fee).unwrap());
+        assert!(mempool.contract_classes.lock().unwrap().contains_key("Mark"));
+    }
+
+    #[test]
+    fn test_sponsorship_limits_are_enforced_by_the_paymaster() {
+        let (mut sequencer, mempool) = sponsored_sequencer(1_000);
+        for nonce in 0..2 {
+            mempool.submit_transaction(sponsored_transfer("Alice", 5, nonce));
+            sequencer.process_transactions();
+        }
+        assert_eq!(mempool.storage.lock().unwrap()["sponsor"]["Alice"], U256::from(2u64));
+        assert_eq!(mempool.balances.lock().unwrap()["Alice"], U256::from(190u64));
+
+        // A third sponsored transaction is refused, but Alice can still pay for it herself
+        mempool.submit_transaction(sponsored_transfer("Alice", 5, 2));
+        assert!(mempool.validate_transaction(now()).is_none());
+        // A fee above what the paymaster sponsors is refused too
+        mempool.submit_transaction(sponsored_transfer("Bob", 5, 0).with_max_fee(U256::from(60u64)));
+        assert!(mempool.validate_transaction(now()).is_none());
+    }
+
+    #[test]
+    fn test_mempool_checks_the_paymaster_balance() {
+        let (_, mempool) = sponsored_sequencer(10);
+        mempool.submit_transaction(sponsored_transfer("Cyndie", 5, 0));
+        assert!(mempool.validate_transaction(now()).is_none());
+
+        let unknown = sponsored_transfer("Cyndie", 5, 0).with_paymaster("nobody");
+        mempool.submit_transaction(unknown);
+        assert!(mempool.validate_transaction(now()).is_none());
+        assert_eq!(mempool.nonces.lock().unwrap()["Cyndie"], 0);
+    }
+
+    #[test]
+    fn test_paymaster_pays_for_a_reverted_transaction() {
+        let (mut sequencer, mempool) = sponsored_sequencer(1_000);
+        let tx = sponsored_transfer("Alice", 500, 0);
+        let tx_hash = tx.get_hash();
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let receipt = sequencer.get_receipt(&tx_hash).unwrap();
+        assert_eq!(receipt.status, TransactionStatus::Reverted);
+        assert_eq!(receipt.fee_payer, "sponsor");
+        let balances = mempool.balances.lock().unwrap();
+        assert_eq!(balances["Alice"], U256::from(200u64));
+        assert_eq!(balances["sponsor"], U256::from(1_000u64).checked_sub(receipt.actual_fee).unwrap());
+        // What __validate_paymaster__ wrote survives the revert
+        assert_eq!(mempool.storage.lock().unwrap()["sponsor"]["Alice"], U256::ONE);
+    }
+}
@@ -0,0 +1,139 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use starknet_simulator::block_stm;
+    use starknet_simulator::execution::BaseState;
+    use starknet_simulator::gas::GasPrices;
+    use starknet_simulator::mempool::Mempool;
+    use starknet_simulator::sequencer::{ExecutionMode, Sequencer};
+    use starknet_simulator::transaction::{Transaction, TransactionType};
+    use starknet_simulator::utils::U256;
+
+    fn transfer(sender: &str, receiver: &str, amount: u64, nonce: u64) -> Transaction {
+        Transaction::new(sender.to_string(), TransactionType::Invoke, Some(receiver.to_string()), None, Some(U256::from(amount)), nonce)
+            .with_max_fee(U256::from(20u64))
+    }
+
+    fn accounts(count: usize) -> HashMap<String, U256> {
+        (0..count).map(|i| (format!("user{}", i), U256::from(1_000u64))).collect()
+    }
+
+    /// Transfers between `account_count` accounts, with nonces kept in order per sender
+    /// and a few wrong nonces and oversized amounts mixed in
+    fn random_transfers(rng: &mut StdRng, count: usize, account_count: usize) -> Vec<Transaction> {
+        let mut next_nonce = HashMap::new();
+        (0..count)
+            .map(|_| {
+                let sender = format!("user{}", rng.random_range(0..account_count));
+                let receiver = format!("user{}", rng.random_range(0..account_count + 2));
+                let nonce = next_nonce.entry(sender.clone()).or_insert(0u64);
+                let tx = transfer(&sender, &receiver, rng.random_range(1..400), *nonce);
+                if rng.random_range(0..20) != 0 {
+                    *nonce += 1;
+                }
+                tx
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parallel_execution_matches_sequential() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let gas_prices = GasPrices::default();
+        for account_count in [2, 10, 200] {
+            let balances = accounts(account_count);
+            let nonces = HashMap::new();
+            let base = BaseState { balances: &balances, nonces: &nonces };
+            let txs = random_transfers(&mut rng, 300, account_count);
+            let execute = |tx: &Transaction, view: &mut block_stm::TxView| block_stm::execute_transfer(tx, view, &gas_prices);
+
+            let sequential = block_stm::execute_sequential(&txs, base, execute);
+            let parallel = block_stm::execute_parallel(&txs, base, 4, execute);
+            for (index, (expected, actual)) in sequential.results.iter().zip(&parallel.results).enumerate() {
+                assert_eq!(expected.output, actual.output, "outcome of tx {} with {} accounts", index, account_count);
+                assert_eq!(expected.writes, actual.writes, "writes of tx {} with {} accounts", index, account_count);
+            }
+        }
+    }
+
+    #[test]
+    fn test_independent_senders_run_once() {
+        let balances = accounts(64);
+        let nonces = HashMap::new();
+        let txs: Vec<Transaction> = (0..32).map(|i| transfer(&format!("user{}", i), &format!("user{}", i + 32), 5, 0)).collect();
+        let gas_prices = GasPrices::default();
+
+        let output = block_stm::execute_parallel(&txs, BaseState { balances: &balances, nonces: &nonces }, 4, |tx, view| {
+            block_stm::execute_transfer(tx, view, &gas_prices)
+        });
+        assert_eq!(output.waves, 1);
+        assert_eq!(output.re_executions(), 0);
+    }
+
+    #[test]
+    fn test_dependent_chain_is_not_re_executed_over_and_over() {
+        let balances = accounts(2);
+        let nonces = HashMap::new();
+        let txs: Vec<Transaction> = (0..20).map(|nonce| transfer("user0", "user1", 5, nonce)).collect();
+        let gas_prices = GasPrices::default();
+
+        let output = block_stm::execute_parallel(&txs, BaseState { balances: &balances, nonces: &nonces }, 4, |tx, view| {
+            block_stm::execute_transfer(tx, view, &gas_prices)
+        });
+        // Every transaction reads the nonce the previous one wrote
+        assert_eq!(output.re_executions(), txs.len() - 1);
+        assert!(output.results.iter().all(|r| matches!(r.output, block_stm::TransferOutcome::Executed { .. })));
+    }
+
+    fn run_sequencer(mode: ExecutionMode, txs: &[Transaction]) -> (Sequencer, Arc<Mempool>) {
+        let mempool = Arc::new(Mempool::new());
+        mempool.balances.lock().unwrap().extend(accounts(12));
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+        sequencer.execution_mode = mode;
+        sequencer.block_policy.max_transactions = 7;
+        for tx in txs {
+            mempool.submit_transaction(tx.clone());
+        }
+        for _ in 0..txs.len() {
+            sequencer.process_transactions();
+        }
+        (sequencer, mempool)
+    }
+
+    #[test]
+    fn test_parallel_sequencer_builds_the_same_blocks() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut txs = random_transfers(&mut rng, 60, 12);
+        // A declaration runs on its own between two parallel batches, and a tip is paid
+        txs.insert(20, Transaction::new("Cyndie".to_string(), TransactionType::Declare, None, Some("erc20_class".to_string()), None, 0));
+        txs[30] = txs[30].clone().with_max_fee(U256::from(60u64)).with_tip(U256::from(2u64));
+
+        let (sequential, sequential_mempool) = run_sequencer(ExecutionMode::Sequential, &txs);
+        let (parallel, parallel_mempool) = run_sequencer(ExecutionMode::Parallel { workers: 4 }, &txs);
+
+        assert_eq!(sequential.state_root, parallel.state_root);
+        assert_eq!(sequential_mempool.snapshot(), parallel_mempool.snapshot());
+        assert_eq!(
+            *sequential_mempool.rejected_transactions.lock().unwrap(),
+            *parallel_mempool.rejected_transactions.lock().unwrap()
+        );
+        let sequential_blocks = sequential.blocks.lock().unwrap();
+        let parallel_blocks = parallel.blocks.lock().unwrap();
+        assert!(sequential_blocks.len() > 1);
+        assert_eq!(sequential_blocks.len(), parallel_blocks.len());
+        for (expected, actual) in sequential_blocks.iter().zip(parallel_blocks.iter()) {
+            assert_eq!(expected.header.transaction_commitment, actual.header.transaction_commitment);
+            assert_eq!(expected.header.state_root, actual.header.state_root);
+            assert_eq!(expected.state_diff, actual.state_diff);
+            let fees = |block: &starknet_simulator::block::L2Block| {
+                block.receipts.iter().map(|r| (r.transaction_hash.clone(), r.actual_fee, r.tip)).collect::<Vec<_>>()
+            };
+            assert_eq!(fees(expected), fees(actual));
+        }
+    }
+}
@@ -0,0 +1,99 @@
+#[cfg(test)]
+mod tests {
+    use starknet_simulator::block::{BlockStatus, InvalidTransition, L2Block, PendingBlock};
+    use starknet_simulator::gas::GasPrices;
+    use starknet_simulator::mempool::Mempool;
+    use starknet_simulator::state::StateDiff;
+    use starknet_simulator::transaction::{Transaction, TransactionReceipt, TransactionType};
+    use starknet_simulator::utils::U256;
+
+    fn block() -> L2Block {
+        L2Block::new(1, "genesis_hash".to_string(), "sequencer".to_string(), vec![], vec![], GasPrices::default())
+    }
+
+    #[test]
+    fn test_block_walks_the_pipeline_in_order() {
+        let mut block = block();
+        assert_eq!(block.header.block_status, BlockStatus::Pending);
+        for status in [
+            BlockStatus::AcceptedOnL2,
+            BlockStatus::ProofRequested,
+            BlockStatus::Proven,
+            BlockStatus::SubmittedToL1,
+            BlockStatus::AcceptedOnL1,
+        ] {
+            block.transition(status.clone()).unwrap();
+            assert_eq!(block.header.block_status, status);
+        }
+    }
+
+    #[test]
+    fn test_stages_cannot_be_skipped_or_undone() {
+        let mut block = block();
+        assert_eq!(
+            block.transition(BlockStatus::AcceptedOnL1),
+            Err(InvalidTransition { block_number: 1, from: BlockStatus::Pending, to: BlockStatus::AcceptedOnL1 })
+        );
+        block.transition(BlockStatus::AcceptedOnL2).unwrap();
+        assert!(block.transition(BlockStatus::Proven).is_err());
+        assert!(block.transition(BlockStatus::Pending).is_err());
+        // Only blocks in the proving or settlement stages can be rejected
+        assert!(block.transition(BlockStatus::Rejected { reason: "bad proof".to_string() }).is_err());
+        assert_eq!(block.header.block_status, BlockStatus::AcceptedOnL2);
+    }
+
+    #[test]
+    fn test_rejected_block_goes_back_to_the_proving_queue() {
+        let mut block = block();
+        for status in [BlockStatus::AcceptedOnL2, BlockStatus::ProofRequested, BlockStatus::Proven] {
+            block.transition(status).unwrap();
+        }
+        block.transition(BlockStatus::Rejected { reason: "bad proof".to_string() }).unwrap();
+        assert!(block.transition(BlockStatus::SubmittedToL1).is_err());
+        block.transition(BlockStatus::AcceptedOnL2).unwrap();
+
+        let mut final_block = block.clone();
+        for status in [BlockStatus::ProofRequested, BlockStatus::Proven, BlockStatus::SubmittedToL1, BlockStatus::AcceptedOnL1] {
+            final_block.transition(status).unwrap();
+        }
+        assert!(final_block.transition(BlockStatus::Rejected { reason: "late".to_string() }).is_err());
+    }
+
+    #[test]
+    fn test_pending_block_seals_into_an_accepted_block() {
+        let mempool = Mempool::new();
+        let parent_state = mempool.snapshot();
+        let mut pending = PendingBlock::new(1, "genesis_hash".to_string(), "sequencer".to_string(), GasPrices::default(), parent_state.clone());
+        assert!(pending.is_empty());
+
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::ONE), 0);
+        let receipt = TransactionReceipt {
+            transaction_hash: tx.get_hash(),
+            tx_id: tx.id,
+            status: tx.status.clone(),
+            resources: Default::default(),
+            gas_consumed: Default::default(),
+            actual_fee: U256::ZERO,
+            tip: U256::ZERO,
+            messages_sent: vec![],
+            events: vec![],
+            revert_reason: None,
+            fee_payer: "Alice".to_string(),
+            phases: Default::default(),
+        };
+        let mut state_diff = StateDiff::default();
+        state_diff.nonce_updates.insert("Alice".to_string(), 1);
+        pending.push(tx.clone(), receipt, state_diff);
+        assert!(pending.get_receipt(&tx.get_hash()).is_some());
+        assert_eq!(pending.state_diff().nonce_updates.get("Alice"), Some(&1));
+        let state = pending.state();
+        assert_eq!(state.nonces.get("Alice"), Some(&1));
+
+        let block = pending.seal(state.clone());
+        assert_eq!(block.header.block_status, BlockStatus::AcceptedOnL2);
+        assert_eq!(block.header.parent_state_root, parent_state.state_root());
+        assert_eq!(block.header.state_root, state.state_root());
+        assert_eq!(block.transactions.len(), 1);
+        assert!(block.verify_integrity().is_ok());
+    }
+}
@@ -0,0 +1,198 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use starknet_simulator::bridge::{BridgeError, BridgeLedger, L1Bridge, L2Bridge, L2_BRIDGE_ADDRESS};
+    use starknet_simulator::l1::L1Error;
+    use starknet_simulator::mempool::Mempool;
+    use starknet_simulator::prover::Prover;
+    use starknet_simulator::sequencer::Sequencer;
+    use starknet_simulator::state::{self, StateError};
+    use starknet_simulator::transaction::{Transaction, TransactionStatus, TransactionType};
+    use starknet_simulator::utils::U256;
+    use starknet_simulator::verifier::Verifier;
+
+    struct Bridged {
+        sequencer: Arc<Mutex<Sequencer>>,
+        mempool: Arc<Mempool>,
+        l1_bridge: L1Bridge,
+        prover: Prover,
+        verifier: Verifier,
+    }
+
+    fn setup() -> Bridged {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+        sequencer.deploy_contract(L2_BRIDGE_ADDRESS, Arc::new(L2Bridge));
+
+        let mut l1_balances = HashMap::new();
+        l1_balances.insert("Mark_L1".to_string(), U256::from(1000u64));
+        let l1_bridge = L1Bridge::new(sequencer.core_contract.clone(), l1_balances);
+
+        let core_contract = sequencer.core_contract.clone();
+        let sequencer = Arc::new(Mutex::new(sequencer));
+        let mut prover = Prover::new(sequencer.clone());
+        prover.proving_delay = Duration::ZERO;
+        let mut verifier = Verifier::new(sequencer.clone(), core_contract);
+        verifier.verification_delay = Duration::ZERO;
+
+        Bridged { sequencer, mempool, l1_bridge, prover, verifier }
+    }
+
+    fn ledger(bridged: &Bridged) -> BridgeLedger {
+        BridgeLedger::new(&bridged.l1_bridge, &bridged.mempool.storage.lock().unwrap())
+    }
+
+    fn initiate_withdraw(sender: &str, nonce: u64, amount: u64) -> Transaction {
+        Transaction::new(sender.to_string(), TransactionType::Invoke, None, None, None, nonce)
+            .with_call(L2_BRIDGE_ADDRESS, "initiate_withdraw", vec![format!("{}_L1", sender), amount.to_string()])
+            .with_max_fee(U256::from(20u64))
+    }
+
+    #[test]
+    fn test_deposit_use_withdraw_lifecycle_reconciles() {
+        let mut bridged = setup();
+        let balance = |bridged: &Bridged, account: &str| state::get_balance(&bridged.mempool.balances.lock().unwrap(), account);
+
+        // Deposit: Mark has nothing on L2 until the L1 handler runs
+        bridged.l1_bridge.deposit("Mark_L1", "Mark", U256::from(100u64), U256::from(10u64)).unwrap();
+        assert_eq!(bridged.l1_bridge.l1_balances["Mark_L1"], U256::from(890u64));
+        assert_eq!(ledger(&bridged).deposits_in_flight(), U256::from(100u64));
+        assert!(ledger(&bridged).is_reconciled());
+
+        bridged.sequencer.lock().unwrap().process_transactions();
+        assert_eq!(balance(&bridged, "Mark"), U256::from(100u64));
+        assert_eq!(ledger(&bridged).l2_bridged_supply(), U256::from(100u64));
+        assert!(ledger(&bridged).is_reconciled());
+
+        // Use: the bridged tokens pay for a transfer and its fee
+        let transfer = Transaction::new("Mark".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(20u64)), 0);
+        bridged.mempool.submit_transaction(transfer);
+        bridged.sequencer.lock().unwrap().process_transactions();
+        assert!(balance(&bridged, "Mark") < U256::from(80u64));
+
+        // Withdraw: burned on L2, claimable only once the block is final on L1
+        let withdrawal = initiate_withdraw("Mark", 1, 40);
+        let withdrawal_hash = withdrawal.get_hash();
+        let balance_before = balance(&bridged, "Mark");
+        bridged.mempool.submit_transaction(withdrawal);
+        bridged.sequencer.lock().unwrap().process_transactions();
+        let receipt = bridged.sequencer.lock().unwrap().get_receipt(&withdrawal_hash).unwrap();
+        assert_eq!(receipt.status, TransactionStatus::Succeeded);
+        assert_eq!(
+            balance(&bridged, "Mark"),
+            balance_before.checked_sub(U256::from(40u64)).unwrap().checked_sub(receipt.actual_fee).unwrap()
+        );
+        assert_eq!(ledger(&bridged).withdrawals_in_flight(), U256::from(40u64));
+        assert!(ledger(&bridged).is_reconciled());
+
+        assert!(matches!(
+            bridged.l1_bridge.withdraw("Mark_L1", U256::from(40u64)),
+            Err(BridgeError::L1(L1Error::L2ToL1MessageNotFound { .. }))
+        ));
+
+        bridged.prover.verify_proof().unwrap();
+        bridged.verifier.verify_proofs();
+        bridged.l1_bridge.withdraw("Mark_L1", U256::from(40u64)).unwrap();
+        assert_eq!(bridged.l1_bridge.l1_balances["Mark_L1"], U256::from(930u64));
+        assert!(bridged.l1_bridge.withdraw("Mark_L1", U256::from(40u64)).is_err());
+
+        let ledger = ledger(&bridged);
+        assert_eq!(ledger.l1_locked, U256::from(60u64));
+        assert_eq!(ledger.l2_bridged_supply(), U256::from(60u64));
+        assert_eq!(ledger.withdrawals_in_flight(), U256::ZERO);
+        assert!(ledger.is_reconciled());
+    }
+
+    #[test]
+    fn test_cannot_withdraw_more_than_was_bridged() {
+        let mut bridged = setup();
+        bridged.l1_bridge.deposit("Mark_L1", "Alice", U256::from(10u64), U256::from(10u64)).unwrap();
+
+        // Alice's genesis tokens were never locked on L1
+        let withdrawal = initiate_withdraw("Alice", 0, 50);
+        let withdrawal_hash = withdrawal.get_hash();
+        bridged.mempool.submit_transaction(withdrawal);
+        bridged.sequencer.lock().unwrap().process_transactions();
+
+        let receipt = bridged.sequencer.lock().unwrap().get_receipt(&withdrawal_hash).unwrap();
+        assert_eq!(receipt.status, TransactionStatus::Reverted);
+        assert!(receipt.messages_sent.is_empty());
+        assert_eq!(ledger(&bridged).l2_total_burned, U256::ZERO);
+        assert!(ledger(&bridged).is_reconciled());
+    }
+
+    #[test]
+    fn test_deposit_needs_l1_funds() {
+        let mut bridged = setup();
+
+        assert_eq!(
+            bridged.l1_bridge.deposit("Mark_L1", "Mark", U256::from(995u64), U256::from(10u64)),
+            Err(BridgeError::Balance(StateError::InsufficientBalance {
+                account: "Mark_L1".to_string(),
+                balance: U256::from(1000u64),
+                required: U256::from(1005u64),
+            }))
+        );
+        assert_eq!(bridged.l1_bridge.deposit("Mark_L1", "Mark", U256::ZERO, U256::ONE), Err(BridgeError::ZeroAmount));
+        assert_eq!(bridged.l1_bridge.locked, U256::ZERO);
+    }
+
+    #[test]
+    fn test_failed_withdrawal_changes_nothing_and_stays_claimable() {
+        let mut bridged = setup();
+        bridged.l1_bridge.deposit("Mark_L1", "Mark", U256::from(100u64), U256::from(10u64)).unwrap();
+        bridged.sequencer.lock().unwrap().process_transactions();
+        bridged.mempool.submit_transaction(initiate_withdraw("Mark", 0, 40));
+        bridged.sequencer.lock().unwrap().process_transactions();
+        bridged.prover.verify_proof().unwrap();
+        bridged.verifier.verify_proofs();
+
+        // Crediting the recipient would overflow: the claim fails before anything moves
+        bridged.l1_bridge.l1_balances.insert("Mark_L1".to_string(), U256::MAX);
+        assert_eq!(
+            bridged.l1_bridge.withdraw("Mark_L1", U256::from(40u64)),
+            Err(BridgeError::Balance(StateError::BalanceOverflow { account: "Mark_L1".to_string() }))
+        );
+        assert_eq!(bridged.l1_bridge.locked, U256::from(100u64));
+        assert_eq!(bridged.l1_bridge.total_withdrawn, U256::ZERO);
+
+        bridged.l1_bridge.l1_balances.insert("Mark_L1".to_string(), U256::ZERO);
+        bridged.l1_bridge.withdraw("Mark_L1", U256::from(40u64)).unwrap();
+        assert_eq!(bridged.l1_bridge.l1_balances["Mark_L1"], U256::from(40u64));
+        assert!(ledger(&bridged).is_reconciled());
+    }
+
+    #[test]
+    fn test_cancelled_deposit_is_reclaimed_on_l1() {
+        let mut bridged = setup();
+        let message = bridged.l1_bridge.deposit("Mark_L1", "Mark", U256::from(100u64), U256::from(10u64)).unwrap();
+
+        assert_eq!(
+            bridged.l1_bridge.start_deposit_cancellation("Mallory_L1", &message),
+            Err(BridgeError::NotDepositor { message_hash: message.hash() })
+        );
+        bridged.l1_bridge.start_deposit_cancellation("Mark_L1", &message).unwrap();
+        assert!(matches!(
+            bridged.l1_bridge.reclaim_deposit("Mark_L1", &message),
+            Err(BridgeError::L1(L1Error::CancellationTooEarly { .. }))
+        ));
+        assert_eq!(bridged.l1_bridge.locked, U256::from(100u64));
+
+        bridged.sequencer.lock().unwrap().core_contract.lock().unwrap().message_cancellation_delay = Duration::ZERO;
+        bridged.l1_bridge.reclaim_deposit("Mark_L1", &message).unwrap();
+        // The message fee is not refunded
+        assert_eq!(bridged.l1_bridge.l1_balances["Mark_L1"], U256::from(990u64));
+        assert_eq!(bridged.l1_bridge.locked, U256::ZERO);
+        assert!(bridged.l1_bridge.reclaim_deposit("Mark_L1", &message).is_err());
+
+        // L2 never mints the reclaimed deposit
+        bridged.sequencer.lock().unwrap().process_transactions();
+        assert_eq!(state::get_balance(&bridged.mempool.balances.lock().unwrap(), "Mark"), U256::ZERO);
+        let ledger = ledger(&bridged);
+        assert_eq!(ledger.deposits_in_flight(), U256::ZERO);
+        assert!(ledger.is_reconciled());
+    }
+}
@@ -0,0 +1,207 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use starknet_simulator::bridge::{L2Bridge, L2_BRIDGE_ADDRESS};
+    use starknet_simulator::contract::{self, Contract, EntryPointType, ExecutionContext, ACCOUNT_CLASS_HASH};
+    use starknet_simulator::da::{self, felt_from_str, pack_contract_header, DaMode};
+    use starknet_simulator::gas::GasPrices;
+    use starknet_simulator::mempool::Mempool;
+    use starknet_simulator::prover::Prover;
+    use starknet_simulator::verifier::Verifier;
+    use starknet_simulator::sequencer::Sequencer;
+    use starknet_simulator::state::{StateDiff, FEE_TOKEN_ADDRESS};
+    use starknet_simulator::transaction::{Transaction, TransactionStatus, TransactionType};
+    use starknet_simulator::utils::U256;
+
+    /// Same bridge under a new class hash
+    struct UpgradedBridge;
+
+    impl Contract for UpgradedBridge {
+        fn class_hash(&self) -> String {
+            "starkgate_bridge_v2".to_string()
+        }
+
+        fn entry_point_type(&self, selector: &str) -> Option<EntryPointType> {
+            L2Bridge.entry_point_type(selector)
+        }
+
+        fn execute(&self, selector: &str, calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+            L2Bridge.execute(selector, calldata, ctx)
+        }
+    }
+
+    fn new_sequencer() -> Sequencer {
+        let mut sequencer = Sequencer::new(Arc::new(Mempool::new()));
+        sequencer.processing_delay = Duration::ZERO;
+        sequencer
+    }
+
+    #[test]
+    fn test_encoding_follows_the_felt_layout() {
+        let mut diff = StateDiff::default();
+        diff.storage_updates.entry("token".to_string()).or_default().insert("Alice".to_string(), U256::from(7u64));
+        diff.storage_updates.entry("token".to_string()).or_default().insert("Bob".to_string(), U256::from(9u64));
+        diff.nonce_updates.insert("Alice".to_string(), 3);
+        diff.deployed_contracts.insert("counter".to_string(), "counter_class".to_string());
+        diff.declared_classes.insert("counter_class".to_string(), "counter_casm".to_string());
+
+        let felts = da::encode_state_diff(&diff);
+        let expected = vec![
+            U256::from(3u64),
+            // Alice: nonce only
+            felt_from_str("Alice"),
+            pack_contract_header(false, 3, 0),
+            // counter: deployed, no storage
+            felt_from_str("counter"),
+            pack_contract_header(true, 0, 0),
+            felt_from_str("counter_class"),
+            // token: two storage updates
+            felt_from_str("token"),
+            pack_contract_header(false, 0, 2),
+            felt_from_str("Alice"),
+            U256::from(7u64),
+            felt_from_str("Bob"),
+            U256::from(9u64),
+            // declared classes
+            U256::ONE,
+            felt_from_str("counter_class"),
+            felt_from_str("counter_casm"),
+        ];
+        assert_eq!(felts, expected);
+        assert_eq!(pack_contract_header(true, 3, 2), U256::from_low_high((3u128 << 64) | 2, 1));
+        assert!(felt_from_str("Alice").high < 1u128 << 122);
+    }
+
+    #[test]
+    fn test_block_carries_its_diff_and_da_size() {
+        let mut sequencer = new_sequencer();
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::ONE), 0);
+        sequencer.mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let blocks = sequencer.blocks.lock().unwrap();
+        let block = &blocks[0];
+        assert_eq!(block.state_diff.nonce_updates["Alice"], 1);
+        assert!(block.state_diff.storage_updates[FEE_TOKEN_ADDRESS].contains_key("Bob"));
+        assert_eq!(block.da_encoding, da::encode_state_diff(&block.state_diff));
+        assert_eq!(block.header.state_diff_length, block.da_encoding.len());
+        assert_eq!(block.da_size_bytes(), block.da_encoding.len() * 32);
+    }
+
+    #[test]
+    fn test_diff_tracks_deployed_declared_and_replaced_classes() {
+        let mut sequencer = new_sequencer();
+        sequencer.deploy_contract(L2_BRIDGE_ADDRESS, Arc::new(L2Bridge));
+
+        let declare = Transaction::new("Alice".to_string(), TransactionType::Declare, None, Some("counter_class".to_string()), None, 0);
+        let deploy = Transaction::new("Mark".to_string(), TransactionType::DeployAccount, None, None, None, 0);
+        sequencer.mempool.submit_transaction(declare);
+        sequencer.mempool.submit_transaction(deploy);
+        sequencer.mempool.balances.lock().unwrap().insert("Mark".to_string(), U256::from(50u64));
+        sequencer.process_transactions();
+
+        {
+            let blocks = sequencer.blocks.lock().unwrap();
+            let diff = &blocks[0].state_diff;
+            assert_eq!(diff.deployed_contracts[L2_BRIDGE_ADDRESS], "starkgate_bridge_class");
+            assert_eq!(diff.deployed_contracts["Mark"], ACCOUNT_CLASS_HASH);
+            assert_eq!(diff.declared_classes["counter_class"], contract::compiled_class_hash("counter_class"));
+            assert!(diff.replaced_classes.is_empty());
+        }
+
+        // Declaring the same class again reverts; replacing a class shows up as a replacement
+        let declare_again = Transaction::new("Alice".to_string(), TransactionType::Declare, None, Some("counter_class".to_string()), None, 1);
+        sequencer.mempool.submit_transaction(declare_again);
+        sequencer.replace_class(L2_BRIDGE_ADDRESS, Arc::new(UpgradedBridge)).unwrap();
+        assert!(sequencer.replace_class("nowhere", Arc::new(UpgradedBridge)).is_err());
+        sequencer.process_transactions();
+
+        let blocks = sequencer.blocks.lock().unwrap();
+        assert_eq!(blocks[1].transactions[0].status, TransactionStatus::Reverted);
+        assert!(blocks[1].state_diff.declared_classes.is_empty());
+        assert_eq!(blocks[1].state_diff.replaced_classes[L2_BRIDGE_ADDRESS], "starkgate_bridge_v2");
+        assert!(blocks[1].state_diff.deployed_contracts.is_empty());
+    }
+
+    #[test]
+    fn test_each_da_mode_has_its_own_pricing_and_capacity() {
+        let gas_prices = GasPrices {
+            l1_gas_price: U256::from(2u64),
+            l1_data_gas_price: U256::from(3u64),
+            ..GasPrices::default()
+        };
+
+        let calldata = DaMode::calldata().publication_cost(1_000, &gas_prices);
+        assert_eq!((calldata.units, calldata.l1_blocks, calldata.cost), (1_000, 1, U256::from(32_000u64)));
+
+        // Blobs are paid whole: 1,000 bytes still take a full blob
+        let blob = DaMode::blob().publication_cost(1_000, &gas_prices);
+        assert_eq!((blob.units, blob.l1_blocks, blob.cost), (1, 1, U256::from(131_072u64 * 3)));
+        let big = DaMode::blob().publication_cost(7 * 131_072, &gas_prices);
+        assert_eq!((big.units, big.l1_blocks), (7, 2));
+
+        let offchain = DaMode::offchain().publication_cost(1_000, &gas_prices);
+        assert_eq!((offchain.units, offchain.l1_blocks, offchain.cost), (0, 0, U256::ONE));
+    }
+
+    fn proven_blocks(count: u64) -> (Arc<Mutex<Sequencer>>, Verifier) {
+        let mut sequencer = new_sequencer();
+        for nonce in 0..count {
+            let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::ONE), nonce);
+            sequencer.mempool.submit_transaction(tx);
+            sequencer.process_transactions();
+        }
+        let core_contract = sequencer.core_contract.clone();
+        let sequencer = Arc::new(Mutex::new(sequencer));
+        let mut prover = Prover::new(sequencer.clone());
+        prover.proving_delay = Duration::ZERO;
+        prover.verify_proof().unwrap();
+
+        let mut verifier = Verifier::new(sequencer.clone(), core_contract);
+        verifier.verification_delay = Duration::ZERO;
+        (sequencer, verifier)
+    }
+
+    #[test]
+    fn test_blob_capacity_spreads_blocks_over_l1_blocks() {
+        let (sequencer, verifier) = proven_blocks(3);
+        let block_bytes = sequencer.lock().unwrap().blocks.lock().unwrap().iter().map(|b| b.da_size_bytes()).max().unwrap();
+        // One blob per L2 block, two blobs per L1 block
+        verifier.core_contract.lock().unwrap().da_mode = DaMode::Blob {
+            blob_gas_per_blob: 131_072,
+            bytes_per_blob: block_bytes,
+            max_blobs_per_l1_block: 2,
+        };
+
+        verifier.verify_proofs();
+        assert_eq!(verifier.core_contract.lock().unwrap().block_number, 2);
+        verifier.verify_proofs();
+        assert_eq!(verifier.core_contract.lock().unwrap().block_number, 3);
+
+        let da_costs = verifier.da_costs.lock().unwrap();
+        assert_eq!(da_costs.iter().map(|(block_number, _)| *block_number).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(da_costs.iter().all(|(_, cost)| cost.cost == U256::from(131_072u64)));
+    }
+
+    #[test]
+    fn test_offchain_mode_keeps_data_off_l1_and_costs_compare() {
+        let (sequencer, verifier) = proven_blocks(2);
+        verifier.core_contract.lock().unwrap().da_mode = DaMode::offchain();
+        verifier.verify_proofs();
+
+        let core_contract = verifier.core_contract.lock().unwrap();
+        assert_eq!(core_contract.block_number, 2);
+        assert!(core_contract.state_updates.iter().all(|u| u.state_diff.is_empty() && u.da_mode == DaMode::offchain()));
+
+        let sequencer = sequencer.lock().unwrap();
+        let blocks = sequencer.blocks.lock().unwrap();
+        let costs = da::compare_da_costs(&blocks, &[DaMode::calldata(), DaMode::blob(), DaMode::offchain()]);
+        let total_bytes: usize = blocks.iter().map(|b| b.da_size_bytes()).sum();
+        assert!(costs.iter().all(|cost| cost.bytes == total_bytes));
+        assert_eq!(costs[0].cost, U256::from(total_bytes as u64 * 16));
+        assert_eq!(costs[1].units, 2); // one partly filled blob per block
+        // Small diffs: off-chain < calldata < half-empty blobs
+        assert!(costs[2].cost < costs[0].cost && costs[0].cost < costs[1].cost);
+    }
+}
@@ -0,0 +1,200 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use starknet_simulator::contract::{Contract, EntryPointType, ExecutionContext};
+    use starknet_simulator::event::{Event, EventFilter, EventQueryError};
+    use starknet_simulator::mempool::Mempool;
+    use starknet_simulator::sequencer::Sequencer;
+    use starknet_simulator::transaction::{Transaction, TransactionStatus, TransactionType};
+    use starknet_simulator::utils::U256;
+
+    /// `emit` emits `Transfer(caller, to)` with the amount as data; `emit_and_fail` emits
+    /// and then fails; `emit_and_catch` emits `Outer` and makes a failing call to itself
+    struct Emitter;
+
+    impl Contract for Emitter {
+        fn class_hash(&self) -> String {
+            "emitter_class".to_string()
+        }
+
+        fn entry_point_type(&self, selector: &str) -> Option<EntryPointType> {
+            matches!(selector, "emit" | "emit_and_fail" | "emit_and_catch").then_some(EntryPointType::External)
+        }
+
+        fn execute(&self, selector: &str, calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+            match selector {
+                "emit" => {
+                    let caller = ctx.caller_address.clone();
+                    ctx.emit_event(vec!["Transfer".to_string(), caller, calldata[0].clone()], vec![calldata[1].clone()]);
+                    Ok(vec![])
+                }
+                "emit_and_fail" => {
+                    ctx.emit_event(vec!["Failed".to_string()], vec![]);
+                    Err("emitter failed".to_string())
+                }
+                _ => {
+                    ctx.emit_event(vec!["Outer".to_string()], vec![]);
+                    let address = ctx.contract_address.clone();
+                    assert!(ctx.call_contract(&address, "emit_and_fail", &[]).is_err());
+                    Ok(vec![])
+                }
+            }
+        }
+    }
+
+    fn setup() -> (Sequencer, Arc<Mempool>) {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+        sequencer.deploy_contract("token", Arc::new(Emitter));
+        sequencer.deploy_contract("nft", Arc::new(Emitter));
+        (sequencer, mempool)
+    }
+
+    fn call(sender: &str, nonce: u64, contract: &str, selector: &str, calldata: &[&str]) -> Transaction {
+        Transaction::new(sender.to_string(), TransactionType::Invoke, None, None, None, nonce)
+            .with_call(contract, selector, calldata.iter().map(|arg| arg.to_string()).collect())
+            .with_max_fee(U256::from(50u64))
+    }
+
+    fn transfer_event(from_address: &str, from: &str, to: &str, amount: &str) -> Event {
+        Event {
+            from_address: from_address.to_string(),
+            keys: vec!["Transfer".to_string(), from.to_string(), to.to_string()],
+            data: vec![amount.to_string()],
+        }
+    }
+
+    /// Block 0: Alice and Bob emit on `token` and `nft`. Block 1: Cyndie emits on `token`.
+    fn two_blocks_of_events() -> (Sequencer, Arc<Mempool>) {
+        let (mut sequencer, mempool) = setup();
+        mempool.submit_transaction(call("Alice", 0, "token", "emit", &["Bob", "1"]));
+        mempool.submit_transaction(call("Bob", 0, "nft", "emit", &["Alice", "2"]));
+        sequencer.process_transactions();
+        mempool.submit_transaction(call("Cyndie", 0, "token", "emit", &["Alice", "3"]));
+        sequencer.process_transactions();
+        (sequencer, mempool)
+    }
+
+    #[test]
+    fn test_events_are_kept_in_receipts_and_blocks() {
+        let (mut sequencer, mempool) = setup();
+        let tx = call("Alice", 0, "token", "emit", &["Bob", "5"]);
+        let tx_hash = tx.get_hash();
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let expected = vec![transfer_event("token", "Alice", "Bob", "5")];
+        assert_eq!(sequencer.get_receipt(&tx_hash).unwrap().events, expected);
+        let mut blocks = sequencer.blocks.lock().unwrap();
+        assert_eq!(blocks[0].events(), expected);
+        assert_eq!(blocks[0].header.event_count, 1);
+        assert!(blocks[0].verify_integrity().is_ok());
+
+        // An event edited after sealing no longer matches the commitment
+        blocks[0].receipts[0].events[0].data[0] = "500".to_string();
+        assert!(blocks[0].verify_integrity().is_err());
+    }
+
+    #[test]
+    fn test_reverted_calls_emit_nothing() {
+        let (mut sequencer, mempool) = setup();
+        let failing = call("Alice", 0, "token", "emit_and_fail", &[]);
+        let catching = call("Bob", 0, "token", "emit_and_catch", &[]);
+        let (failing_hash, catching_hash) = (failing.get_hash(), catching.get_hash());
+        mempool.submit_transaction(failing);
+        mempool.submit_transaction(catching);
+        sequencer.process_transactions();
+
+        let failed = sequencer.get_receipt(&failing_hash).unwrap();
+        assert_eq!(failed.status, TransactionStatus::Reverted);
+        assert!(failed.events.is_empty());
+
+        // Only the event of the failed inner call is dropped
+        let caught = sequencer.get_receipt(&catching_hash).unwrap();
+        assert_eq!(caught.status, TransactionStatus::Succeeded);
+        let keys: Vec<Vec<String>> = caught.events.iter().map(|event| event.keys.clone()).collect();
+        assert_eq!(keys, vec![vec!["Outer".to_string()]]);
+    }
+
+    #[test]
+    fn test_get_events_filters_by_block_range_address_and_keys() {
+        let (sequencer, _) = two_blocks_of_events();
+        let query = |filter: EventFilter| -> Vec<Event> {
+            let chunk = sequencer.get_events(&filter).unwrap();
+            assert_eq!(chunk.continuation_token, None);
+            chunk.events.into_iter().map(|emitted| emitted.event).collect()
+        };
+
+        assert_eq!(query(EventFilter::new(10)).len(), 3);
+        assert_eq!(
+            query(EventFilter { address: Some("token".to_string()), ..EventFilter::new(10) }),
+            vec![transfer_event("token", "Alice", "Bob", "1"), transfer_event("token", "Cyndie", "Alice", "3")]
+        );
+        // Anything sent to Alice
+        let to_alice = EventFilter { keys: vec![vec![], vec![], vec!["Alice".to_string()]], ..EventFilter::new(10) };
+        assert_eq!(
+            query(to_alice),
+            vec![transfer_event("nft", "Bob", "Alice", "2"), transfer_event("token", "Cyndie", "Alice", "3")]
+        );
+        // Transfers sent by Bob or Cyndie on token
+        let filter = EventFilter {
+            address: Some("token".to_string()),
+            keys: vec![vec!["Transfer".to_string()], vec!["Bob".to_string(), "Cyndie".to_string()]],
+            ..EventFilter::new(10)
+        };
+        assert_eq!(query(filter), vec![transfer_event("token", "Cyndie", "Alice", "3")]);
+
+        let latest_block = sequencer.blocks.lock().unwrap()[1].clone();
+        let latest = latest_block.header.block_number;
+        let chunk = sequencer
+            .get_events(&EventFilter { from_block: Some(latest), ..EventFilter::new(10) })
+            .unwrap();
+        assert_eq!(chunk.events.len(), 1);
+        assert_eq!(chunk.events[0].block_number, latest);
+        assert_eq!(chunk.events[0].block_hash, latest_block.get_block_hash());
+        assert_eq!(chunk.events[0].transaction_hash, latest_block.receipts[0].transaction_hash);
+        assert_eq!(query(EventFilter { to_block: Some(latest - 1), ..EventFilter::new(10) }).len(), 2);
+    }
+
+    #[test]
+    fn test_get_events_pages_with_a_continuation_token() {
+        let (mut sequencer, mempool) = two_blocks_of_events();
+        mempool.submit_transaction(call("Alice", 1, "token", "emit", &["Mark", "4"]));
+        mempool.submit_transaction(call("Bob", 1, "token", "emit", &["Mark", "5"]));
+        sequencer.process_transactions();
+
+        let all = sequencer.get_events(&EventFilter::new(100)).unwrap().events;
+        assert_eq!(all.len(), 5);
+
+        let mut paged = vec![];
+        let mut pages = 0;
+        let mut filter = EventFilter::new(2);
+        loop {
+            let chunk = sequencer.get_events(&filter).unwrap();
+            paged.extend(chunk.events);
+            pages += 1;
+            match chunk.continuation_token {
+                Some(token) => filter.continuation_token = Some(token),
+                None => break,
+            }
+        }
+        assert_eq!(pages, 3);
+        assert_eq!(paged, all);
+
+        // A page that ends exactly on the last match has no token
+        let filter = EventFilter { address: Some("nft".to_string()), ..EventFilter::new(1) };
+        assert_eq!(sequencer.get_events(&filter).unwrap().continuation_token, None);
+
+        let invalid = EventFilter { continuation_token: Some("next".to_string()), ..EventFilter::new(2) };
+        assert_eq!(
+            sequencer.get_events(&invalid),
+            Err(EventQueryError::InvalidContinuationToken { token: "next".to_string() })
+        );
+        assert_eq!(
+            sequencer.get_events(&EventFilter::new(0)),
+            Err(EventQueryError::InvalidChunkSize { chunk_size: 0 })
+        );
+    }
+}
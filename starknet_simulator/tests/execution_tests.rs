@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use starknet_simulator::execution::{self, BaseState};
+    use starknet_simulator::gas::{FeeCharge, GasPrices};
+    use starknet_simulator::state::{self, StateOverlay, FEE_TOKEN_ADDRESS};
+    use starknet_simulator::transaction::{PhaseOutcome, Transaction, TransactionType};
+    use starknet_simulator::utils::U256;
+
+    fn transfer(nonce: u64) -> Transaction {
+        Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), nonce)
+    }
+
+    fn balances(alice: u64) -> HashMap<String, U256> {
+        HashMap::from([("Alice".to_string(), U256::from(alice))])
+    }
+
+    #[test]
+    fn test_admission_checks_nonce_bounds_and_balance() {
+        let nonces = HashMap::from([("Alice".to_string(), 1)]);
+        let gas_prices = GasPrices::default();
+        let rich = balances(200);
+        let mut state = BaseState { balances: &rich, nonces: &nonces };
+
+        assert!(execution::check_admission(&transfer(1), &mut state, &gas_prices).is_ok());
+        // A wrong nonce may be resent later; a fee the sender cannot pay may not
+        let wrong_nonce = execution::check_admission(&transfer(0), &mut state, &gas_prices).unwrap_err();
+        assert!(!wrong_nonce.remember);
+        let too_low = execution::check_admission(&transfer(1).with_max_fee(U256::ONE), &mut state, &gas_prices).unwrap_err();
+        assert!(too_low.remember);
+
+        let poor = balances(5);
+        let mut state = BaseState { balances: &poor, nonces: &nonces };
+        let rejection = execution::check_admission(&transfer(1), &mut state, &gas_prices).unwrap_err();
+        assert!(rejection.remember);
+        assert!(rejection.reason.contains("Alice's balance"));
+    }
+
+    #[test]
+    fn test_fee_comes_out_of_the_execution_or_the_state_before_it() {
+        let charge = FeeCharge { base_fee: U256::from(8u64), tip: U256::from(2u64) };
+
+        let (mut executed, mut validated) = (balances(50), balances(100));
+        let fee = execution::transfer_fee(Ok(()), &mut executed, &mut validated, "Alice", charge);
+        assert_eq!(fee.revert_reason, None);
+        assert_eq!(executed["Alice"], U256::from(40u64));
+        assert_eq!(validated["Alice"], U256::from(100u64));
+
+        // What the execution left cannot pay: it reverts and the fee comes out of the state before it
+        let (mut executed, mut validated) = (balances(5), balances(100));
+        let fee = execution::transfer_fee(Ok(()), &mut executed, &mut validated, "Alice", charge);
+        assert!(fee.revert_reason.is_some_and(|reason| reason.contains("fee transfer failed")));
+        assert_eq!(validated["Alice"], U256::from(90u64));
+        assert_eq!(fee.phases.fee_transfer, PhaseOutcome::Succeeded);
+
+        // Neither can: the fee is capped, base fee first
+        let (mut executed, mut validated) = (balances(0), balances(9));
+        let fee = execution::transfer_fee(Err("reverted".to_string()), &mut executed, &mut validated, "Alice", charge);
+        assert_eq!(fee.charge, FeeCharge { base_fee: U256::from(8u64), tip: U256::ONE });
+        assert_eq!(validated["Alice"], U256::ZERO);
+        assert!(matches!(fee.phases.fee_transfer, PhaseOutcome::Reverted(_)));
+    }
+
+    #[test]
+    fn test_overlay_holds_only_the_writes_and_reads_through() {
+        let committed_balances = HashMap::from([("Alice".to_string(), U256::from(200u64)), ("Bob".to_string(), U256::from(500u64))]);
+        let mut committed_storage =
+            HashMap::from([("erc20".to_string(), HashMap::from([("a".to_string(), U256::ONE), ("b".to_string(), U256::from(2u64))]))]);
+        let mut overlay = StateOverlay::default();
+
+        state::debit(overlay.balances_for(&committed_balances, &["Alice"]), "Alice", U256::from(50u64)).unwrap();
+        overlay.storage_write("erc20", "a", U256::from(7u64));
+        assert_eq!(overlay.balances.len(), 1);
+        assert_eq!(overlay.balance(&committed_balances, "Alice"), U256::from(150u64));
+        assert_eq!(overlay.balance(&committed_balances, "Bob"), U256::from(500u64));
+        assert_eq!(overlay.storage["erc20"].len(), 1);
+        assert_eq!(overlay.storage_read(&committed_storage, "erc20", "a"), U256::from(7u64));
+        assert_eq!(overlay.storage_read(&committed_storage, "erc20", "b"), U256::from(2u64));
+
+        let state_diff = overlay.state_diff();
+        assert_eq!(state_diff.storage_updates[FEE_TOKEN_ADDRESS]["Alice"], U256::from(150u64));
+        assert_eq!(state_diff.storage_updates["erc20"].len(), 1);
+
+        let mut balances = committed_balances.clone();
+        overlay.commit(&mut balances, &mut committed_storage, &mut HashMap::new(), &mut HashMap::new());
+        assert_eq!(balances["Alice"], U256::from(150u64));
+        assert_eq!(balances["Bob"], U256::from(500u64));
+        // Slots the transaction did not write are kept
+        assert_eq!(committed_storage["erc20"]["a"], U256::from(7u64));
+        assert_eq!(committed_storage["erc20"]["b"], U256::from(2u64));
+    }
+}
@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod tests {
+    use starknet_simulator::gas::{Builtin, FeeMarket, GasPrices, GasVector, ResourceMeter};
+    use starknet_simulator::transaction::{Transaction, TransactionType};
+    use starknet_simulator::utils::U256;
+
+    #[test]
+    fn test_l2_gas_is_decided_by_the_bottleneck_resource() {
+        let mut meter = ResourceMeter::new();
+        meter.steps(400); // 400 * 0.0025 = 1 gas
+        meter.builtin(Builtin::Ecdsa, 1); // 2.56 gas
+
+        let gas = meter.finish().to_gas();
+        assert_eq!(gas.l2_gas, 3);
+    }
+
+    #[test]
+    fn test_repeated_storage_writes_are_charged_once() {
+        let mut meter = ResourceMeter::new();
+        meter.storage_write("Alice", "balance");
+        meter.storage_write("Alice", "balance");
+        meter.storage_write("Bob", "balance");
+
+        let resources = meter.finish();
+        assert_eq!(resources.storage_writes, 2);
+        assert_eq!(resources.to_gas().l1_gas, 4);
+    }
+
+    #[test]
+    fn test_fee_scales_with_gas_prices() {
+        let gas = GasVector { l1_gas: 6, l1_data_gas: 0, l2_gas: 4 };
+        let prices = GasPrices {
+            l1_gas_price: U256::from(3u64),
+            l2_gas_price: U256::from(2u64),
+            ..GasPrices::default()
+        };
+
+        assert_eq!(prices.fee_for(&gas).unwrap(), U256::from(26u64));
+    }
+
+    #[test]
+    fn test_l1_data_gas_is_charged_at_the_data_gas_price() {
+        let mut meter = ResourceMeter::new();
+        meter.storage_write("Alice", "balance");
+        meter.l1_data(3);
+        let gas = meter.finish().to_gas();
+        assert_eq!((gas.l1_gas, gas.l1_data_gas), (2, 3));
+
+        let cheap_blobs = GasPrices::default();
+        let dear_blobs = GasPrices { l1_data_gas_price: U256::from(10u64), ..cheap_blobs };
+        assert_eq!(cheap_blobs.fee_for(&gas).unwrap(), U256::from(5u64));
+        assert_eq!(dear_blobs.fee_for(&gas).unwrap(), U256::from(32u64));
+    }
+
+    #[test]
+    fn test_fee_overflow_is_reported() {
+        let gas = GasVector { l1_gas: 2, l1_data_gas: 0, l2_gas: 0 };
+        let prices = GasPrices {
+            l1_gas_price: U256::MAX,
+            l2_gas_price: U256::ONE,
+            ..GasPrices::default()
+        };
+
+        assert!(prices.fee_for(&gas).is_none());
+    }
+
+    #[test]
+    fn test_declare_costs_more_than_invoke() {
+        let invoke_fee = Transaction::calculate_fee(&TransactionType::Invoke);
+        let declare_fee = Transaction::calculate_fee(&TransactionType::Declare);
+        let deploy_fee = Transaction::calculate_fee(&TransactionType::DeployAccount);
+
+        assert!(declare_fee > deploy_fee);
+        assert!(deploy_fee > invoke_fee);
+    }
+
+    #[test]
+    fn test_l2_gas_price_rises_above_target_and_falls_below() {
+        let market = FeeMarket::default();
+        let price = U256::from(800u64);
+
+        // Full block (2x target): +12.5%
+        assert_eq!(market.next_l2_gas_price(price, market.max_l2_gas), U256::from(900u64));
+        // Empty block: -12.5%
+        assert_eq!(market.next_l2_gas_price(price, 0), U256::from(700u64));
+        // Exactly on target: unchanged
+        assert_eq!(market.next_l2_gas_price(price, market.target_l2_gas), price);
+    }
+
+    #[test]
+    fn test_l2_gas_price_change_is_capped_and_floored() {
+        let market = FeeMarket::default();
+
+        // Usage above the block limit counts as a full block
+        assert_eq!(market.next_l2_gas_price(U256::from(800u64), 10_000), U256::from(900u64));
+        // A price of 1 still rises, and never drops below the minimum
+        assert_eq!(market.next_l2_gas_price(U256::ONE, market.max_l2_gas), U256::from(2u64));
+        assert_eq!(market.next_l2_gas_price(U256::ONE, 0), market.min_l2_gas_price);
+    }
+}
@@ -0,0 +1,463 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use starknet_simulator::block::{BlockStatus, L2Block};
+    use starknet_simulator::contract::{parse_felt, Contract, EntryPointType, ExecutionContext};
+    use starknet_simulator::l1::{ConstantGasPrice, L1Error, L1GasPriceSource, L1GasQuote, L1ToL2Message, L1ToL2MessageStatus, RandomWalkGasPrice, ScriptedGasPrice, StarknetCoreContract};
+    use starknet_simulator::mempool::Mempool;
+    use starknet_simulator::prover::Prover;
+    use starknet_simulator::sequencer::Sequencer;
+    use starknet_simulator::verifier::Verifier;
+    use starknet_simulator::transaction::{Transaction, TransactionStatus, TransactionType};
+    use starknet_simulator::utils::U256;
+
+    fn quote(l1_gas_price: u64, l1_data_gas_price: u64) -> L1GasQuote {
+        L1GasQuote {
+            l1_gas_price: U256::from(l1_gas_price),
+            l1_data_gas_price: U256::from(l1_data_gas_price),
+        }
+    }
+
+    #[test]
+    fn test_scripted_prices_replay_then_repeat_the_last_quote() {
+        let mut source = ScriptedGasPrice::new(vec![quote(1, 1), quote(3, 2)]);
+
+        assert_eq!(source.next_quote(), quote(1, 1));
+        assert_eq!(source.next_quote(), quote(3, 2));
+        assert_eq!(source.next_quote(), quote(3, 2));
+    }
+
+    #[test]
+    fn test_random_walk_is_reproducible_and_bounded() {
+        let mut a = RandomWalkGasPrice::new(42, quote(10, 5), 3, 1, 20);
+        let mut b = RandomWalkGasPrice::new(42, quote(10, 5), 3, 1, 20);
+
+        for _ in 0..100 {
+            let next = a.next_quote();
+            assert_eq!(next, b.next_quote());
+            assert!(next.l1_gas_price >= U256::ONE && next.l1_gas_price <= U256::from(20u64));
+            assert!(next.l1_data_gas_price >= U256::ONE && next.l1_data_gas_price <= U256::from(20u64));
+        }
+    }
+
+    #[test]
+    fn test_block_records_l1_prices_and_fees_scale_with_them() {
+        let mut fees = vec![];
+        for l1_price in [1u64, 3] {
+            let mempool = Arc::new(Mempool::new());
+            let mut sequencer = Sequencer::new(mempool.clone());
+            sequencer.processing_delay = Duration::ZERO;
+            sequencer.l1_gas_oracle = Box::new(ConstantGasPrice::new(U256::from(l1_price), U256::from(7u64)));
+
+            let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::ONE), 0)
+                .with_max_fee(U256::from(100u64));
+            let tx_hash = tx.get_hash();
+            mempool.submit_transaction(tx);
+            sequencer.process_transactions();
+
+            let blocks = sequencer.blocks.lock().unwrap();
+            assert_eq!(blocks[0].header.l1_gas_price, U256::from(l1_price));
+            assert_eq!(blocks[0].header.l1_data_gas_price, U256::from(7u64));
+            drop(blocks);
+            fees.push(sequencer.get_receipt(&tx_hash).unwrap().actual_fee);
+        }
+
+        assert!(fees[1] > fees[0]);
+    }
+
+    /// Produces two blocks (one transfer each) and returns the sequencer and its core contract
+    fn two_blocks() -> (Arc<Mutex<Sequencer>>, Arc<Mutex<StarknetCoreContract>>) {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+        let core_contract = sequencer.core_contract.clone();
+
+        for nonce in 0..2 {
+            let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::ONE), nonce);
+            mempool.submit_transaction(tx);
+            sequencer.process_transactions();
+        }
+        (Arc::new(Mutex::new(sequencer)), core_contract)
+    }
+
+    #[test]
+    fn test_proven_blocks_are_settled_on_l1_in_order() {
+        let (sequencer, core_contract) = two_blocks();
+        let mut prover = Prover::new(sequencer.clone());
+        prover.proving_delay = Duration::ZERO;
+        let mut verifier = Verifier::new(sequencer.clone(), core_contract.clone());
+        verifier.verification_delay = Duration::ZERO;
+
+        prover.verify_proof().unwrap();
+        verifier.verify_proofs();
+
+        let sequencer = sequencer.lock().unwrap();
+        let core_contract = core_contract.lock().unwrap();
+        assert_eq!(core_contract.block_number, 2);
+        assert_eq!(core_contract.state_root, sequencer.state_root);
+        let logged: Vec<u64> = core_contract.state_updates.iter().map(|u| u.block_number).collect();
+        assert_eq!(logged, vec![1, 2]);
+        assert_eq!(core_contract.state_updates[1].prev_state_root, core_contract.state_updates[0].new_state_root);
+        assert!(sequencer.blocks.lock().unwrap().iter().all(|b| b.header.block_status == BlockStatus::AcceptedOnL1));
+    }
+
+    #[test]
+    fn test_update_must_chain_from_the_previous_root() {
+        let (sequencer, core_contract) = two_blocks();
+        let prover = Prover::new(sequencer.clone());
+        let sequencer = sequencer.lock().unwrap();
+        let blocks = sequencer.blocks.lock().unwrap();
+        let mut core_contract = core_contract.lock().unwrap();
+
+        // Block #2 cannot be accepted before block #1
+        let proof_2 = prover.generate_proof(&blocks[1]);
+        assert_eq!(
+            core_contract.update_state(&proof_2, &blocks[1].state_diff),
+            Err(L1Error::BlockNumberMismatch { expected: 1, got: 2 })
+        );
+
+        // A block #1 claiming to start from another root is refused
+        let mut forked = blocks[0].clone();
+        forked.header.parent_state_root = "forked_root".to_string();
+        let forked_proof = prover.generate_proof(&forked);
+        assert!(matches!(
+            core_contract.update_state(&forked_proof, &forked.state_diff),
+            Err(L1Error::StateRootMismatch { .. })
+        ));
+        assert!(core_contract.state_updates.is_empty());
+    }
+
+    #[test]
+    fn test_tampered_proof_or_diff_is_rejected() {
+        let (sequencer, core_contract) = two_blocks();
+        let prover = Prover::new(sequencer.clone());
+        let sequencer = sequencer.lock().unwrap();
+        let blocks = sequencer.blocks.lock().unwrap();
+        let mut core_contract = core_contract.lock().unwrap();
+
+        let mut proof = prover.generate_proof(&blocks[0]);
+        proof.new_state_root = "attacker_root".to_string();
+        assert_eq!(
+            core_contract.update_state(&proof, &blocks[0].state_diff),
+            Err(L1Error::InvalidProof { block_number: 1 })
+        );
+
+        let proof = prover.generate_proof(&blocks[0]);
+        assert_eq!(
+            core_contract.update_state(&proof, &blocks[1].state_diff),
+            Err(L1Error::StateDiffMismatch { block_number: 1 })
+        );
+
+        assert!(core_contract.update_state(&proof, &blocks[0].state_diff).is_ok());
+    }
+
+    #[test]
+    fn test_new_state_root_must_follow_from_the_diff() {
+        let (sequencer, core_contract) = two_blocks();
+        let prover = Prover::new(sequencer.clone());
+        let sequencer = sequencer.lock().unwrap();
+        let blocks = sequencer.blocks.lock().unwrap();
+        let mut core_contract = core_contract.lock().unwrap();
+
+        // A consistently proven block claiming a root its diff does not lead to
+        let mut forged = blocks[0].clone();
+        forged.header.state_root = "attacker_root".to_string();
+        let forged_proof = prover.generate_proof(&forged);
+        assert!(matches!(
+            core_contract.update_state(&forged_proof, &forged.state_diff),
+            Err(L1Error::NewStateRootMismatch { block_number: 1, .. })
+        ));
+        assert_eq!(core_contract.block_number, 0);
+
+        let proof = prover.generate_proof(&blocks[0]);
+        assert!(core_contract.update_state(&proof, &blocks[0].state_diff).is_ok());
+        assert_eq!(core_contract.state_root, blocks[0].header.state_root);
+        assert_eq!(core_contract.state.state_root(), blocks[0].header.state_root);
+    }
+
+    /// Credits deposits from L1 to an account in its own storage
+    struct DepositCounter;
+
+    impl Contract for DepositCounter {
+        fn class_hash(&self) -> String {
+            "deposit_counter_class".to_string()
+        }
+
+        fn entry_point_type(&self, selector: &str) -> Option<EntryPointType> {
+            match selector {
+                "handle_deposit" => Some(EntryPointType::L1Handler),
+                "get_deposits" => Some(EntryPointType::External),
+                _ => None,
+            }
+        }
+
+        fn execute(&self, selector: &str, calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+            let account = calldata.get(1).ok_or("missing account")?;
+            match selector {
+                "handle_deposit" => {
+                    if calldata[0] != "l1_bridge" {
+                        return Err("unknown L1 sender".to_string());
+                    }
+                    let amount = parse_felt(calldata, 2)?;
+                    let total = ctx.storage_read(account).checked_add(amount).ok_or("overflow")?;
+                    ctx.storage_write(account, total);
+                    Ok(vec![])
+                }
+                _ => Ok(vec![ctx.storage_read(account).to_string()]),
+            }
+        }
+    }
+
+    fn messaging_sequencer() -> Sequencer {
+        let mut sequencer = Sequencer::new(Arc::new(Mempool::new()));
+        sequencer.processing_delay = Duration::ZERO;
+        sequencer.deploy_contract("counter", Arc::new(DepositCounter));
+        sequencer
+    }
+
+    fn deposit(sequencer: &Sequencer, selector: &str, amount: u64) -> L1ToL2Message {
+        sequencer.core_contract.lock().unwrap().send_message_to_l2(
+            "l1_bridge",
+            "counter",
+            selector,
+            vec!["Alice".to_string(), amount.to_string()],
+            U256::from(100u64),
+        )
+    }
+
+    #[test]
+    fn test_l1_message_is_handled_as_l1_handler_tx_and_consumed_on_settlement() {
+        let mut sequencer = messaging_sequencer();
+        let first = deposit(&sequencer, "handle_deposit", 5);
+        let second = deposit(&sequencer, "handle_deposit", 7);
+        assert_eq!((first.nonce, second.nonce), (0, 1));
+        assert_ne!(first.hash(), second.hash());
+
+        sequencer.process_transactions();
+
+        {
+            let blocks = sequencer.blocks.lock().unwrap();
+            let block = &blocks[0];
+            assert_eq!(block.transactions.len(), 2);
+            assert!(block.transactions.iter().all(|tx| tx.tx_type == TransactionType::L1Handler));
+            assert_eq!(block.transactions[1].nonce, 1);
+            assert_eq!(block.transactions[1].calldata, vec!["l1_bridge", "Alice", "7"]);
+            assert_eq!(block.l1_to_l2_messages, vec![first.hash(), second.hash()]);
+            assert_eq!(block.state_diff.storage_updates["counter"]["Alice"], U256::from(12u64));
+        }
+        assert_eq!(sequencer.mempool.storage.lock().unwrap()["counter"]["Alice"], U256::from(12u64));
+
+        // Still pending on L1 until the block is settled
+        let core_contract = sequencer.core_contract.clone();
+        assert_eq!(core_contract.lock().unwrap().l1_to_l2_message_status(&first.hash()), Some(L1ToL2MessageStatus::Pending));
+
+        let sequencer = Arc::new(Mutex::new(sequencer));
+        let mut prover = Prover::new(sequencer.clone());
+        prover.proving_delay = Duration::ZERO;
+        let mut verifier = Verifier::new(sequencer.clone(), core_contract.clone());
+        verifier.verification_delay = Duration::ZERO;
+        prover.verify_proof().unwrap();
+        verifier.verify_proofs();
+
+        let core_contract = core_contract.lock().unwrap();
+        assert_eq!(core_contract.block_number, 1);
+        assert_eq!(core_contract.l1_to_l2_message_status(&first.hash()), Some(L1ToL2MessageStatus::Consumed));
+        assert_eq!(core_contract.l1_to_l2_message_status(&second.hash()), Some(L1ToL2MessageStatus::Consumed));
+    }
+
+    #[test]
+    fn test_failed_l1_handler_is_not_included_and_message_can_be_cancelled() {
+        let mut sequencer = messaging_sequencer();
+        // An external entry point cannot be reached from L1
+        let message = deposit(&sequencer, "get_deposits", 5);
+
+        sequencer.process_transactions();
+        assert!(sequencer.blocks.lock().unwrap().is_empty());
+        assert!(sequencer.mempool.storage.lock().unwrap().get("counter").is_none());
+
+        let mut core_contract = sequencer.core_contract.lock().unwrap();
+        assert_eq!(core_contract.l1_to_l2_message_status(&message.hash()), Some(L1ToL2MessageStatus::Pending));
+        assert!(core_contract.has_pending_messages());
+
+        assert_eq!(
+            core_contract.cancel_l1_to_l2_message(&message, "l1_bridge"),
+            Err(L1Error::CancellationNotRequested { message_hash: message.hash() })
+        );
+        assert_eq!(
+            core_contract.start_l1_to_l2_message_cancellation(&message, "Mallory"),
+            Err(L1Error::NotMessageSender { message_hash: message.hash() })
+        );
+        core_contract.start_l1_to_l2_message_cancellation(&message, "l1_bridge").unwrap();
+        assert_eq!(
+            core_contract.cancel_l1_to_l2_message(&message, "l1_bridge"),
+            Err(L1Error::CancellationTooEarly { message_hash: message.hash() })
+        );
+
+        core_contract.message_cancellation_delay = Duration::ZERO;
+        core_contract.cancel_l1_to_l2_message(&message, "l1_bridge").unwrap();
+        assert_eq!(core_contract.l1_to_l2_message_status(&message.hash()), Some(L1ToL2MessageStatus::Cancelled));
+    }
+
+    #[test]
+    fn test_message_whose_handler_failed_is_delivered_again() {
+        let mut sequencer = Sequencer::new(Arc::new(Mempool::new()));
+        sequencer.processing_delay = Duration::ZERO;
+        // Nothing is deployed at the recipient yet
+        let first = deposit(&sequencer, "handle_deposit", 5);
+        let second = deposit(&sequencer, "handle_deposit", 7);
+        sequencer.process_transactions();
+        assert!(sequencer.blocks.lock().unwrap().is_empty());
+
+        sequencer.deploy_contract("counter", Arc::new(DepositCounter));
+        sequencer.process_transactions();
+        let blocks = sequencer.blocks.lock().unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].l1_to_l2_messages, vec![first.hash(), second.hash()]);
+        assert_eq!(blocks[0].state_diff.storage_updates["counter"]["Alice"], U256::from(12u64));
+        assert!(!sequencer.core_contract.lock().unwrap().has_pending_messages());
+    }
+
+    #[test]
+    fn test_cancelled_message_is_not_delivered() {
+        let mut sequencer = messaging_sequencer();
+        let message = deposit(&sequencer, "handle_deposit", 5);
+        {
+            let mut core_contract = sequencer.core_contract.lock().unwrap();
+            core_contract.message_cancellation_delay = Duration::ZERO;
+            core_contract.start_l1_to_l2_message_cancellation(&message, "l1_bridge").unwrap();
+            core_contract.cancel_l1_to_l2_message(&message, "l1_bridge").unwrap();
+        }
+
+        sequencer.process_transactions();
+        assert!(sequencer.blocks.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_l1_rejects_blocks_consuming_unknown_messages() {
+        let mut sequencer = messaging_sequencer();
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::ONE), 0);
+        sequencer.mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+        assert_eq!(sequencer.blocks.lock().unwrap()[0].transactions[0].status, TransactionStatus::Succeeded);
+
+        let mut forged = sequencer.blocks.lock().unwrap()[0].clone();
+        forged.l1_to_l2_messages.push("never_sent".to_string());
+        let proof = Prover::new(Arc::new(Mutex::new(Sequencer::new(Arc::new(Mempool::new()))))).generate_proof(&forged);
+
+        let mut core_contract = sequencer.core_contract.lock().unwrap();
+        assert_eq!(
+            core_contract.update_state(&proof, &forged.state_diff),
+            Err(L1Error::MessageNotFound { message_hash: "never_sent".to_string() })
+        );
+        assert_eq!(core_contract.block_number, 0);
+    }
+
+    /// Sends `[caller, amount]` to an L1 recipient
+    struct Withdrawer;
+
+    impl Contract for Withdrawer {
+        fn class_hash(&self) -> String {
+            "withdrawer_class".to_string()
+        }
+
+        fn entry_point_type(&self, selector: &str) -> Option<EntryPointType> {
+            (selector == "withdraw").then_some(EntryPointType::External)
+        }
+
+        fn execute(&self, _selector: &str, calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+            let recipient = calldata.first().ok_or("missing recipient")?.clone();
+            let amount = parse_felt(calldata, 1)?;
+            if amount.is_zero() {
+                return Err("nothing to withdraw".to_string());
+            }
+            let caller = ctx.caller_address.clone();
+            ctx.send_message_to_l1(&recipient, vec![caller, amount.to_string()]);
+            Ok(vec![])
+        }
+    }
+
+    fn withdraw(nonce: u64, amount: u64) -> Transaction {
+        Transaction::new("Alice".to_string(), TransactionType::Invoke, None, None, None, nonce)
+            .with_call("withdrawer", "withdraw", vec!["l1_recipient".to_string(), amount.to_string()])
+            .with_max_fee(U256::from(50u64))
+    }
+
+    #[test]
+    fn test_l2_to_l1_message_is_consumable_once_after_acceptance_on_l1() {
+        let mut sequencer = Sequencer::new(Arc::new(Mempool::new()));
+        sequencer.processing_delay = Duration::ZERO;
+        sequencer.deploy_contract("withdrawer", Arc::new(Withdrawer));
+        let core_contract = sequencer.core_contract.clone();
+
+        let tx = withdraw(0, 30);
+        let tx_hash = tx.get_hash();
+        sequencer.mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let payload = vec!["Alice".to_string(), "30".to_string()];
+        let receipt = sequencer.get_receipt(&tx_hash).unwrap();
+        assert_eq!(receipt.messages_sent.len(), 1);
+        assert_eq!(receipt.messages_sent[0].from_address, "withdrawer");
+        assert_eq!(receipt.messages_sent[0].to_address, "l1_recipient");
+        assert_eq!(receipt.messages_sent[0].payload, payload);
+        let message_hash = receipt.messages_sent[0].hash();
+
+        // Nothing can be consumed before the block is accepted on L1
+        assert!(matches!(
+            core_contract.lock().unwrap().consume_message_from_l2("l1_recipient", "withdrawer", &payload),
+            Err(L1Error::L2ToL1MessageNotFound { .. })
+        ));
+
+        let sequencer = Arc::new(Mutex::new(sequencer));
+        let mut prover = Prover::new(sequencer.clone());
+        prover.proving_delay = Duration::ZERO;
+        prover.verify_proof().unwrap();
+        assert_eq!(core_contract.lock().unwrap().l2_to_l1_message_count(&message_hash), 0);
+
+        let mut verifier = Verifier::new(sequencer.clone(), core_contract.clone());
+        verifier.verification_delay = Duration::ZERO;
+        verifier.verify_proofs();
+
+        let mut core_contract = core_contract.lock().unwrap();
+        assert_eq!(core_contract.state_updates[0].l2_to_l1_messages, vec![message_hash.clone()]);
+        // Only the recipient can consume it
+        assert!(core_contract.consume_message_from_l2("Mallory", "withdrawer", &payload).is_err());
+        assert_eq!(core_contract.consume_message_from_l2("l1_recipient", "withdrawer", &payload), Ok(message_hash.clone()));
+        assert_eq!(
+            core_contract.consume_message_from_l2("l1_recipient", "withdrawer", &payload),
+            Err(L1Error::L2ToL1MessageNotFound { message_hash })
+        );
+    }
+
+    #[test]
+    fn test_reverted_tx_sends_no_messages_and_commitment_binds_them() {
+        let mut sequencer = Sequencer::new(Arc::new(Mempool::new()));
+        sequencer.processing_delay = Duration::ZERO;
+        sequencer.deploy_contract("withdrawer", Arc::new(Withdrawer));
+
+        let reverted = withdraw(0, 0);
+        let reverted_hash = reverted.get_hash();
+        sequencer.mempool.submit_transaction(reverted);
+        sequencer.mempool.submit_transaction(withdraw(1, 5));
+        sequencer.process_transactions();
+
+        let receipt = sequencer.get_receipt(&reverted_hash).unwrap();
+        assert_eq!(receipt.status, TransactionStatus::Reverted);
+        assert!(receipt.messages_sent.is_empty());
+
+        let blocks = sequencer.blocks.lock().unwrap();
+        assert_eq!(blocks[0].l2_to_l1_messages().len(), 1);
+        let mut tampered = blocks[0].clone();
+        tampered.receipts[1].messages_sent[0].payload[1] = "500".to_string();
+        let recomputed = L2Block::new(
+            1,
+            tampered.header.parent_block_hash.clone(),
+            tampered.header.sequencer_address.clone(),
+            tampered.transactions.clone(),
+            tampered.receipts.clone(),
+            Default::default(),
+        );
+        assert_ne!(recomputed.header.message_commitment, blocks[0].header.message_commitment);
+    }
+}
@@ -1,16 +1,22 @@
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
+    use std::time::Duration;
+    use starknet_simulator::gas::{GasPrices, ResourceBounds, ResourceBoundsMapping};
     use starknet_simulator::mempool::Mempool;
+    use starknet_simulator::sequencer::Sequencer;
     use starknet_simulator::transaction::{Transaction, TransactionType, TransactionStatus};
+    use starknet_simulator::utils::U256;
+
+    const BLOCK_TIMESTAMP: u64 = 1_700_000_000;
 
     #[test]
     fn test_valid_transaction_passes() {
         let mempool = Arc::new(Mempool::new());
-        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(10), 0);
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 0);
         
         mempool.submit_transaction(tx.clone());
-        let validated_tx = mempool.validate_transaction();
+        let validated_tx = mempool.validate_transaction(BLOCK_TIMESTAMP);
 
         assert!(validated_tx.is_some());
         assert_eq!(validated_tx.as_ref().unwrap().status, TransactionStatus::Validated);
@@ -19,11 +25,11 @@ mod tests {
     #[test]
     fn test_transaction_rejected_due_to_insufficient_funds() {
         let mempool = Arc::new(Mempool::new());
-        let tx = Transaction::new("Mark".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(50), 0);  
+        let tx = Transaction::new("Mark".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(50u64)), 0);  
         // Mark has 0 balance
 
         mempool.submit_transaction(tx.clone());
-        let validated_tx = mempool.validate_transaction();
+        let validated_tx = mempool.validate_transaction(BLOCK_TIMESTAMP);
 
         assert!(validated_tx.is_none());
     }
@@ -31,11 +37,12 @@ mod tests {
     #[test]
     fn test_transaction_rejected_due_to_insufficient_funds_for_fee() {
         let mempool = Arc::new(Mempool::new());
-        let tx = Transaction::new("Mike".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(90), 0);  
-        // Mike has 90, but he needs 90 + 5 (fee) = 95
+        let tx = Transaction::new("Mike".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 0)
+            .with_max_fee(U256::from(90u64));
+        // Mike has 90, which does not cover a max fee of 90
 
         mempool.submit_transaction(tx.clone());
-        let validated_tx = mempool.validate_transaction();
+        let validated_tx = mempool.validate_transaction(BLOCK_TIMESTAMP);
 
         assert!(validated_tx.is_none());
     }
@@ -44,51 +51,68 @@ mod tests {
     fn test_transaction_rejected_due_to_incorrect_nonce() {
         let mempool = Arc::new(Mempool::new());
 
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+
+        // A nonce ahead of the sender's is rejected
+        let early = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 1);
+        mempool.submit_transaction(early);
+        assert!(mempool.validate_transaction(BLOCK_TIMESTAMP).is_none());
+
         // First transaction should succeed (nonce = 0)
-        let tx1 = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(10), 0);
+        let tx1 = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 0);
         mempool.submit_transaction(tx1.clone());
-        let validated_tx1 = mempool.validate_transaction();
-        assert!(validated_tx1.is_some());
+        sequencer.process_transactions();
+        assert_eq!(sequencer.get_receipt(&tx1.get_hash()).unwrap().status, TransactionStatus::Succeeded);
 
         // Second transaction with incorrect nonce (should be 1)
-        let tx2 = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(10), 0);
+        let tx2 = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 0);
         mempool.submit_transaction(tx2.clone());
-        let validated_tx2 = mempool.validate_transaction();
+        let validated_tx2 = mempool.validate_transaction(BLOCK_TIMESTAMP);
         assert!(validated_tx2.is_none());
     }
 
     #[test]
-    fn test_fee_is_deducted_only_if_validated() {
+    fn test_validation_charges_no_fee() {
         let mempool = Arc::new(Mempool::new());
 
         // Alice starts with 200 tokens
-        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(10), 0);
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 0);
         
         // Capture balance before transaction validation
         let balances_before = mempool.balances.lock().unwrap().clone();
         let initial_balance = balances_before.get("Alice").unwrap();
 
         mempool.submit_transaction(tx.clone());
-        let validated_tx = mempool.validate_transaction();
+        let validated_tx = mempool.validate_transaction(BLOCK_TIMESTAMP);
         assert!(validated_tx.is_some());
 
         // Capture balance after transaction validation
         let balances_after = mempool.balances.lock().unwrap().clone();
         let updated_balance = balances_after.get("Alice").unwrap();
 
-        // Check that the fee was deducted
-        assert_eq!(*updated_balance, initial_balance - tx.fee);
+        // The fee is only charged after execution
+        assert_eq!(updated_balance, initial_balance);
     }
 
     #[test]
     fn test_nonce_is_incremented_correctly() {
         let mempool = Arc::new(Mempool::new());
 
-        let tx1 = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(10), 0);
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+
+        let tx1 = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 0);
         mempool.submit_transaction(tx1.clone());
-        let validated_tx1 = mempool.validate_transaction();
+        let validated_tx1 = mempool.validate_transaction(BLOCK_TIMESTAMP);
         assert!(validated_tx1.is_some());
 
+        // Passing the mempool's checks does not use the nonce up: the sequencer consumes it
+        // once the transaction passes its `__validate__`
+        assert_eq!(mempool.nonces.lock().unwrap()["Alice"], 0);
+        mempool.submit_transaction(tx1.clone());
+        sequencer.process_transactions();
+
         // Fetch the updated nonce
         let nonces_after = mempool.nonces.lock().unwrap().clone();
         let updated_nonce = nonces_after.get("Alice").unwrap();
@@ -96,4 +120,77 @@ mod tests {
         // Check that nonce increased to 1
         assert_eq!(*updated_nonce, 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_transfer_amount_is_left_to_execution() {
+        let mempool = Arc::new(Mempool::new());
+
+        // Alice cannot send 500, but she can pay the fee, so the transfer reverts instead
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(500u64)), 0);
+        mempool.submit_transaction(tx.clone());
+
+        assert!(mempool.validate_transaction(BLOCK_TIMESTAMP).is_some());
+        assert_eq!(*mempool.balances.lock().unwrap().get("Alice").unwrap(), U256::from(200u64));
+    }
+
+    #[test]
+    fn test_transaction_rejected_when_max_fee_below_estimate() {
+        let mempool = Arc::new(Mempool::new());
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 0)
+            .with_max_fee(U256::ONE);
+
+        mempool.submit_transaction(tx);
+
+        assert!(mempool.validate_transaction(BLOCK_TIMESTAMP).is_none());
+        assert_eq!(*mempool.balances.lock().unwrap().get("Alice").unwrap(), U256::from(200u64));
+    }
+
+    #[test]
+    fn test_transaction_rejected_when_resource_price_below_current_price() {
+        let mempool = Arc::new(Mempool::new());
+        mempool.gas_prices.lock().unwrap().l2_gas_price = U256::from(3u64);
+
+        let bounds = ResourceBoundsMapping {
+            l1_gas: ResourceBounds { max_amount: 20, max_price_per_unit: U256::from(3u64) },
+            l1_data_gas: ResourceBounds { max_amount: 20, max_price_per_unit: U256::from(3u64) },
+            l2_gas: ResourceBounds { max_amount: 20, max_price_per_unit: U256::from(2u64) },
+        };
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 0)
+            .with_resource_bounds(bounds);
+        mempool.submit_transaction(tx);
+
+        assert!(mempool.validate_transaction(BLOCK_TIMESTAMP).is_none());
+    }
+
+    #[test]
+    fn test_transaction_rejected_when_l1_data_gas_bound_below_estimate() {
+        let mempool = Arc::new(Mempool::new());
+
+        let bounds = ResourceBoundsMapping {
+            l1_gas: ResourceBounds { max_amount: 20, max_price_per_unit: U256::from(3u64) },
+            l1_data_gas: ResourceBounds { max_amount: 1, max_price_per_unit: U256::from(3u64) },
+            l2_gas: ResourceBounds { max_amount: 20, max_price_per_unit: U256::from(3u64) },
+        };
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Declare, None, Some("Contract".to_string()), None, 0)
+            .with_resource_bounds(bounds);
+        assert!(tx.check_fee_bounds(&GasPrices::default()).is_err());
+        mempool.submit_transaction(tx);
+
+        assert!(mempool.validate_transaction(BLOCK_TIMESTAMP).is_none());
+    }
+
+    #[test]
+    fn test_higher_tip_is_validated_first() {
+        let mempool = Arc::new(Mempool::new());
+        let low = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::ONE), 0);
+        let high = Transaction::new("Bob".to_string(), TransactionType::Invoke, Some("Alice".to_string()), None, Some(U256::ONE), 0)
+            .with_max_fee(U256::from(100u64))
+            .with_tip(U256::from(5u64));
+
+        mempool.submit_transaction(low);
+        mempool.submit_transaction(high);
+
+        assert_eq!(mempool.validate_transaction(BLOCK_TIMESTAMP).unwrap().sender, "Bob");
+        assert_eq!(mempool.validate_transaction(BLOCK_TIMESTAMP).unwrap().sender, "Alice");
+    }
+}
@@ -0,0 +1,137 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use starknet_simulator::block::BlockStatus;
+    use starknet_simulator::mempool::Mempool;
+    use starknet_simulator::pipeline::{Pipeline, PipelineConfig, PipelineStats};
+    use starknet_simulator::prover::Prover;
+    use starknet_simulator::sequencer::Sequencer;
+    use starknet_simulator::transaction::{Transaction, TransactionType};
+    use starknet_simulator::utils::U256;
+    use starknet_simulator::verifier::Verifier;
+
+    fn start(proving_delay: Duration) -> (Arc<Mutex<Sequencer>>, Pipeline) {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = Sequencer::new(mempool);
+        sequencer.processing_delay = Duration::ZERO;
+        sequencer.block_policy.block_time = Duration::from_millis(20);
+        let core_contract = sequencer.core_contract.clone();
+        let sequencer = Arc::new(Mutex::new(sequencer));
+        let mut prover = Prover::new(sequencer.clone());
+        prover.proving_delay = proving_delay;
+        let mut verifier = Verifier::new(sequencer.clone(), core_contract);
+        verifier.verification_delay = Duration::ZERO;
+        let config = PipelineConfig { poll_interval: Duration::from_millis(10) };
+        let pipeline = Pipeline::start(sequencer.clone(), prover, verifier, config);
+        (sequencer, pipeline)
+    }
+
+    fn transfer(sender: &str, nonce: u64) -> Transaction {
+        Transaction::new(sender.to_string(), TransactionType::Invoke, Some("Mark".to_string()), None, Some(U256::ONE), nonce)
+            .with_max_fee(U256::from(20u64))
+    }
+
+    fn wait_for(pipeline: &Pipeline, done: impl Fn(&PipelineStats) -> bool) -> PipelineStats {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let stats = pipeline.stats();
+            if done(&stats) || Instant::now() > deadline {
+                return stats;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_concurrent_submissions_are_settled() {
+        let (sequencer, pipeline) = start(Duration::ZERO);
+        let pipeline = Arc::new(pipeline);
+        let submitters: Vec<_> = ["Alice", "Bob", "Cyndie"]
+            .into_iter()
+            .map(|sender| {
+                let pipeline = pipeline.clone();
+                thread::spawn(move || {
+                    for nonce in 0..8 {
+                        pipeline.submit(transfer(sender, nonce));
+                        thread::sleep(Duration::from_millis(2));
+                    }
+                })
+            })
+            .collect();
+        for submitter in submitters {
+            submitter.join().unwrap();
+        }
+
+        let stats = wait_for(&pipeline, |s| s.transactions_settled == 24);
+        assert_eq!(stats.submitted, 24);
+        assert_eq!((stats.in_channel, stats.in_mempool), (0, 0));
+        assert_eq!(stats.transactions_included, 24);
+        assert_eq!(stats.blocks_settled, stats.blocks_produced);
+
+        let stats = Arc::into_inner(pipeline).unwrap().shutdown();
+        assert_eq!(stats.blocks_rejected, 0);
+        let sequencer = sequencer.lock().unwrap();
+        let core_contract = sequencer.core_contract.lock().unwrap();
+        assert_eq!(core_contract.state_root, sequencer.state_root);
+        assert_eq!(core_contract.block_number, stats.blocks_produced as u64);
+    }
+
+    #[test]
+    fn test_submission_keeps_flowing_while_a_block_is_proven() {
+        let (_, pipeline) = start(Duration::from_millis(500));
+        pipeline.submit(transfer("Alice", 0));
+        let stats = wait_for(&pipeline, |s| s.blocks_produced == 1 && s.awaiting_proof == 1);
+        assert_eq!(stats.blocks_produced, 1);
+
+        // The prover is busy with block #1; the sequencer keeps building blocks meanwhile
+        let started = Instant::now();
+        for nonce in 0..10 {
+            pipeline.submit(transfer("Bob", nonce));
+        }
+        assert!(started.elapsed() < Duration::from_millis(100));
+        let stats = wait_for(&pipeline, |s| s.transactions_included == 11);
+        assert_eq!(stats.transactions_included, 11);
+        assert_eq!(stats.blocks_settled, 0);
+
+        let stats = pipeline.shutdown();
+        assert_eq!(stats.transactions_settled, 11);
+    }
+
+    #[test]
+    fn test_rejected_proof_is_reproven_and_settled() {
+        let (sequencer, pipeline) = start(Duration::from_millis(200));
+        let (blocks, core_contract) = {
+            let sequencer = sequencer.lock().unwrap();
+            (sequencer.blocks.clone(), sequencer.core_contract.clone())
+        };
+        pipeline.submit(transfer("Alice", 0));
+        let stats = wait_for(&pipeline, |s| s.blocks_produced == 1 && s.awaiting_proof == 1);
+        assert_eq!(stats.awaiting_proof, 1);
+
+        {
+            // The verifier reads the core contract before it looks for proven blocks,
+            // so holding it keeps block #1 from being submitted until its proof is tampered with
+            let _l1 = core_contract.lock().unwrap();
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while blocks.lock().unwrap()[0].header.block_status != BlockStatus::Proven && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(5));
+            }
+            let mut blocks = blocks.lock().unwrap();
+            assert_eq!(blocks[0].header.block_status, BlockStatus::Proven);
+            blocks[0].proof.as_mut().unwrap().new_state_root = "0xbad".to_string();
+        }
+
+        let stats = wait_for(&pipeline, |s| s.blocks_settled == 1);
+        assert_eq!(stats.blocks_settled, 1);
+        assert_eq!(stats.blocks_rejected, 0);
+
+        let stats = pipeline.shutdown();
+        assert_eq!(stats.transactions_settled, 1);
+        let sequencer = sequencer.lock().unwrap();
+        let core_contract = sequencer.core_contract.lock().unwrap();
+        assert_eq!(core_contract.block_number, 1);
+        assert_eq!(core_contract.state_root, sequencer.state_root);
+    }
+}
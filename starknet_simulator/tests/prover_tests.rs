@@ -0,0 +1,144 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use starknet_simulator::block::BlockStatus;
+    use starknet_simulator::mempool::Mempool;
+    use starknet_simulator::prover::Prover;
+    use starknet_simulator::sequencer::Sequencer;
+    use starknet_simulator::state::StateDiff;
+    use starknet_simulator::transaction::{Transaction, TransactionType};
+    use starknet_simulator::utils::U256;
+    use starknet_simulator::verifier::Verifier;
+
+    /// One Alice→Bob transfer per block
+    fn blocks(count: u64) -> Arc<Mutex<Sequencer>> {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+        for nonce in 0..count {
+            let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::ONE), nonce);
+            mempool.submit_transaction(tx);
+            sequencer.process_transactions();
+        }
+        Arc::new(Mutex::new(sequencer))
+    }
+
+    fn prover(sequencer: &Arc<Mutex<Sequencer>>, max_blocks_per_job: usize, max_wait: Duration) -> Prover {
+        let mut prover = Prover::new(sequencer.clone());
+        prover.proving_delay = Duration::ZERO;
+        prover.max_blocks_per_job = max_blocks_per_job;
+        prover.max_wait = max_wait;
+        prover
+    }
+
+    #[test]
+    fn test_one_proof_and_one_l1_update_for_a_batch() {
+        let sequencer = blocks(3);
+        let prover = prover(&sequencer, 3, Duration::from_secs(3600));
+        prover.verify_proof().unwrap();
+
+        let jobs = prover.jobs.lock().unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!((jobs[0].first_block_number, jobs[0].last_block_number, jobs[0].transaction_count), (1, 3, 3));
+        {
+            let sequencer = sequencer.lock().unwrap();
+            let blocks = sequencer.blocks.lock().unwrap();
+            let proof = blocks[0].proof.clone().unwrap();
+            assert_eq!((proof.first_block_number, proof.block_number), (1, 3));
+            assert!(blocks.iter().all(|b| b.proof.as_ref() == Some(&proof)));
+        }
+
+        let core_contract = sequencer.lock().unwrap().core_contract.clone();
+        let mut verifier = Verifier::new(sequencer.clone(), core_contract.clone());
+        verifier.verification_delay = Duration::ZERO;
+        verifier.verify_proofs();
+
+        let core_contract = core_contract.lock().unwrap();
+        assert_eq!(core_contract.state_updates.len(), 1);
+        assert_eq!((core_contract.state_updates[0].first_block_number, core_contract.block_number), (1, 3));
+        assert_eq!(core_contract.state_root, sequencer.lock().unwrap().state_root);
+        // The squashed diff publishes Alice's final nonce once
+        assert_eq!(core_contract.state_updates[0].state_diff.nonce_updates["Alice"], 3);
+        assert_eq!(verifier.da_costs.lock().unwrap().len(), 1);
+        let sequencer = sequencer.lock().unwrap();
+        assert!(sequencer.blocks.lock().unwrap().iter().all(|b| b.header.block_status == BlockStatus::AcceptedOnL1));
+    }
+
+    #[test]
+    fn test_aborted_job_leaves_no_block_half_proven() {
+        let sequencer = blocks(2);
+        let mut prover = prover(&sequencer, 2, Duration::ZERO);
+        prover.proving_delay = Duration::from_millis(200);
+        let chain = prover.blocks.clone();
+
+        let job = thread::spawn(move || prover.verify_proof());
+        while chain.lock().unwrap()[1].header.block_status != BlockStatus::ProofRequested {
+            thread::sleep(Duration::from_millis(5));
+        }
+        // Block #2 is moved on while the job is running
+        chain.lock().unwrap()[1].transition(BlockStatus::Rejected { reason: "moved".to_string() }).unwrap();
+        assert!(job.join().unwrap().is_err());
+
+        let chain = chain.lock().unwrap();
+        assert_eq!(chain[0].header.block_status, BlockStatus::AcceptedOnL2);
+        assert!(chain[0].proof.is_none());
+        assert_eq!(chain[1].header.block_status, BlockStatus::Rejected { reason: "moved".to_string() });
+    }
+
+    #[test]
+    fn test_partial_batch_waits_for_the_time_window() {
+        let sequencer = blocks(2);
+        let waiting = prover(&sequencer, 5, Duration::from_secs(3600));
+        waiting.verify_proof().unwrap();
+        assert!(waiting.jobs.lock().unwrap().is_empty());
+        assert!(sequencer.lock().unwrap().blocks.lock().unwrap().iter().all(|b| b.proof.is_none()));
+
+        // Once the window has passed, whatever is waiting is proven together
+        let expired = prover(&sequencer, 5, Duration::ZERO);
+        expired.verify_proof().unwrap();
+        let jobs = expired.jobs.lock().unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!((jobs[0].first_block_number, jobs[0].last_block_number), (1, 2));
+    }
+
+    #[test]
+    fn test_aggregation_amortizes_proving_cost() {
+        let single = blocks(4);
+        let single_prover = prover(&single, 1, Duration::ZERO);
+        single_prover.verify_proof().unwrap();
+
+        let batched = blocks(4);
+        let batched_prover = prover(&batched, 4, Duration::ZERO);
+        batched_prover.verify_proof().unwrap();
+
+        let single_jobs = single_prover.jobs.lock().unwrap();
+        let batched_jobs = batched_prover.jobs.lock().unwrap();
+        assert_eq!((single_jobs.len(), batched_jobs.len()), (4, 1));
+        assert!(batched_jobs[0].cost_per_transaction() < single_jobs[0].cost_per_transaction());
+
+        let single_total = single_jobs.iter().fold(U256::ZERO, |sum, job| sum.checked_add(job.proving_cost).unwrap());
+        assert!(batched_jobs[0].proving_cost < single_total);
+    }
+
+    #[test]
+    fn test_squashed_diff_keeps_final_values() {
+        let mut first = StateDiff::default();
+        first.nonce_updates.insert("Alice".to_string(), 1);
+        first.storage_updates.entry("token".to_string()).or_default().insert("Alice".to_string(), U256::from(5u64));
+        first.deployed_contracts.insert("counter".to_string(), "v1".to_string());
+        let mut second = StateDiff::default();
+        second.nonce_updates.insert("Alice".to_string(), 2);
+        second.storage_updates.entry("token".to_string()).or_default().insert("Alice".to_string(), U256::from(3u64));
+        second.replaced_classes.insert("counter".to_string(), "v2".to_string());
+        second.replaced_classes.insert("bridge".to_string(), "v2".to_string());
+
+        let squashed = StateDiff::squash([&first, &second]);
+        assert_eq!(squashed.nonce_updates["Alice"], 2);
+        assert_eq!(squashed.storage_updates["token"]["Alice"], U256::from(3u64));
+        assert_eq!(squashed.deployed_contracts["counter"], "v2");
+        assert_eq!(squashed.replaced_classes.len(), 1);
+        assert_eq!(squashed.replaced_classes["bridge"], "v2");
+    }
+}
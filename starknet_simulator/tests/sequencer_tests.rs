@@ -0,0 +1,449 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use starknet_simulator::contract::{Contract, EntryPointType, ExecutionContext};
+    use starknet_simulator::gas::{ResourceBounds, ResourceBoundsMapping};
+    use starknet_simulator::mempool::Mempool;
+    use starknet_simulator::sequencer::{ExecutionMode, Sequencer};
+    use starknet_simulator::transaction::{PhaseOutcome, Transaction, TransactionType, TransactionStatus};
+    use starknet_simulator::utils::U256;
+
+    fn new_sequencer(mempool: &Arc<Mempool>) -> Sequencer {
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+        sequencer
+    }
+
+    #[test]
+    fn test_transfer_updates_both_balances() {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = new_sequencer(&mempool);
+
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 0);
+        let fee = tx.fee;
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let balances = mempool.balances.lock().unwrap();
+        let expected_alice = U256::from(190u64).checked_sub(fee).unwrap();
+        assert_eq!(*balances.get("Alice").unwrap(), expected_alice);
+        assert_eq!(*balances.get("Bob").unwrap(), U256::from(510u64));
+    }
+
+    #[test]
+    fn test_receiver_overflow_reverts_instead_of_panicking() {
+        let mempool = Arc::new(Mempool::new());
+        mempool.balances.lock().unwrap().insert("Bob".to_string(), U256::MAX);
+        let mut sequencer = new_sequencer(&mempool);
+
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 0);
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let blocks = sequencer.blocks.lock().unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].transactions[0].status, TransactionStatus::Reverted);
+        assert_eq!(*mempool.balances.lock().unwrap().get("Bob").unwrap(), U256::MAX);
+    }
+
+    #[test]
+    fn test_receipt_reports_metered_fee() {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = new_sequencer(&mempool);
+
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 0);
+        let tx_hash = tx.get_hash();
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let receipt = sequencer.get_receipt(&tx_hash).unwrap();
+        assert_eq!(receipt.status, TransactionStatus::Succeeded);
+        assert_eq!(receipt.resources.storage_writes, 3); // nonce + two balances
+        assert!(receipt.gas_consumed.l2_gas > 0);
+        assert_eq!(
+            receipt.actual_fee,
+            mempool.gas_prices.lock().unwrap().fee_for(&receipt.gas_consumed).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_only_the_actual_fee_is_charged() {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = new_sequencer(&mempool);
+
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 0)
+            .with_max_fee(U256::from(100u64));
+        let tx_hash = tx.get_hash();
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let receipt = sequencer.get_receipt(&tx_hash).unwrap();
+        assert!(receipt.actual_fee < U256::from(100u64));
+        let expected_alice = U256::from(190u64).checked_sub(receipt.actual_fee).unwrap();
+        assert_eq!(*mempool.balances.lock().unwrap().get("Alice").unwrap(), expected_alice);
+    }
+
+    #[test]
+    fn test_reverted_transaction_is_charged_once() {
+        let mempool = Arc::new(Mempool::new());
+        mempool.balances.lock().unwrap().insert("Bob".to_string(), U256::MAX);
+        let mut sequencer = new_sequencer(&mempool);
+
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 0);
+        let tx_hash = tx.get_hash();
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let receipt = sequencer.get_receipt(&tx_hash).unwrap();
+        assert_eq!(receipt.status, TransactionStatus::Reverted);
+        assert!(receipt.revert_reason.as_deref().is_some_and(|reason| reason.contains("overflow")));
+        let expected_alice = U256::from(200u64).checked_sub(receipt.actual_fee).unwrap();
+        assert_eq!(*mempool.balances.lock().unwrap().get("Alice").unwrap(), expected_alice);
+    }
+
+    #[test]
+    fn test_transfer_above_the_balance_reverts_and_pays_the_fee_once() {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = new_sequencer(&mempool);
+
+        // Mike has 90: enough for the fee, not for the transfer
+        let tx = Transaction::new("Mike".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(90u64)), 0)
+            .with_max_fee(U256::from(20u64));
+        let tx_hash = tx.get_hash();
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let receipt = sequencer.get_receipt(&tx_hash).unwrap();
+        assert_eq!(receipt.status, TransactionStatus::Reverted);
+        assert_eq!(receipt.phases.validate, PhaseOutcome::Succeeded);
+        assert!(matches!(receipt.phases.execute, PhaseOutcome::Reverted(_)));
+        assert_eq!(receipt.phases.fee_transfer, PhaseOutcome::Succeeded);
+        let balances = mempool.balances.lock().unwrap();
+        assert_eq!(balances["Mike"], U256::from(90u64).checked_sub(receipt.actual_fee).unwrap());
+        assert_eq!(balances["Bob"], U256::from(500u64));
+        assert_eq!(mempool.nonces.lock().unwrap()["Mike"], 1);
+    }
+
+    #[test]
+    fn test_execution_that_leaves_too_little_for_the_fee_reverts() {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = new_sequencer(&mempool);
+
+        // After sending 195 Alice has 5 left, less than the fee
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(195u64)), 0)
+            .with_max_fee(U256::from(20u64));
+        let tx_hash = tx.get_hash();
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let receipt = sequencer.get_receipt(&tx_hash).unwrap();
+        assert_eq!(receipt.status, TransactionStatus::Reverted);
+        assert!(receipt.revert_reason.as_deref().is_some_and(|reason| reason.starts_with("fee transfer failed")));
+        let balances = mempool.balances.lock().unwrap();
+        assert_eq!(balances["Alice"], U256::from(200u64).checked_sub(receipt.actual_fee).unwrap());
+        assert_eq!(balances["Bob"], U256::from(500u64));
+    }
+
+    #[test]
+    fn test_out_of_gas_reverts_and_never_charges_above_the_bounds() {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = new_sequencer(&mempool);
+
+        let bounds = ResourceBoundsMapping {
+            l1_gas: ResourceBounds { max_amount: 50, max_price_per_unit: U256::from(2u64) },
+            l1_data_gas: ResourceBounds { max_amount: 10, max_price_per_unit: U256::from(2u64) },
+            l2_gas: ResourceBounds { max_amount: 1, max_price_per_unit: U256::from(8u64) },
+        };
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 0)
+            .with_resource_bounds(bounds);
+        assert_eq!(tx.version(), 3);
+        let tx_hash = tx.get_hash();
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let receipt = sequencer.get_receipt(&tx_hash).unwrap();
+        assert_eq!(receipt.status, TransactionStatus::Reverted);
+        assert!(receipt.actual_fee <= bounds.max_fee().unwrap());
+
+        let balances = mempool.balances.lock().unwrap();
+        assert_eq!(*balances.get("Bob").unwrap(), U256::from(500u64));
+        let expected_alice = U256::from(200u64).checked_sub(receipt.actual_fee).unwrap();
+        assert_eq!(*balances.get("Alice").unwrap(), expected_alice);
+    }
+
+    #[test]
+    fn test_congested_block_raises_the_next_l2_gas_price() {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = new_sequencer(&mempool);
+
+        for (nonce, sender) in [(0, "Bob"), (0, "Cyndie"), (1, "Bob"), (1, "Cyndie"), (2, "Bob"), (2, "Cyndie")] {
+            let tx = Transaction::new(sender.to_string(), TransactionType::Invoke, Some("Mark".to_string()), None, Some(U256::ONE), nonce);
+            mempool.submit_transaction(tx);
+        }
+        sequencer.process_transactions();
+
+        let blocks = sequencer.blocks.lock().unwrap();
+        let header = &blocks[0].header;
+        assert_eq!(header.l2_gas_price, U256::ONE);
+        assert!(header.l2_gas_used > sequencer.fee_market.target_l2_gas);
+        assert!(mempool.gas_prices.lock().unwrap().l2_gas_price > header.l2_gas_price);
+    }
+
+    #[test]
+    fn test_tip_is_paid_to_the_sequencer() {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = new_sequencer(&mempool);
+
+        let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::from(10u64)), 0)
+            .with_max_fee(U256::from(50u64))
+            .with_tip(U256::from(2u64));
+        let tx_hash = tx.get_hash();
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let receipt = sequencer.get_receipt(&tx_hash).unwrap();
+        let expected_tip = U256::from(receipt.gas_consumed.l2_gas * 2);
+        assert_eq!(receipt.tip, expected_tip);
+        let balances = mempool.balances.lock().unwrap();
+        assert_eq!(*balances.get(&sequencer.sequencer_address).unwrap(), expected_tip);
+    }
+
+    fn submit_invokes(mempool: &Mempool, sender: &str, count: u64) {
+        for nonce in 0..count {
+            let tx = Transaction::new(sender.to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::ONE), nonce)
+                .with_max_fee(U256::from(20u64));
+            mempool.submit_transaction(tx);
+        }
+    }
+
+    #[test]
+    fn test_full_block_carries_leftovers_over() {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = new_sequencer(&mempool);
+        sequencer.block_policy.max_transactions = 2;
+        submit_invokes(&mempool, "Cyndie", 5);
+
+        sequencer.process_transactions();
+        assert_eq!(sequencer.blocks.lock().unwrap()[0].transactions.len(), 2);
+        assert_eq!(mempool.transactions.lock().unwrap().len(), 3);
+
+        sequencer.process_transactions();
+        sequencer.process_transactions();
+        let blocks = sequencer.blocks.lock().unwrap();
+        let sizes: Vec<usize> = blocks.iter().map(|b| b.transactions.len()).collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+        assert!(mempool.transactions.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rejected_transaction_does_not_seal_the_block_early() {
+        for mode in [ExecutionMode::Sequential, ExecutionMode::Parallel { workers: 4 }] {
+            let mempool = Arc::new(Mempool::new());
+            let mut sequencer = new_sequencer(&mempool);
+            sequencer.execution_mode = mode;
+            for (sender, nonce) in [("Alice", 0), ("Bob", 5), ("Cyndie", 0), ("Mike", 0)] {
+                let tx = Transaction::new(sender.to_string(), TransactionType::Invoke, Some("Mark".to_string()), None, Some(U256::ONE), nonce);
+                mempool.submit_transaction(tx);
+            }
+
+            sequencer.process_transactions();
+            let blocks = sequencer.blocks.lock().unwrap();
+            assert_eq!(blocks.len(), 1);
+            let senders: Vec<&str> = blocks[0].transactions.iter().map(|tx| tx.sender.as_str()).collect();
+            assert_eq!(senders, vec!["Alice", "Cyndie", "Mike"], "{:?}", mode);
+            assert!(mempool.transactions.lock().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_l2_gas_limit_closes_the_block() {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = new_sequencer(&mempool);
+        sequencer.block_policy.max_l2_gas = 8;
+        submit_invokes(&mempool, "Cyndie", 3);
+
+        sequencer.process_transactions();
+        let blocks = sequencer.blocks.lock().unwrap();
+        assert_eq!(blocks[0].transactions.len(), 2);
+        assert!(blocks[0].header.l2_gas_used >= 8);
+        assert_eq!(mempool.transactions.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_block_is_due_when_full_or_after_block_time() {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = new_sequencer(&mempool);
+        sequencer.block_policy.block_time = Duration::from_secs(3600);
+        assert!(!sequencer.block_due(), "nothing to include");
+
+        submit_invokes(&mempool, "Cyndie", 1);
+        assert!(!sequencer.block_due(), "one transaction neither fills a block nor has waited long enough");
+        assert!(!sequencer.produce_block_if_due());
+
+        sequencer.block_policy.block_time = Duration::ZERO;
+        assert!(sequencer.block_due());
+        assert!(sequencer.produce_block_if_due());
+        assert_eq!(sequencer.blocks.lock().unwrap().len(), 1);
+
+        sequencer.block_policy.block_time = Duration::from_secs(3600);
+        sequencer.block_policy.max_transactions = 2;
+        submit_invokes(&mempool, "Alice", 2);
+        assert!(sequencer.block_due(), "two waiting transactions fill a block");
+    }
+
+    #[test]
+    fn test_executed_transactions_are_visible_in_the_pending_block() {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = new_sequencer(&mempool);
+        sequencer.processing_delay = Duration::from_millis(300);
+        submit_invokes(&mempool, "Cyndie", 2);
+        let first_hash = mempool.transactions.lock().unwrap()[0].get_hash();
+        let (pending_block, blocks) = (sequencer.pending_block.clone(), sequencer.blocks.clone());
+        assert!(sequencer.pending_block().is_none());
+
+        let sequencer = Arc::new(Mutex::new(sequencer));
+        let worker = {
+            let sequencer = sequencer.clone();
+            thread::spawn(move || sequencer.lock().unwrap().process_transactions())
+        };
+
+        // The first transaction is queryable while the sequencer pauses before the second
+        let started = Instant::now();
+        let pending = loop {
+            let pending = pending_block.lock().unwrap().clone();
+            if let Some(pending) = pending.filter(|b| !b.is_empty()) {
+                break pending;
+            }
+            assert!(started.elapsed() < Duration::from_secs(5), "no transaction reached the pending block");
+            thread::sleep(Duration::from_millis(5));
+        };
+        assert!(blocks.lock().unwrap().is_empty());
+        assert_eq!(pending.block_number, 1);
+        assert_eq!(pending.transactions.len(), 1);
+        assert_eq!(pending.get_receipt(&first_hash).unwrap().status, TransactionStatus::Succeeded);
+        assert_eq!(pending.state().nonces.get("Cyndie"), Some(&1));
+        assert!(!pending.state_diff().is_empty());
+
+        worker.join().unwrap();
+        let sequencer = sequencer.lock().unwrap();
+        assert!(sequencer.pending_block().is_none());
+        let blocks = blocks.lock().unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].transactions.len(), 2);
+        assert_eq!(blocks[0].header.state_root, sequencer.state_root);
+        drop(blocks);
+        assert!(sequencer.get_receipt(&first_hash).is_some());
+    }
+
+    /// Counts its calls; `increment_and_fail` counts and then fails
+    struct Counter;
+
+    impl Contract for Counter {
+        fn class_hash(&self) -> String {
+            "counter_class".to_string()
+        }
+
+        fn entry_point_type(&self, selector: &str) -> Option<EntryPointType> {
+            matches!(selector, "increment" | "increment_and_fail").then_some(EntryPointType::External)
+        }
+
+        fn execute(&self, selector: &str, _calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+            let count = ctx.storage_read("count");
+            ctx.storage_write("count", count.checked_add(U256::ONE).ok_or("count overflow")?);
+            if selector == "increment_and_fail" {
+                return Err("counter failed".to_string());
+            }
+            Ok(vec![])
+        }
+    }
+
+    /// Records the call, increments the counter and mints 100 to the caller, then acts on
+    /// `calldata[0]`: `fail` fails, `catch` also makes a failing call to the counter and
+    /// carries on
+    struct Router;
+
+    impl Contract for Router {
+        fn class_hash(&self) -> String {
+            "router_class".to_string()
+        }
+
+        fn entry_point_type(&self, selector: &str) -> Option<EntryPointType> {
+            (selector == "route").then_some(EntryPointType::External)
+        }
+
+        fn execute(&self, _selector: &str, calldata: &[String], ctx: &mut ExecutionContext) -> Result<Vec<String>, String> {
+            ctx.storage_write("routed", U256::ONE);
+            ctx.call_contract("counter", "increment", &[])?;
+            let caller = ctx.caller_address.clone();
+            ctx.mint(&caller, U256::from(100u64))?;
+            match calldata.first().map(String::as_str) {
+                Some("fail") => Err("router failed".to_string()),
+                Some("catch") => {
+                    assert!(ctx.call_contract("counter", "increment_and_fail", &[]).is_err());
+                    Ok(vec![])
+                }
+                _ => Ok(vec![]),
+            }
+        }
+    }
+
+    fn route(mode: &str) -> Transaction {
+        Transaction::new("Alice".to_string(), TransactionType::Invoke, None, None, None, 0)
+            .with_call("router", "route", vec![mode.to_string()])
+            .with_max_fee(U256::from(50u64))
+    }
+
+    fn contract_sequencer(mempool: &Arc<Mempool>) -> Sequencer {
+        let mut sequencer = new_sequencer(mempool);
+        sequencer.deploy_contract("counter", Arc::new(Counter));
+        sequencer.deploy_contract("router", Arc::new(Router));
+        sequencer
+    }
+
+    #[test]
+    fn test_revert_discards_all_writes_but_keeps_fee_and_nonce() {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = contract_sequencer(&mempool);
+
+        let tx = route("fail");
+        let tx_hash = tx.get_hash();
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let receipt = sequencer.get_receipt(&tx_hash).unwrap();
+        assert_eq!(receipt.status, TransactionStatus::Reverted);
+        assert_eq!(receipt.revert_reason.as_deref(), Some("router failed"));
+        assert!(!receipt.actual_fee.is_zero());
+
+        // Neither the router's nor the counter's storage nor the mint survived
+        let storage = mempool.storage.lock().unwrap();
+        assert!(storage.get("router").is_none_or(|slots| slots.is_empty()));
+        assert!(storage.get("counter").is_none_or(|slots| slots.is_empty()));
+        let expected_alice = U256::from(200u64).checked_sub(receipt.actual_fee).unwrap();
+        assert_eq!(mempool.balances.lock().unwrap()["Alice"], expected_alice);
+        assert_eq!(mempool.nonces.lock().unwrap()["Alice"], 1);
+    }
+
+    #[test]
+    fn test_failed_inner_call_is_rolled_back_alone() {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = contract_sequencer(&mempool);
+
+        let tx = route("catch");
+        let tx_hash = tx.get_hash();
+        mempool.submit_transaction(tx);
+        sequencer.process_transactions();
+
+        let receipt = sequencer.get_receipt(&tx_hash).unwrap();
+        assert_eq!(receipt.status, TransactionStatus::Succeeded);
+        assert_eq!(receipt.revert_reason, None);
+
+        let storage = mempool.storage.lock().unwrap();
+        assert_eq!(storage["router"]["routed"], U256::ONE);
+        assert_eq!(storage["counter"]["count"], U256::ONE);
+        let expected_alice = U256::from(300u64).checked_sub(receipt.actual_fee).unwrap();
+        assert_eq!(mempool.balances.lock().unwrap()["Alice"], expected_alice);
+    }
+}
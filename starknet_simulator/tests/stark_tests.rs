@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests {
+    use starknet_simulator::stark::{self, Felt, MerkleTree, PublicInput, StarkError, MODULUS};
+    use starknet_simulator::state::{StateDiff, FEE_TOKEN_ADDRESS};
+    use starknet_simulator::utils::U256;
+
+    fn public_input() -> PublicInput {
+        let mut diff = StateDiff::default();
+        let balances = diff.storage_updates.entry(FEE_TOKEN_ADDRESS.to_string()).or_default();
+        for (account, balance) in [("Alice", 989u64), ("Bob", 1001), ("Charlie", 1000), ("sequencer", 9)] {
+            balances.insert(account.to_string(), U256::from(balance));
+        }
+        PublicInput::for_state_transition("genesis_root", &diff)
+    }
+
+    #[test]
+    fn test_field_arithmetic() {
+        let a = Felt::new(MODULUS - 1);
+        assert_eq!(a + Felt::ONE, Felt::ZERO);
+        assert_eq!(Felt::ZERO - Felt::ONE, a);
+        assert_eq!(a * a, Felt::ONE);
+        let x = Felt::new(123_456_789);
+        assert_eq!(x * x.inverse().unwrap(), Felt::ONE);
+        assert_eq!(Felt::ZERO.inverse(), None);
+
+        let root = Felt::root_of_unity(64);
+        assert_eq!(root.pow(64), Felt::ONE);
+        assert_ne!(root.pow(32), Felt::ONE);
+    }
+
+    #[test]
+    fn test_merkle_openings() {
+        let values: Vec<Felt> = (0..8).map(Felt::new).collect();
+        let tree = MerkleTree::new(&values);
+        let path = tree.open(5);
+        assert!(MerkleTree::verify(&tree.root(), 5, Felt::new(5), &path));
+        assert!(!MerkleTree::verify(&tree.root(), 5, Felt::new(6), &path));
+        assert!(!MerkleTree::verify(&tree.root(), 4, Felt::new(5), &path));
+    }
+
+    #[test]
+    fn test_honest_proof_verifies() {
+        let input = public_input();
+        let proof = stark::prove(&input);
+        assert_eq!(proof.trace_length, 8);
+        assert_eq!(proof.output, input.trace()[7]);
+        assert_eq!(proof.fri_roots.len(), 3);
+        assert_eq!(stark::verify(&input, &proof), Ok(()));
+    }
+
+    #[test]
+    fn test_forged_trace_fails_the_low_degree_test() {
+        let input = public_input();
+        // Break one transition in the middle; the boundary rows still match the claim
+        let mut trace = input.trace();
+        trace[3] = trace[3] + Felt::ONE;
+        let proof = stark::prove_trace(&input, trace);
+        assert!(matches!(
+            stark::verify(&input, &proof),
+            Err(StarkError::FriFoldMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_tampered_proofs_are_rejected() {
+        let input = public_input();
+        let proof = stark::prove(&input);
+
+        let mut wrong_output = proof.clone();
+        wrong_output.output = wrong_output.output + Felt::ONE;
+        assert!(stark::verify(&input, &wrong_output).is_err());
+
+        let mut wrong_opening = proof.clone();
+        wrong_opening.queries[0].trace.value = wrong_opening.queries[0].trace.value + Felt::ONE;
+        assert!(matches!(
+            stark::verify(&input, &wrong_opening),
+            Err(StarkError::InvalidTraceOpening { .. })
+        ));
+
+        let mut wrong_final = proof.clone();
+        wrong_final.fri_final = wrong_final.fri_final + Felt::ONE;
+        assert!(stark::verify(&input, &wrong_final).is_err());
+
+        // The proof is bound to its public input
+        let mut other_input = input.clone();
+        other_input.updates[0] = other_input.updates[0] + Felt::ONE;
+        assert!(stark::verify(&other_input, &proof).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use starknet_simulator::codec;
+    use starknet_simulator::mempool::Mempool;
+    use starknet_simulator::prover::Prover;
+    use starknet_simulator::sequencer::Sequencer;
+    use starknet_simulator::storage::{FileStorage, MemoryStorage, Storage, StorageError, WriteBatch};
+    use starknet_simulator::transaction::{Transaction, TransactionStatus, TransactionType};
+    use starknet_simulator::utils::U256;
+    use starknet_simulator::verifier::Verifier;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("starknet_simulator_{}_{}.db", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn open(storage: impl Storage + 'static) -> Sequencer {
+        let mut sequencer = Sequencer::open(Arc::new(Mempool::new()), storage).unwrap();
+        sequencer.processing_delay = Duration::ZERO;
+        sequencer
+    }
+
+    /// Memory storage whose writes fail while `failing` is set
+    #[derive(Clone, Default)]
+    struct FlakyStorage {
+        inner: MemoryStorage,
+        failing: Arc<AtomicBool>,
+    }
+
+    impl Storage for FlakyStorage {
+        fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+            self.inner.get(key)
+        }
+
+        fn keys(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+            self.inner.keys(prefix)
+        }
+
+        fn write(&mut self, batch: WriteBatch) -> Result<(), StorageError> {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(StorageError::Io { path: "flaky".to_string(), reason: "disk full".to_string() });
+            }
+            self.inner.write(batch)
+        }
+    }
+
+    fn transfer(sender: &str, receiver: &str, amount: u64, nonce: u64) -> Transaction {
+        Transaction::new(sender.to_string(), TransactionType::Invoke, Some(receiver.to_string()), None, Some(U256::from(amount)), nonce)
+    }
+
+    #[test]
+    fn test_node_resumes_after_a_restart() {
+        let storage = MemoryStorage::new();
+        let mut sequencer = open(storage.clone());
+        let first = transfer("Alice", "Bob", 10, 0);
+        let first_hash = first.get_hash();
+        sequencer.mempool.submit_transaction(first);
+        sequencer.process_transactions();
+        // Still waiting in the mempool when the node stops
+        sequencer.mempool.submit_transaction(transfer("Alice", "Mark", 5, 1));
+
+        let block = sequencer.blocks.lock().unwrap()[0].clone();
+        let balances = sequencer.mempool.balances.lock().unwrap().clone();
+        let gas_prices = *sequencer.mempool.gas_prices.lock().unwrap();
+        drop(sequencer);
+
+        let mut restarted = open(storage);
+        assert_eq!(restarted.block_number, block.header.block_number + 1);
+        assert_eq!(restarted.parent_block_hash, block.get_block_hash());
+        assert_eq!(restarted.state_root, block.header.state_root);
+        assert_eq!(*restarted.mempool.balances.lock().unwrap(), balances);
+        assert_eq!(*restarted.mempool.gas_prices.lock().unwrap(), gas_prices);
+        assert_eq!(restarted.mempool.nonces.lock().unwrap()["Alice"], 1);
+        assert_eq!(restarted.get_receipt(&first_hash).unwrap().status, TransactionStatus::Succeeded);
+        assert_eq!(restarted.mempool.transactions.lock().unwrap().len(), 1);
+
+        // The queued transaction goes into the next block, which chains from the restored one
+        restarted.process_transactions();
+        let blocks = restarted.blocks.lock().unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[1].header.parent_block_hash, block.get_block_hash());
+        assert_eq!(blocks[1].header.parent_state_root, block.header.state_root);
+        assert_eq!(blocks[1].receipts[0].status, TransactionStatus::Succeeded);
+        assert_eq!(restarted.mempool.balances.lock().unwrap()["Mark"], U256::from(5u64));
+    }
+
+    #[test]
+    fn test_restored_blocks_settle_from_the_saved_genesis() {
+        let storage = MemoryStorage::new();
+        let mut sequencer = open(storage.clone());
+        for nonce in 0..2 {
+            sequencer.mempool.submit_transaction(transfer("Cyndie", "Bob", 20, nonce));
+            sequencer.process_transactions();
+        }
+        drop(sequencer);
+
+        let restarted = Arc::new(Mutex::new(open(storage)));
+        let core_contract = restarted.lock().unwrap().core_contract.clone();
+        let mut prover = Prover::new(restarted.clone());
+        prover.proving_delay = Duration::ZERO;
+        let mut verifier = Verifier::new(restarted.clone(), core_contract.clone());
+        verifier.verification_delay = Duration::ZERO;
+        prover.verify_proof().unwrap();
+        verifier.verify_proofs();
+
+        assert_eq!(core_contract.lock().unwrap().block_number, 2);
+        assert_eq!(core_contract.lock().unwrap().state_root, restarted.lock().unwrap().state_root);
+    }
+
+    #[test]
+    fn test_node_resumes_from_a_file() {
+        let path = temp_path("node");
+        let mut sequencer = open(FileStorage::open(&path).unwrap());
+        for nonce in 0..2 {
+            sequencer.mempool.submit_transaction(transfer("Cyndie", "Bob", 20, nonce));
+            sequencer.process_transactions();
+        }
+        let hashes: Vec<String> = sequencer.blocks.lock().unwrap().iter().map(|b| b.get_block_hash()).collect();
+        drop(sequencer);
+
+        let restarted = open(FileStorage::open(&path).unwrap());
+        let restored: Vec<String> = restarted.blocks.lock().unwrap().iter().map(|b| b.get_block_hash()).collect();
+        assert_eq!(restored, hashes);
+        assert_eq!(restarted.parent_block_hash, hashes[1]);
+        assert_eq!(restarted.mempool.balances.lock().unwrap()["Bob"], U256::from(540u64));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_block_that_failed_to_save_is_saved_with_the_next_one() {
+        let storage = FlakyStorage::default();
+        let mut sequencer = open(storage.clone());
+        storage.failing.store(true, Ordering::SeqCst);
+        sequencer.mempool.submit_transaction(transfer("Cyndie", "Bob", 20, 0));
+        sequencer.process_transactions();
+        assert!(storage.keys("block/").unwrap().is_empty());
+
+        storage.failing.store(false, Ordering::SeqCst);
+        sequencer.mempool.submit_transaction(transfer("Cyndie", "Bob", 20, 1));
+        sequencer.process_transactions();
+        let hashes: Vec<String> = sequencer.blocks.lock().unwrap().iter().map(|b| b.get_block_hash()).collect();
+        drop(sequencer);
+
+        let restarted = open(storage);
+        let restored: Vec<String> = restarted.blocks.lock().unwrap().iter().map(|b| b.get_block_hash()).collect();
+        assert_eq!(restored, hashes);
+        assert_eq!(restarted.mempool.balances.lock().unwrap()["Bob"], U256::from(540u64));
+    }
+
+    #[test]
+    fn test_file_storage_drops_a_write_cut_short() {
+        let path = temp_path("torn");
+        let mut storage = FileStorage::open(&path).unwrap();
+        storage.put("a", b"1".to_vec()).unwrap();
+        let mut batch = WriteBatch::default();
+        batch.put("b", b"2".to_vec());
+        batch.delete("a");
+        storage.write(batch).unwrap();
+        drop(storage);
+
+        // A crash in the middle of appending the next batch
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(b"40 deadbeef\nPb:").unwrap();
+
+        let mut storage = FileStorage::open(&path).unwrap();
+        assert_eq!(storage.get("a").unwrap(), None);
+        assert_eq!(storage.get("b").unwrap(), Some(b"2".to_vec()));
+        storage.put("c", b"3".to_vec()).unwrap();
+        drop(storage);
+
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(storage.keys("").unwrap(), vec!["b".to_string(), "c".to_string()]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_file_storage_refuses_a_broken_record_before_valid_ones() {
+        // A complete record, taken from another file
+        let other_path = temp_path("record");
+        FileStorage::open(&other_path).unwrap().put("c", b"3".to_vec()).unwrap();
+        let record = fs::read(&other_path).unwrap();
+        fs::remove_file(&other_path).unwrap();
+
+        let path = temp_path("damaged");
+        FileStorage::open(&path).unwrap().put("a", b"1".to_vec()).unwrap();
+        let mut log = OpenOptions::new().append(true).open(&path).unwrap();
+        log.write_all(b"40 deadbeef\nPb:").unwrap();
+        log.write_all(&record).unwrap();
+        drop(log);
+        let len = fs::metadata(&path).unwrap().len();
+
+        assert!(matches!(FileStorage::open(&path), Err(StorageError::Corrupt { .. })));
+        // The record after the broken one is still in the file
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tampered_block_is_refused_on_restart() {
+        let mut storage = MemoryStorage::new();
+        let mut sequencer = open(storage.clone());
+        sequencer.mempool.submit_transaction(transfer("Bob", "Mark", 50, 0));
+        sequencer.process_transactions();
+        let mut block = sequencer.blocks.lock().unwrap()[0].clone();
+        drop(sequencer);
+
+        let key = storage.keys("block/").unwrap().remove(0);
+        block.receipts[0].actual_fee = U256::ZERO;
+        block.transactions[0].amount = Some(U256::from(500u64));
+        storage.put(&key, codec::to_bytes(&block)).unwrap();
+
+        let result = Sequencer::open(Arc::new(Mempool::new()), storage);
+        assert!(matches!(result, Err(StorageError::Corrupt { .. })));
+    }
+}
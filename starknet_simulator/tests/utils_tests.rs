@@ -0,0 +1,51 @@
+#[cfg(test)]
+mod tests {
+    use starknet_simulator::utils::U256;
+
+    #[test]
+    fn test_checked_add_carries_into_high_half() {
+        let value = U256::from_low_high(u128::MAX, 0);
+        let sum = value.checked_add(U256::ONE).unwrap();
+
+        assert_eq!(sum, U256::from_low_high(0, 1));
+    }
+
+    #[test]
+    fn test_checked_arithmetic_reports_overflow_and_underflow() {
+        assert!(U256::MAX.checked_add(U256::ONE).is_none());
+        assert!(U256::ZERO.checked_sub(U256::ONE).is_none());
+        assert!(U256::MAX.checked_mul(U256::from(2u64)).is_none());
+    }
+
+    #[test]
+    fn test_checked_mul_across_limbs() {
+        let value = U256::from(u128::MAX);
+        let product = value.checked_mul(U256::from(2u64)).unwrap();
+
+        assert_eq!(product, U256::from_low_high(u128::MAX - 1, 1));
+    }
+
+    #[test]
+    fn test_display_and_parse_round_trip() {
+        let max = "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+        assert_eq!(U256::MAX.to_string(), max);
+        assert_eq!(max.parse::<U256>().unwrap(), U256::MAX);
+        assert_eq!("42".parse::<U256>().unwrap(), U256::from(42u64));
+    }
+
+    #[test]
+    fn test_parse_rejects_out_of_range_and_garbage() {
+        let too_big = "115792089237316195423570985008687907853269984665640564039457584007913129639936";
+        assert!(too_big.parse::<U256>().is_err());
+        assert!("12a".parse::<U256>().is_err());
+        assert!("-1".parse::<U256>().is_err());
+    }
+
+    #[test]
+    fn test_ordering_compares_high_half_first() {
+        let small_high = U256::from_low_high(u128::MAX, 0);
+        let big_high = U256::from_low_high(0, 1);
+
+        assert!(small_high < big_high);
+    }
+}
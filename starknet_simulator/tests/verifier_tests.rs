@@ -0,0 +1,197 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use starknet_simulator::block::BlockStatus;
+    use starknet_simulator::mempool::Mempool;
+    use starknet_simulator::prover::Prover;
+    use starknet_simulator::sequencer::Sequencer;
+    use starknet_simulator::transaction::{Transaction, TransactionReceipt, TransactionStatus, TransactionType};
+    use starknet_simulator::utils::U256;
+    use starknet_simulator::verifier::Verifier;
+
+    /// Two proven blocks with one Alice→Bob transfer each, and a verifier ready to settle them
+    fn proven_blocks() -> (Arc<Mutex<Sequencer>>, Prover, Verifier) {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+        for nonce in 0..2 {
+            let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::ONE), nonce);
+            mempool.submit_transaction(tx);
+            sequencer.process_transactions();
+        }
+        let core_contract = sequencer.core_contract.clone();
+        let sequencer = Arc::new(Mutex::new(sequencer));
+
+        let mut prover = Prover::new(sequencer.clone());
+        prover.proving_delay = Duration::ZERO;
+        prover.verify_proof().unwrap();
+        let mut verifier = Verifier::new(sequencer.clone(), core_contract);
+        verifier.verification_delay = Duration::ZERO;
+        (sequencer, prover, verifier)
+    }
+
+    fn statuses(sequencer: &Arc<Mutex<Sequencer>>) -> Vec<BlockStatus> {
+        let sequencer = sequencer.lock().unwrap();
+        let blocks = sequencer.blocks.lock().unwrap();
+        blocks.iter().map(|b| b.header.block_status.clone()).collect()
+    }
+
+    fn rejection_reason(sequencer: &Arc<Mutex<Sequencer>>, index: usize) -> String {
+        match &statuses(sequencer)[index] {
+            BlockStatus::Rejected { reason } => reason.clone(),
+            status => panic!("expected a rejected proof, block is {:?}", status),
+        }
+    }
+
+    #[test]
+    fn test_untampered_blocks_settle() {
+        let (sequencer, _, verifier) = proven_blocks();
+        verifier.verify_proofs();
+        assert_eq!(statuses(&sequencer), vec![BlockStatus::AcceptedOnL1, BlockStatus::AcceptedOnL1]);
+        assert_eq!(verifier.core_contract.lock().unwrap().block_number, 2);
+    }
+
+    #[test]
+    fn test_prover_and_verifier_each_own_one_stage() {
+        let mempool = Arc::new(Mempool::new());
+        let mut sequencer = Sequencer::new(mempool.clone());
+        sequencer.processing_delay = Duration::ZERO;
+        mempool.submit_transaction(Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::ONE), 0));
+        sequencer.process_transactions();
+        let core_contract = sequencer.core_contract.clone();
+        let sequencer = Arc::new(Mutex::new(sequencer));
+        assert_eq!(statuses(&sequencer), vec![BlockStatus::AcceptedOnL2]);
+
+        // Nothing to settle until the block is proven
+        let mut verifier = Verifier::new(sequencer.clone(), core_contract);
+        verifier.verification_delay = Duration::ZERO;
+        verifier.verify_proofs();
+        assert_eq!(statuses(&sequencer), vec![BlockStatus::AcceptedOnL2]);
+
+        let mut prover = Prover::new(sequencer.clone());
+        prover.proving_delay = Duration::ZERO;
+        prover.verify_proof().unwrap();
+        assert_eq!(statuses(&sequencer), vec![BlockStatus::Proven]);
+        verifier.verify_proofs();
+        assert_eq!(statuses(&sequencer), vec![BlockStatus::AcceptedOnL1]);
+    }
+
+    #[test]
+    fn test_block_edited_after_proving_is_rejected() {
+        let (sequencer, prover, verifier) = proven_blocks();
+        {
+            let sequencer = sequencer.lock().unwrap();
+            let mut blocks = sequencer.blocks.lock().unwrap();
+            blocks[1].transactions[0].amount = Some(U256::from(500u64));
+        }
+        verifier.verify_proofs();
+
+        // Block #1 settles; block #2 is stopped before reaching L1
+        assert_eq!(statuses(&sequencer)[0], BlockStatus::AcceptedOnL1);
+        assert!(rejection_reason(&sequencer, 1).contains("transactions of block #2"));
+        assert_eq!(verifier.core_contract.lock().unwrap().block_number, 1);
+
+        // A fresh proof over the edited block cannot fix it
+        assert_eq!(prover.reprove_rejected(), 1);
+        prover.verify_proof().unwrap();
+        verifier.verify_proofs();
+        assert!(rejection_reason(&sequencer, 1).contains("do not match its header"));
+        assert_eq!(verifier.core_contract.lock().unwrap().block_number, 1);
+    }
+
+    #[test]
+    fn test_header_edited_after_proving_is_rejected() {
+        let (sequencer, _, verifier) = proven_blocks();
+        {
+            let sequencer = sequencer.lock().unwrap();
+            let mut blocks = sequencer.blocks.lock().unwrap();
+            blocks[0].header.state_root = "forged_root".to_string();
+        }
+        verifier.verify_proofs();
+        assert!(rejection_reason(&sequencer, 0).contains("changed after it was proven"));
+        // Nothing after the rejected block is settled either
+        assert_eq!(statuses(&sequencer)[1], BlockStatus::Proven);
+        assert_eq!(verifier.core_contract.lock().unwrap().block_number, 0);
+    }
+
+    #[test]
+    fn test_receipt_edited_after_proving_is_rejected() {
+        let tampers: [fn(&mut TransactionReceipt); 3] = [
+            |receipt| receipt.actual_fee = U256::ZERO,
+            |receipt| receipt.status = TransactionStatus::Reverted,
+            |receipt| receipt.revert_reason = Some("never ran".to_string()),
+        ];
+        for tamper in tampers {
+            let (sequencer, _, verifier) = proven_blocks();
+            {
+                let sequencer = sequencer.lock().unwrap();
+                let mut blocks = sequencer.blocks.lock().unwrap();
+                tamper(&mut blocks[0].receipts[0]);
+            }
+            verifier.verify_proofs();
+            assert!(rejection_reason(&sequencer, 0).contains("receipts of block #1"));
+            assert_eq!(verifier.core_contract.lock().unwrap().block_number, 0);
+        }
+    }
+
+    #[test]
+    fn test_timestamp_edited_after_proving_is_rejected() {
+        let (sequencer, _, verifier) = proven_blocks();
+        {
+            let sequencer = sequencer.lock().unwrap();
+            let mut blocks = sequencer.blocks.lock().unwrap();
+            blocks[0].header.block_timestamp += 1;
+        }
+        verifier.verify_proofs();
+        assert!(rejection_reason(&sequencer, 0).contains("changed after it was proven"));
+        assert_eq!(verifier.core_contract.lock().unwrap().block_number, 0);
+    }
+
+    #[test]
+    fn test_reproven_block_is_not_batched_across_a_proven_one() {
+        let (sequencer, _, verifier) = proven_blocks();
+        {
+            let sequencer = sequencer.lock().unwrap();
+            let mut blocks = sequencer.blocks.lock().unwrap();
+            blocks[0].header.state_root = "forged_root".to_string();
+        }
+        verifier.verify_proofs();
+        {
+            let mut sequencer = sequencer.lock().unwrap();
+            let tx = Transaction::new("Alice".to_string(), TransactionType::Invoke, Some("Bob".to_string()), None, Some(U256::ONE), 2);
+            sequencer.mempool.submit_transaction(tx);
+            sequencer.process_transactions();
+        }
+
+        // Blocks #1 and #3 wait for a proof, but #2 between them is already proven: each gets its own job
+        let mut prover = Prover::new(sequencer.clone());
+        prover.proving_delay = Duration::ZERO;
+        prover.max_blocks_per_job = 2;
+        assert_eq!(prover.reprove_rejected(), 1);
+        prover.verify_proof().unwrap();
+        assert_eq!(statuses(&sequencer), vec![BlockStatus::Proven; 3]);
+        let jobs = prover.jobs.lock().unwrap();
+        let covered: Vec<(u64, u64)> = jobs.iter().map(|job| (job.first_block_number, job.last_block_number)).collect();
+        assert_eq!(covered, vec![(1, 1), (3, 3)]);
+    }
+
+    #[test]
+    fn test_corrupted_proof_is_rejected_then_reproven() {
+        let (sequencer, prover, verifier) = proven_blocks();
+        {
+            let sequencer = sequencer.lock().unwrap();
+            let mut blocks = sequencer.blocks.lock().unwrap();
+            let proof = blocks[0].proof.as_mut().unwrap();
+            proof.stark.fri_final = proof.stark.fri_final + starknet_simulator::stark::Felt::ONE;
+        }
+        verifier.verify_proofs();
+        assert!(rejection_reason(&sequencer, 0).contains("STARK does not verify"));
+
+        assert_eq!(prover.reprove_rejected(), 1);
+        assert_eq!(statuses(&sequencer)[0], BlockStatus::AcceptedOnL2);
+        prover.verify_proof().unwrap();
+        verifier.verify_proofs();
+        assert_eq!(statuses(&sequencer), vec![BlockStatus::AcceptedOnL1, BlockStatus::AcceptedOnL1]);
+    }
+}